@@ -0,0 +1,24 @@
+#!/bin/sh
+# Build the seed corpora for the fuzz targets out of DejaVuSans.ttf.
+#
+# Run from anywhere, then fuzz with e.g. `cargo fuzz run render`.
+set -e
+
+FUZZ_DIR="$(cd "$(dirname "$0")" && pwd)"
+FONT="$FUZZ_DIR/../DejaVuSans.ttf"
+CORPUS="$FUZZ_DIR/corpus"
+
+mkdir -p "$CORPUS/face_load" "$CORPUS/render" "$CORPUS/shape"
+
+cp "$FONT" "$CORPUS/face_load/DejaVuSans.ttf"
+cp "$FONT" "$CORPUS/render/DejaVuSans.ttf"
+
+# A truncated copy of the font gets the fuzzer into FreeType's error paths quickly.
+head -c 4096 "$FONT" > "$CORPUS/face_load/DejaVuSans-truncated.ttf"
+
+printf 'Hello World!' > "$CORPUS/shape/latin"
+printf 'Γειά σου Κόσμε!' > "$CORPUS/shape/greek"
+printf 'Привет, мир!' > "$CORPUS/shape/cyrillic"
+printf 'مرحبا بالعالم' > "$CORPUS/shape/arabic"
+printf 'ffi fl AV To Wa' > "$CORPUS/shape/ligatures-kerning"
+printf 'e\xcc\x81 a\xcc\x8a n\xcc\x83' > "$CORPUS/shape/combining"
@@ -0,0 +1,37 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+#[macro_use]
+extern crate lazy_static;
+extern crate glyphydog;
+
+use glyphydog::*;
+
+lazy_static!{
+    static ref LIB: FTLib = FTLib::new();
+}
+
+fuzz_target!(|data: &[u8]| {
+    // The validator must never panic, regardless of whether the font gets accepted.
+    let _ = validate_font(data, 0, &ValidationOptions::default());
+
+    if let Ok(mut face) = Face::new(data, 0, &LIB) {
+        face.metrics_font_units();
+        let _ = face.metrics_sized(FaceSize::new(16*64, 16*64), DPI::new(72, 72));
+        face.char_index('A');
+    }
+});
@@ -0,0 +1,62 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+#[macro_use]
+extern crate lazy_static;
+extern crate glyphydog;
+
+use glyphydog::*;
+
+lazy_static!{
+    static ref LIB: FTLib = FTLib::new();
+}
+
+const RENDER_MODES: [RenderMode; 5] = [
+    RenderMode::Normal,
+    RenderMode::Light,
+    RenderMode::Mono,
+    RenderMode::Lcd,
+    RenderMode::LcdV
+];
+
+/// Fonts can claim to have up to 65535 glyphs. Only render the first few, so that each run stays
+/// fast enough to be useful.
+const MAX_GLYPHS: u32 = 64;
+
+fuzz_target!(|data: &[u8]| {
+    let mut face = match Face::new(data, 0, &LIB) {
+        Ok(face) => face,
+        Err(_) => return
+    };
+    let size = FaceSize::new(16*64, 16*64);
+    let dpi = DPI::new(72, 72);
+
+    for glyph_index in 0..MAX_GLYPHS {
+        for &render_mode in &RENDER_MODES {
+            let mut slot = match face.load_glyph(glyph_index, size, dpi, LoadFlags::empty(), render_mode) {
+                Ok(slot) => slot,
+                // Running off the end of the font's glyphs shows up as an error here.
+                Err(_) => continue
+            };
+            slot.metrics();
+            if let Ok(bitmap) = slot.render_glyph(render_mode) {
+                // Walk every pixel of the bitmap, catching bad pitches and dimensions.
+                bitmap.to_rgba8();
+            }
+        }
+    }
+});
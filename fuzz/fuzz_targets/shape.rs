@@ -0,0 +1,50 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+#[macro_use]
+extern crate lazy_static;
+extern crate glyphydog;
+
+use glyphydog::*;
+
+use std::str;
+use std::sync::Mutex;
+
+lazy_static!{
+    static ref LIB: FTLib = FTLib::new();
+    static ref FACE: Mutex<Face<&'static [u8]>> = Mutex::new(
+        Face::new(&include_bytes!("../../DejaVuSans.ttf")[..], 0, &LIB).unwrap()
+    );
+}
+
+fuzz_target!(|data: &[u8]| {
+    let text = match str::from_utf8(data) {
+        Ok(text) => text,
+        Err(_) => return
+    };
+
+    let mut face = FACE.lock().unwrap();
+    let mut shaper = Shaper::new();
+    let size = FaceSize::new(16*64, 16*64);
+    let dpi = DPI::new(72, 72);
+
+    if let Ok(glyphs) = shaper.shape_text(text, &mut face, size, dpi) {
+        for glyph in glyphs {
+            assert!(glyph.str_index <= text.len());
+        }
+    }
+});
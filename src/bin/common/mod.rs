@@ -0,0 +1,126 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Option handling shared by the command-line tools.
+#![allow(dead_code)]
+
+use glyphydog::{FTLib, Face, FaceSize, DPI, StrikeSelection, ShapeOptions, Feature, Tag, Direction, RenderMode};
+use getopts::{Options, Matches};
+
+use std::{env, process};
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Print an error message and exit.
+pub fn fail<D: Display>(message: D) -> ! {
+    eprintln!("error: {}", message);
+    process::exit(1)
+}
+
+/// Add the options for opening and sizing a face, along with `--help`.
+pub fn add_face_options(opts: &mut Options) {
+    opts.optopt("i", "face-index", "index of the face within the font file (default 0)", "INDEX");
+    opts.optopt("s", "size", "font size in points (default 12)", "POINTS");
+    opts.optopt("", "dpi", "resolution in dots per inch (default 72)", "DPI");
+    opts.optopt("", "strike", "use an embedded bitmap strike: its index, or \"nearest\" to scale the strike closest to --size", "STRIKE");
+    opts.optflag("h", "help", "print this help message");
+}
+
+/// Add the options controlling how text gets shaped.
+pub fn add_shape_options(opts: &mut Options) {
+    opts.optopt("f", "features", "comma-separated list of features to apply, e.g. \"kern,-liga,ss01\"", "LIST");
+    opts.optopt("", "direction", "text direction: ltr, rtl, ttb or btt (default: guessed)", "DIR");
+    opts.optopt("", "script", "ISO 15924 script tag, e.g. Latn (default: guessed)", "TAG");
+    opts.optopt("", "language", "BCP 47 language tag, e.g. en (default: guessed)", "LANG");
+}
+
+pub fn add_render_mode_option(opts: &mut Options) {
+    opts.optopt("m", "render-mode", "normal, light, mono, lcd or lcdv (default normal)", "MODE");
+}
+
+/// Parse the program's arguments, printing the usage message and exiting if `--help` is given.
+pub fn parse_args(opts: &Options, brief: &str) -> Matches {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let matches = opts.parse(&args).unwrap_or_else(|e| fail(e));
+    if matches.opt_present("help") {
+        print!("{}", opts.usage(brief));
+        process::exit(0);
+    }
+    matches
+}
+
+/// Parse the value of an option, if it's given.
+pub fn opt<T: FromStr>(matches: &Matches, name: &str) -> Option<T> {
+    matches.opt_str(name)
+        .map(|s| s.parse().unwrap_or_else(|_| fail(format!("invalid value for --{}: {}", name, s))))
+}
+
+/// Parse the value of an option, falling back to `default` if it isn't given.
+pub fn opt_or<T: FromStr>(matches: &Matches, name: &str, default: T) -> T {
+    opt(matches, name).unwrap_or(default)
+}
+
+/// Retrieve the free argument at `index`, exiting with an error naming it if it's missing.
+pub fn free_arg<'a>(matches: &'a Matches, index: usize, name: &str) -> &'a str {
+    matches.free.get(index).map(|s| &**s).unwrap_or_else(|| fail(format!("missing {}", name)))
+}
+
+pub fn open_face(path: &str, matches: &Matches, lib: &FTLib) -> Face<()> {
+    let face_index = opt_or(matches, "face-index", 0);
+    Face::new_path(path, face_index, lib).unwrap_or_else(|e| fail(format!("{}: {}", path, e)))
+}
+
+pub fn face_size(matches: &Matches) -> (FaceSize, DPI) {
+    let points = opt_or(matches, "size", 12.0f32);
+    let dpi = opt_or(matches, "dpi", 72);
+    if points <= 0.0 {
+        fail("--size must be positive");
+    }
+
+    let strike = match matches.opt_str("strike") {
+        None => None,
+        Some(ref s) if s == "nearest" => Some(StrikeSelection::Nearest),
+        Some(s) => Some(StrikeSelection::Fixed(s.parse().unwrap_or_else(|_| fail(format!("invalid strike: {}", s)))))
+    };
+    (FaceSize::from_points(points).with_strike(strike), DPI::new(dpi, dpi))
+}
+
+pub fn shape_options(matches: &Matches) -> ShapeOptions {
+    let features = match matches.opt_str("features") {
+        Some(list) => list.split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse::<Feature>().unwrap_or_else(|_| fail(format!("invalid feature: {}", s))))
+            .collect(),
+        None => Vec::new()
+    };
+
+    ShapeOptions {
+        features,
+        direction: matches.opt_str("direction").map(|s| s.parse::<Direction>().unwrap_or_else(|_| fail(format!("invalid direction: {}", s)))),
+        script: matches.opt_str("script").map(|s| s.parse::<Tag>().unwrap_or_else(|_| fail(format!("invalid script tag: {}", s)))),
+        language: matches.opt_str("language")
+    }
+}
+
+pub fn render_mode(matches: &Matches) -> RenderMode {
+    match matches.opt_str("render-mode").as_ref().map(|s| &**s) {
+        None | Some("normal") => RenderMode::Normal,
+        Some("light") => RenderMode::Light,
+        Some("mono") => RenderMode::Mono,
+        Some("lcd") => RenderMode::Lcd,
+        Some("lcdv") => RenderMode::LcdV,
+        Some(mode) => fail(format!("unknown render mode: {}", mode))
+    }
+}
@@ -0,0 +1,135 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bake a font into an AngelCode BMFont descriptor and PNG atlas pages.
+
+extern crate glyphydog;
+extern crate getopts;
+extern crate cgmath_geometry;
+extern crate png;
+
+mod common;
+
+use glyphydog::{FTLib, LoadFlags, LcdFilter, RenderMode, AtlasOptions, BmFont, BmFontFormat, Image};
+use getopts::Options;
+use common::fail;
+
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+use png::HasParameters;
+
+use cgmath_geometry::rect::{DimsBox, GeoBox};
+
+fn main() {
+    let mut opts = Options::new();
+    common::add_face_options(&mut opts);
+    common::add_render_mode_option(&mut opts);
+    opts.optopt("c", "chars", "characters to include (default: the --charset characters)", "TEXT");
+    opts.optopt("", "charset", "ascii or latin1 (default ascii)", "SET");
+    opts.optopt("", "page-size", "atlas page size as WIDTHxHEIGHT (default 512x512)", "SIZE");
+    opts.optopt("", "spacing", "empty pixels between glyphs (default 1)", "PIXELS");
+    opts.optopt("", "format", "descriptor format: text, xml, binary or json (default text)", "FORMAT");
+    opts.optflag("", "no-kerning", "don't include kerning pairs");
+    opts.optopt("o", "output", "output path, without an extension (default: the font's file name)", "PATH");
+
+    let matches = common::parse_args(&opts, "Usage: glyphydog-bmfont [options] FONT");
+    let font_path = common::free_arg(&matches, 0, "font file");
+
+    let chars = match (matches.opt_str("chars"), matches.opt_str("charset").as_ref().map(|s| &**s)) {
+        (Some(chars), _) => chars.chars().collect::<Vec<_>>(),
+        (None, None) | (None, Some("ascii")) => (0x20u8..0x7F).map(|c| c as char).collect(),
+        (None, Some("latin1")) => (0x20u8..0x7F).chain(0xA0u8..=0xFF).map(|c| c as char).collect(),
+        (None, Some(charset)) => fail(format!("unknown charset: {}", charset))
+    };
+    let format = match matches.opt_str("format").as_ref().map(|s| &**s) {
+        None | Some("text") => BmFontFormat::Text,
+        Some("xml") => BmFontFormat::Xml,
+        Some("binary") => BmFontFormat::Binary,
+        Some("json") => BmFontFormat::Json,
+        Some(format) => fail(format!("unknown descriptor format: {}", format))
+    };
+    let page_dims = match matches.opt_str("page-size") {
+        Some(s) => parse_dims(&s).unwrap_or_else(|| fail(format!("invalid page size: {}", s))),
+        None => DimsBox::new2(512, 512)
+    };
+    if 65535 < page_dims.width() || 65535 < page_dims.height() {
+        fail("--page-size can't be larger than 65535x65535");
+    }
+    let render_mode = common::render_mode(&matches);
+    let (face_size, dpi) = common::face_size(&matches);
+
+    let output = match matches.opt_str("output") {
+        Some(output) => output,
+        None => Path::new(font_path).with_extension("").file_name()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| fail("can't derive an output name from the font path; pass --output"))
+    };
+    let output = Path::new(&output);
+    let stem = output.file_name()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| fail(format!("invalid output path: {}", output.display())));
+
+    let mut lib = FTLib::new();
+    if let RenderMode::Lcd | RenderMode::LcdV = render_mode {
+        lib.set_lcd_filter(LcdFilter::Default).unwrap_or_else(|e| fail(e));
+    }
+    let mut face = common::open_face(font_path, &matches, &lib);
+
+    let options = AtlasOptions {
+        face_size,
+        dpi,
+        load_flags: LoadFlags::empty(),
+        render_mode,
+        page_dims,
+        spacing: common::opt_or(&matches, "spacing", 1),
+        kerning: !matches.opt_present("no-kerning")
+    };
+    let font = BmFont::build(&mut face, &chars, &options).unwrap_or_else(|e| fail(e));
+
+    let extension = match format {
+        BmFontFormat::Json => "json",
+        _ => "fnt"
+    };
+    let descriptor_path = output.with_file_name(format!("{}.{}", stem, extension));
+    fs::write(&descriptor_path, font.descriptor(format, &stem))
+        .unwrap_or_else(|e| fail(format!("{}: {}", descriptor_path.display(), e)));
+
+    for (i, page) in font.pages.iter().enumerate() {
+        write_png(&output.with_file_name(BmFont::page_file_name(&stem, i)), page);
+    }
+
+    println!("wrote {} characters on {} page(s) to {}", font.chars.len(), font.pages.len(), descriptor_path.display());
+}
+
+/// Parse dimensions written as `WIDTHxHEIGHT`.
+fn parse_dims(s: &str) -> Option<DimsBox<cgmath_geometry::D2, u32>> {
+    let mut parts = s.splitn(2, 'x');
+    let width = parts.next()?.trim().parse().ok()?;
+    let height = parts.next()?.trim().parse().ok()?;
+    match width != 0 && height != 0 {
+        true => Some(DimsBox::new2(width, height)),
+        false => None
+    }
+}
+
+fn write_png(path: &Path, image: &Image) {
+    let file = File::create(path).unwrap_or_else(|e| fail(format!("{}: {}", path.display(), e)));
+    let ref mut w = BufWriter::new(file);
+
+    let mut encoder = png::Encoder::new(w, image.dims.width(), image.dims.height());
+    encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().unwrap_or_else(|e| fail(e));
+    writer.write_image_data(&image.data).unwrap_or_else(|e| fail(e));
+}
@@ -0,0 +1,300 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Print everything glyphydog can tell about a font file.
+
+extern crate glyphydog;
+extern crate getopts;
+
+mod common;
+
+use glyphydog::{FTLib, Face, FaceSize, DPI, LayoutTable, Tag};
+use getopts::Options;
+use common::fail;
+
+use std::fmt::Write;
+
+/// The information gathered about a font, which can be printed either as indented text or JSON.
+enum Value {
+    Null,
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Array(Vec<Value>),
+    Object(Vec<(&'static str, Value)>)
+}
+
+fn main() {
+    let mut opts = Options::new();
+    common::add_face_options(&mut opts);
+    opts.optflag("a", "all-faces", "describe every face in the font file, instead of just the one selected by --face-index");
+    opts.optflag("g", "glyphs", "list the name of every glyph");
+    opts.optflag("j", "json", "print JSON instead of text");
+
+    let matches = common::parse_args(&opts, "Usage: glyphydog-info [options] FONT");
+    let font_path = common::free_arg(&matches, 0, "font file");
+
+    let lib = FTLib::new();
+    let (face_size, dpi) = common::face_size(&matches);
+    let face = common::open_face(font_path, &matches, &lib);
+
+    let faces = match matches.opt_present("all-faces") {
+        false => vec![face],
+        true => (0..face.num_faces())
+            .map(|i| Face::new_path(font_path, i as i32, &lib).unwrap_or_else(|e| fail(format!("{}: {}", font_path, e))))
+            .collect()
+    };
+
+    let list_glyphs = matches.opt_present("glyphs");
+    let info = Value::Object(vec![
+        ("path", Value::Str(font_path.to_owned())),
+        ("num_faces", Value::Int(faces[0].num_faces() as i64)),
+        ("faces", Value::Array(faces.into_iter().map(|mut face| describe_face(&mut face, face_size, dpi, list_glyphs)).collect()))
+    ]);
+
+    let mut out = String::new();
+    match matches.opt_present("json") {
+        true => write_json(&mut out, &info, 0),
+        false => write_text(&mut out, &info, 0)
+    }
+    println!("{}", out.trim_end());
+}
+
+fn describe_face<B: ?Sized>(face: &mut Face<B>, face_size: FaceSize, dpi: DPI, list_glyphs: bool) -> Value {
+    let opt_str = |s: Option<String>| s.map(Value::Str).unwrap_or(Value::Null);
+
+    let fu = face.metrics_font_units();
+    let metrics = Value::Object(vec![
+        ("units_per_em", Value::Int(fu.units_per_em as i64)),
+        ("ascender", Value::Int(fu.ascender as i64)),
+        ("descender", Value::Int(fu.descender as i64)),
+        ("height", Value::Int(fu.height as i64)),
+        ("max_advance_width", Value::Int(fu.max_advance_width as i64)),
+        ("max_advance_height", Value::Int(fu.max_advance_height as i64)),
+        ("underline_position", Value::Int(fu.underline_position as i64)),
+        ("underline_thickness", Value::Int(fu.underline_thickness as i64))
+    ]);
+
+    let sized = match face.metrics_sized(face_size, dpi) {
+        Ok(m) => Value::Object(vec![
+            ("size", Value::Float(face_size.height as f64 / 64.0)),
+            ("dpi", Value::Int(dpi.vert as i64)),
+            ("x_ppem", Value::Int(m.x_ppem as i64)),
+            ("y_ppem", Value::Int(m.y_ppem as i64)),
+            ("x_scale", Value::Int(m.x_scale as i64)),
+            ("y_scale", Value::Int(m.y_scale as i64)),
+            ("ascender", Value::Int(m.ascender as i64)),
+            ("descender", Value::Int(m.descender as i64)),
+            ("height", Value::Int(m.height as i64)),
+            ("max_advance", Value::Int(m.max_advance as i64))
+        ]),
+        Err(e) => Value::Str(e.to_string())
+    };
+
+    let names = face.names().into_iter().map(|name| Value::Object(vec![
+        ("name_id", Value::Int(name.name_id as i64)),
+        ("platform_id", Value::Int(name.platform_id as i64)),
+        ("encoding_id", Value::Int(name.encoding_id as i64)),
+        ("language_id", Value::Int(name.language_id as i64)),
+        ("value", opt_str(name.value))
+    ])).collect();
+
+    let charmaps = face.charmaps().into_iter().map(|charmap| Value::Object(vec![
+        ("platform_id", Value::Int(charmap.platform_id as i64)),
+        ("encoding_id", Value::Int(charmap.encoding_id as i64)),
+        ("encoding", Value::Str(charmap.encoding.to_string())),
+        ("format", charmap.format.map(|f| Value::Int(f as i64)).unwrap_or(Value::Null))
+    ])).collect();
+
+    let coverage = face.coverage().into_iter().map(|range| match range.start() == range.end() {
+        true => Value::Str(format!("U+{:04X}", range.start())),
+        false => Value::Str(format!("U+{:04X}-U+{:04X}", range.start(), range.end()))
+    }).collect();
+
+    let layout = |table| {
+        let tag_list = |tags: Vec<Tag>| Value::Array(tags.into_iter().map(|t| Value::Str(t.to_string())).collect());
+        let scripts = face.layout_systems(table).into_iter().map(|script| Value::Object(vec![
+            ("script", Value::Str(script.tag.to_string())),
+            ("languages", Value::Array(script.languages.into_iter().map(|language| Value::Object(vec![
+                ("language", language.tag.map(|t| Value::Str(t.to_string())).unwrap_or(Value::Null)),
+                ("required_feature", language.required_feature.map(|t| Value::Str(t.to_string())).unwrap_or(Value::Null)),
+                ("features", tag_list(language.features))
+            ])).collect()))
+        ])).collect();
+
+        let mut features = face.layout_features(table);
+        features.sort();
+        features.dedup();
+        let features = features.into_iter().map(|feature| describe_feature(face, table, feature)).collect();
+
+        Value::Object(vec![
+            ("scripts", Value::Array(scripts)),
+            ("features", Value::Array(features))
+        ])
+    };
+
+    let axes = face.variation_axes().into_iter().map(|axis| Value::Object(vec![
+        ("tag", Value::Str(axis.tag.to_string())),
+        ("name", Value::Str(axis.name)),
+        ("minimum", Value::Float(axis.minimum as f64)),
+        ("default", Value::Float(axis.default as f64)),
+        ("maximum", Value::Float(axis.maximum as f64))
+    ])).collect();
+
+    let strikes = face.strikes().into_iter().map(|strike| Value::Object(vec![
+        ("width", Value::Int(strike.width as i64)),
+        ("height", Value::Int(strike.height as i64)),
+        ("size", Value::Float(strike.size as f64 / 64.0)),
+        ("x_ppem", Value::Float(strike.x_ppem as f64 / 64.0)),
+        ("y_ppem", Value::Float(strike.y_ppem as f64 / 64.0))
+    ])).collect();
+
+    let mut fields = vec![
+        ("face_index", Value::Int(face.face_index() as i64)),
+        ("family_name", opt_str(face.family_name())),
+        ("style_name", opt_str(face.style_name())),
+        ("postscript_name", opt_str(face.postscript_name())),
+        ("num_glyphs", Value::Int(face.num_glyphs() as i64)),
+        ("metrics_font_units", metrics),
+        ("metrics_26_6", sized),
+        ("names", Value::Array(names)),
+        ("charmaps", Value::Array(charmaps)),
+        ("coverage", Value::Array(coverage)),
+        ("gsub", layout(LayoutTable::Gsub)),
+        ("gpos", layout(LayoutTable::Gpos)),
+        ("variation_axes", Value::Array(axes)),
+        ("strikes", Value::Array(strikes))
+    ];
+    if list_glyphs {
+        let glyph_names = face.glyph_names().into_iter().enumerate()
+            .map(|(i, name)| Value::Str(name.unwrap_or_else(|| format!("gid{}", i))))
+            .collect();
+        fields.push(("glyph_names", Value::Array(glyph_names)));
+    }
+    Value::Object(fields)
+}
+
+#[cfg(feature = "feature-names")]
+fn describe_feature<B: ?Sized>(face: &Face<B>, table: LayoutTable, feature: Tag) -> Value {
+    let opt_str = |s: Option<String>| s.map(Value::Str).unwrap_or(Value::Null);
+    match face.feature_names(table, feature) {
+        None => Value::Str(feature.to_string()),
+        Some(names) => Value::Object(vec![
+            ("tag", Value::Str(feature.to_string())),
+            ("label", opt_str(names.label)),
+            ("tooltip", opt_str(names.tooltip)),
+            ("sample", opt_str(names.sample)),
+            ("parameters", Value::Array(names.parameters.into_iter().map(Value::Str).collect())),
+            ("characters", Value::Array(names.characters.into_iter().map(|c| Value::Str(format!("U+{:04X}", c as u32))).collect()))
+        ])
+    }
+}
+
+/// Feature names need Harfbuzz 2.1, so without it features are only listed by tag.
+#[cfg(not(feature = "feature-names"))]
+fn describe_feature<B: ?Sized>(_: &Face<B>, _: LayoutTable, feature: Tag) -> Value {
+    Value::Str(feature.to_string())
+}
+
+fn write_json(out: &mut String, value: &Value, indent: usize) {
+    match *value {
+        Value::Null => out.push_str("null"),
+        Value::Int(i) => write!(out, "{}", i).unwrap(),
+        Value::Float(f) => write!(out, "{}", f).unwrap(),
+        Value::Str(ref s) => write_json_string(out, s),
+        Value::Array(ref values) if values.is_empty() => out.push_str("[]"),
+        Value::Array(ref values) => {
+            out.push_str("[\n");
+            for (i, value) in values.iter().enumerate() {
+                push_indent(out, indent + 1);
+                write_json(out, value, indent + 1);
+                out.push_str(if i + 1 == values.len() {"\n"} else {",\n"});
+            }
+            push_indent(out, indent);
+            out.push(']');
+        },
+        Value::Object(ref fields) => {
+            out.push_str("{\n");
+            for (i, &(key, ref value)) in fields.iter().enumerate() {
+                push_indent(out, indent + 1);
+                write_json_string(out, key);
+                out.push_str(": ");
+                write_json(out, value, indent + 1);
+                out.push_str(if i + 1 == fields.len() {"\n"} else {",\n"});
+            }
+            push_indent(out, indent);
+            out.push('}');
+        }
+    }
+}
+
+fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+}
+
+/// Write a value as indented `key: value` lines, with arrays of plain values joined onto one line.
+fn write_text(out: &mut String, value: &Value, indent: usize) {
+    match *value {
+        Value::Object(ref fields) => for &(key, ref value) in fields {
+            push_indent(out, indent);
+            out.push_str(key);
+            out.push(':');
+            match *value {
+                Value::Object(_) => {
+                    out.push('\n');
+                    write_text(out, value, indent + 1);
+                },
+                Value::Array(ref values) if values.iter().any(|v| match *v {Value::Object(_) => true, _ => false}) => {
+                    out.push('\n');
+                    for (i, value) in values.iter().enumerate() {
+                        push_indent(out, indent + 1);
+                        writeln!(out, "[{}]", i).unwrap();
+                        write_text(out, value, indent + 2);
+                    }
+                },
+                _ => {
+                    out.push(' ');
+                    write_text(out, value, indent);
+                    out.push('\n');
+                }
+            }
+        },
+        Value::Array(ref values) => for (i, value) in values.iter().enumerate() {
+            if i != 0 {
+                out.push_str(", ");
+            }
+            write_text(out, value, indent);
+        },
+        Value::Null => out.push('-'),
+        Value::Int(i) => write!(out, "{}", i).unwrap(),
+        Value::Float(f) => write!(out, "{}", f).unwrap(),
+        Value::Str(ref s) => out.push_str(s)
+    }
+}
+
+fn push_indent(out: &mut String, indent: usize) {
+    for _ in 0..indent {
+        out.push_str("  ");
+    }
+}
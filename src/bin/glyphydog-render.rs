@@ -0,0 +1,224 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Render text to a PNG image.
+
+extern crate glyphydog;
+extern crate getopts;
+extern crate cgmath_geometry;
+extern crate png;
+
+mod common;
+
+use glyphydog::{FTLib, Face, Shaper, FaceSize, DPI, ShapeOptions, ShapedGlyph, RenderMode, LoadFlags, LcdFilter, RenderTarget, ImageFormat, Rgba, BlendOptions, CoverageAdjust, render_text_to_buffer};
+use getopts::Options;
+use common::fail;
+
+use std::fs::File;
+use std::io::{self, Read, BufWriter};
+use png::HasParameters;
+
+use cgmath_geometry::cgmath::Point2;
+use cgmath_geometry::rect::DimsBox;
+
+/// Everything needed to shape a run of text.
+struct Shaping<'a> {
+    shaper: Shaper,
+    face: Face<()>,
+    face_size: FaceSize,
+    dpi: DPI,
+    options: &'a ShapeOptions
+}
+
+fn main() {
+    let mut opts = Options::new();
+    common::add_face_options(&mut opts);
+    common::add_shape_options(&mut opts);
+    common::add_render_mode_option(&mut opts);
+    opts.optopt("", "fg", "text color as RRGGBB or RRGGBBAA (default 000000)", "COLOR");
+    opts.optopt("", "bg", "background color as RRGGBB or RRGGBBAA (default ffffff)", "COLOR");
+    opts.optopt("", "blend", "naive or freetype (default naive)", "STYLE");
+    opts.optopt("", "gamma", "coverage gamma, applied on top of --blend (default 1.0)", "GAMMA");
+    opts.optopt("", "contrast", "coverage contrast, applied on top of --blend (default 0.0)", "CONTRAST");
+    opts.optopt("", "width", "canvas width in pixels (default: fit the text)", "PIXELS");
+    opts.optopt("", "height", "canvas height in pixels (default: fit the text)", "PIXELS");
+    opts.optopt("", "wrap", "wrap lines longer than this many pixels (default: the canvas width, if given)", "PIXELS");
+    opts.optopt("", "margin", "space around the text in pixels (default 8)", "PIXELS");
+    opts.optopt("o", "output", "path to write the image to (default out.png)", "PATH");
+
+    let matches = common::parse_args(&opts, "Usage: glyphydog-render [options] FONT [TEXT]\n\nRenders TEXT, or standard input if TEXT isn't given.");
+    let font_path = common::free_arg(&matches, 0, "font file");
+    let text = match matches.free.get(1) {
+        Some(text) => text.clone(),
+        None => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text).unwrap_or_else(|e| fail(e));
+            text
+        }
+    };
+
+    let render_mode = common::render_mode(&matches);
+    let mut blend = match matches.opt_str("blend").as_ref().map(|s| &**s) {
+        None | Some("naive") => BlendOptions::NAIVE,
+        Some("freetype") => BlendOptions::FREETYPE,
+        Some(blend) => fail(format!("unknown blend style: {}", blend))
+    };
+    blend.coverage = CoverageAdjust::new(common::opt_or(&matches, "gamma", 1.0), common::opt_or(&matches, "contrast", 0.0))
+        .unwrap_or_else(|_| fail("--gamma must be positive and --contrast greater than -1"));
+    let fg = color_opt(&matches, "fg", Rgba::new(0, 0, 0, 255));
+    let bg = color_opt(&matches, "bg", Rgba::new(255, 255, 255, 255));
+    let margin = common::opt_or(&matches, "margin", 8u32) as i32;
+    let canvas_width = common::opt::<u32>(&matches, "width");
+    let canvas_height = common::opt::<u32>(&matches, "height");
+    let wrap_width = common::opt::<u32>(&matches, "wrap")
+        .or(canvas_width.map(|w| w.saturating_sub(margin as u32 * 2)))
+        .map(|w| w as i32);
+    let output = matches.opt_str("output").unwrap_or_else(|| "out.png".to_owned());
+
+    let mut lib = FTLib::new();
+    if let RenderMode::Lcd | RenderMode::LcdV = render_mode {
+        lib.set_lcd_filter(LcdFilter::Default).unwrap_or_else(|e| fail(e));
+    }
+    let (face_size, dpi) = common::face_size(&matches);
+    let options = common::shape_options(&matches);
+    let mut shaping = Shaping {
+        shaper: Shaper::new(),
+        face: common::open_face(font_path, &matches, &lib),
+        face_size,
+        dpi,
+        options: &options
+    };
+
+    let mut lines = Vec::new();
+    for paragraph in text.trim_end_matches('\n').split('\n') {
+        wrap_paragraph(paragraph.trim_end_matches('\r'), wrap_width, &mut shaping, &mut lines);
+    }
+
+    let metrics = shaping.face.metrics_sized(face_size, dpi).unwrap_or_else(|e| fail(e));
+    let ascender = (metrics.ascender + 63) / 64;
+    let descender = (metrics.descender - 63) / 64;
+    let line_height = (metrics.height + 63) / 64;
+
+    let text_width = lines.iter().map(|line| line_width(line, &mut shaping)).max().unwrap_or(0);
+    let text_height = ascender - descender + line_height * (lines.len() as i32 - 1).max(0);
+    let width = canvas_width.unwrap_or((text_width + margin * 2).max(1) as u32);
+    let height = canvas_height.unwrap_or((text_height + margin * 2).max(1) as u32);
+
+    let mut image = Vec::with_capacity(width as usize * height as usize * 4);
+    for _ in 0..width as usize * height as usize {
+        image.extend_from_slice(&[bg.r, bg.g, bg.b, bg.a]);
+    }
+
+    {
+        let mut target = RenderTarget::new(&mut image, DimsBox::new2(width, height), ImageFormat::Rgba8);
+        for (i, line) in lines.iter().enumerate() {
+            let origin = Point2::new(margin, margin + ascender + line_height * i as i32);
+            let glyphs = shaping.shaper.shape_text_with(line, &mut shaping.face, face_size, dpi, &options)
+                .unwrap_or_else(|e| fail(e));
+            render_text_to_buffer(
+                glyphs, &mut shaping.face, face_size, dpi, LoadFlags::empty(), render_mode,
+                &mut target, origin, fg, blend
+            ).unwrap_or_else(|e| fail(e));
+        }
+    }
+
+    let file = File::create(&output).unwrap_or_else(|e| fail(format!("{}: {}", output, e)));
+    let ref mut w = BufWriter::new(file);
+
+    let mut encoder = png::Encoder::new(w, width, height);
+    encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().unwrap_or_else(|e| fail(e));
+    writer.write_image_data(&image).unwrap_or_else(|e| fail(e));
+}
+
+fn color_opt(matches: &getopts::Matches, name: &str, default: Rgba) -> Rgba {
+    match matches.opt_str(name) {
+        Some(s) => parse_color(&s).unwrap_or_else(|| fail(format!("invalid color for --{}: {}", name, s))),
+        None => default
+    }
+}
+
+/// Parse a color written as `RRGGBB` or `RRGGBBAA`, optionally prefixed with `#`.
+fn parse_color(s: &str) -> Option<Rgba> {
+    let s = s.trim_start_matches('#');
+    if !s.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(s.get(i * 2..i * 2 + 2)?, 16).ok();
+
+    match s.len() {
+        6 => Some(Rgba::new(channel(0)?, channel(1)?, channel(2)?, 255)),
+        8 => Some(Rgba::new(channel(0)?, channel(1)?, channel(2)?, channel(3)?)),
+        _ => None
+    }
+}
+
+fn shape(text: &str, shaping: &mut Shaping) -> Vec<ShapedGlyph> {
+    shaping.shaper.shape_text_with(text, &mut shaping.face, shaping.face_size, shaping.dpi, shaping.options)
+        .unwrap_or_else(|e| fail(e))
+        .collect()
+}
+
+fn line_width(line: &str, shaping: &mut Shaping) -> i32 {
+    shape(line, shaping).iter().map(|g| g.advance.x).sum()
+}
+
+/// Break a paragraph into lines no wider than `wrap_width`, breaking at whitespace. Words wider
+/// than `wrap_width` get a line to themselves.
+fn wrap_paragraph<'t>(paragraph: &'t str, wrap_width: Option<i32>, shaping: &mut Shaping, lines: &mut Vec<&'t str>) {
+    let wrap_width = match wrap_width {
+        Some(w) => w,
+        None => {
+            lines.push(paragraph);
+            return;
+        }
+    };
+
+    let mut rest = paragraph;
+    loop {
+        let mut advances = shape(rest, shaping).iter().map(|g| (g.str_index, g.advance.x)).collect::<Vec<_>>();
+        // Sort into logical order, so that the width of any prefix of the text can be found
+        // regardless of its direction.
+        advances.sort_by_key(|&(str_index, _)| str_index);
+        if advances.iter().map(|&(_, advance)| advance).sum::<i32>() <= wrap_width {
+            lines.push(rest);
+            return;
+        }
+
+        let width_before = |byte: usize| advances.iter()
+            .take_while(|&&(str_index, _)| str_index < byte)
+            .map(|&(_, advance)| advance)
+            .sum::<i32>();
+        let mut breaks = rest.char_indices()
+            .filter(|&(i, c)| c.is_whitespace() && 0 < i)
+            .map(|(i, _)| i);
+
+        let break_at = match breaks.next() {
+            None => {
+                lines.push(rest);
+                return;
+            },
+            Some(first) => breaks
+                .take_while(|&i| width_before(i) <= wrap_width)
+                .last()
+                .unwrap_or(first)
+        };
+
+        lines.push(rest[..break_at].trim_end());
+        rest = rest[break_at..].trim_start();
+        if rest.is_empty() {
+            return;
+        }
+    }
+}
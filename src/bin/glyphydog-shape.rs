@@ -0,0 +1,81 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Shape text with a font and print the resulting glyphs in the same formats as `hb-shape`.
+//! Positions are in 26.6 pixels rather than `hb-shape`'s default of font units.
+
+extern crate glyphydog;
+extern crate getopts;
+
+mod common;
+
+use glyphydog::{FTLib, Shaper, SerializeFormat, SerializeFlags, serialize_glyphs};
+use getopts::Options;
+use common::fail;
+
+use std::io::{self, BufRead};
+
+fn main() {
+    let mut opts = Options::new();
+    common::add_face_options(&mut opts);
+    common::add_shape_options(&mut opts);
+    opts.optopt("", "output-format", "text or json (default text)", "FORMAT");
+    opts.optflag("", "no-glyph-names", "print glyph indices instead of names");
+    opts.optflag("", "no-clusters", "don't print cluster values");
+    opts.optflag("", "no-positions", "don't print glyph offsets or advances");
+    opts.optflag("", "no-advances", "don't print glyph advances");
+
+    let matches = common::parse_args(&opts, "Usage: glyphydog-shape [options] FONT [TEXT]\n\nShapes TEXT, or each line of standard input if TEXT isn't given.");
+    let font_path = common::free_arg(&matches, 0, "font file");
+
+    let lib = FTLib::new();
+    let mut face = common::open_face(font_path, &matches, &lib);
+    let (face_size, dpi) = common::face_size(&matches);
+    let options = common::shape_options(&matches);
+    let format = match matches.opt_str("output-format").as_ref().map(|s| &**s) {
+        None | Some("text") => SerializeFormat::Text,
+        Some("json") => SerializeFormat::Json,
+        Some(format) => fail(format!("unknown output format: {}", format))
+    };
+
+    let mut flags = SerializeFlags::empty();
+    for &(opt, flag) in &[
+        ("no-glyph-names", SerializeFlags::NO_GLYPH_NAMES),
+        ("no-clusters", SerializeFlags::NO_CLUSTERS),
+        ("no-positions", SerializeFlags::NO_POSITIONS),
+        ("no-advances", SerializeFlags::NO_ADVANCES)
+    ] {
+        if matches.opt_present(opt) {
+            flags |= flag;
+        }
+    }
+
+    let mut shaper = Shaper::new();
+    let mut shape = |text: &str| {
+        let records = shaper.shape_text_with(text, &mut face, face_size, dpi, &options)
+            .unwrap_or_else(|e| fail(e))
+            .into_records();
+        println!("{}", serialize_glyphs(&records, &face, format, flags));
+    };
+
+    match matches.free.get(1) {
+        Some(text) => shape(text),
+        None => {
+            let stdin = io::stdin();
+            for line in stdin.lock().lines() {
+                shape(&line.unwrap_or_else(|e| fail(e)));
+            }
+        }
+    }
+}
@@ -0,0 +1,535 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Baking a character set into AngelCode BMFont atlases.
+
+use {Face, FaceSize, DPI, LoadFlags, RenderMode, KerningSource, Error, Operation, FaceError};
+use image::Image;
+use render::ImageFormat;
+
+use std::cmp::Reverse;
+use std::fmt::Write;
+use std::collections::{HashMap, BTreeMap};
+
+use cgmath_geometry::D2;
+use cgmath_geometry::rect::{DimsBox, GeoBox};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AtlasOptions {
+    pub face_size: FaceSize,
+    pub dpi: DPI,
+    pub load_flags: LoadFlags,
+    pub render_mode: RenderMode,
+    /// The dimensions of each atlas page. These can't exceed 65535, the largest size the binary
+    /// descriptor format can hold.
+    pub page_dims: DimsBox<D2, u32>,
+    /// The number of empty pixels left between glyphs, and around the edges of each page. Can't
+    /// exceed 255.
+    pub spacing: u32,
+    /// Whether to include kerning pairs. Like Harfbuzz, these come from the GPOS `kern` feature if
+    /// the font has any GPOS pairs, and from the `kern` table otherwise.
+    pub kerning: bool
+}
+
+/// The file formats BMFont descriptors can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BmFontFormat {
+    Text,
+    Xml,
+    Binary,
+    /// The JSON layout used by most web and engine BMFont loaders.
+    Json
+}
+
+/// A baked bitmap font. Each page is an `Rgba8` image holding white glyphs, with the glyph
+/// coverage in the alpha channel.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BmFont {
+    pub face_name: String,
+    /// The size of the font, in pixels per em.
+    pub size: u32,
+    pub line_height: u32,
+    /// The distance from the top of a line to its baseline, in pixels.
+    pub base: u32,
+    pub spacing: u32,
+    pub page_dims: DimsBox<D2, u32>,
+    pub pages: Vec<Image>,
+    /// The baked characters, sorted by character code.
+    pub chars: Vec<BmChar>,
+    pub kernings: Vec<BmKerning>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BmChar {
+    pub id: char,
+    pub glyph_index: u32,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// The offset from the pen position to the left edge of the glyph's image.
+    pub x_offset: i32,
+    /// The offset from the top of the line to the top edge of the glyph's image.
+    pub y_offset: i32,
+    pub x_advance: i32,
+    pub page: u32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BmKerning {
+    pub first: char,
+    pub second: char,
+    pub amount: i32
+}
+
+/// Packs glyphs into pages row by row, starting a new row when the current one fills up and a new
+/// page when the current page does.
+struct ShelfPacker {
+    page_dims: DimsBox<D2, u32>,
+    spacing: u32,
+    pages: Vec<Image>,
+    x: u32,
+    y: u32,
+    shelf_height: u32
+}
+
+impl Default for AtlasOptions {
+    fn default() -> AtlasOptions {
+        AtlasOptions {
+            face_size: FaceSize::new(32*64, 32*64),
+            dpi: DPI::new(72, 72),
+            load_flags: LoadFlags::empty(),
+            render_mode: RenderMode::Normal,
+            page_dims: DimsBox::new2(512, 512),
+            spacing: 1,
+            kerning: true
+        }
+    }
+}
+
+impl BmFont {
+    /// Render every character in `chars` that the face has a glyph for, and pack them into atlas
+    /// pages.
+    ///
+    /// Everything is checked against the limits of the binary descriptor format, which has the
+    /// smallest fields:
+    ///
+    /// - `Error::InvalidArgument` if `options.page_dims` or `options.spacing` are too large.
+    /// - `Error::InvalidPixelSize` if the font's size, line height or baseline don't fit.
+    /// - `Error::GlyphTooBig` if a glyph doesn't fit on a single page, or its offsets, advance or
+    ///   kerning don't fit.
+    /// - `Error::ArrayTooLarge` if the glyphs need more than 256 pages.
+    pub fn build<B: ?Sized>(face: &mut Face<B>, chars: &[char], options: &AtlasOptions) -> Result<BmFont, FaceError> {
+        if MAX_U16 < options.page_dims.width() || MAX_U16 < options.page_dims.height() || MAX_U8 < options.spacing {
+            return Err(Error::InvalidArgument.during(Operation::BuildAtlas));
+        }
+
+        let metrics = face.metrics_sized(options.face_size, options.dpi)?;
+        let base = (metrics.ascender + 63) / 64;
+        let line_height = (metrics.height + 63) / 64;
+        if !fits_i16(metrics.y_ppem as i32) || !fits_u16(line_height) || !fits_u16(base) {
+            return Err(Error::InvalidPixelSize.during(Operation::BuildAtlas));
+        }
+
+        let mut chars = chars.to_vec();
+        chars.sort();
+        chars.dedup();
+
+        let mut glyphs = Vec::new();
+        for c in chars {
+            let glyph_index = face.char_index(c);
+            if glyph_index == 0 {
+                continue;
+            }
+            let glyph = face
+                .load_glyph(glyph_index, options.face_size, options.dpi, options.load_flags, options.render_mode)?
+                .render_image(options.render_mode)?;
+            let image = glyph.bitmap.as_bitmap().to_rgba8();
+
+            let bm_char = BmChar {
+                id: c,
+                glyph_index,
+                x: 0,
+                y: 0,
+                width: image.dims.width(),
+                height: image.dims.height(),
+                x_offset: glyph.bitmap_offset.x,
+                y_offset: base - glyph.bitmap_offset.y,
+                x_advance: (glyph.advance.x + 32) >> 6,
+                page: 0
+            };
+            if !fits_i16(bm_char.x_offset) || !fits_i16(bm_char.y_offset) || !fits_i16(bm_char.x_advance) {
+                return Err(Error::GlyphTooBig.during_glyph(Operation::BuildAtlas, glyph_index));
+            }
+            glyphs.push((bm_char, image));
+        }
+
+        // Packing the tallest glyphs first keeps the shelves tightly filled.
+        let mut order = (0..glyphs.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| Reverse(glyphs[i].0.height));
+
+        let mut packer = ShelfPacker::new(options.page_dims, options.spacing);
+        for i in order {
+            let (ref mut bm_char, ref image) = glyphs[i];
+            if bm_char.width == 0 || bm_char.height == 0 {
+                continue;
+            }
+
+            let (page, x, y) = packer.place(bm_char.width, bm_char.height)
+                .ok_or(Error::GlyphTooBig.during_glyph(Operation::BuildAtlas, bm_char.glyph_index))?;
+            if MAX_U8 < page {
+                return Err(Error::ArrayTooLarge.during(Operation::BuildAtlas));
+            }
+            bm_char.page = page;
+            bm_char.x = x;
+            bm_char.y = y;
+            packer.blit(page, x, y, image);
+        }
+
+        let chars = glyphs.into_iter().map(|(bm_char, _)| bm_char).collect::<Vec<_>>();
+        let mut kernings = Vec::new();
+        if options.kerning {
+            let mut glyph_chars = HashMap::new();
+            for c in &chars {
+                glyph_chars.entry(c.glyph_index).or_insert_with(Vec::new).push(c.id);
+            }
+
+            // Harfbuzz ignores the `kern` table when GPOS kerning is available, so do the same.
+            let pairs = face.kerning_pairs().collect::<Vec<_>>();
+            let source = match pairs.iter().any(|p| p.source == KerningSource::Gpos) {
+                true => KerningSource::Gpos,
+                false => KerningSource::Kern
+            };
+
+            let mut amounts = BTreeMap::new();
+            for pair in pairs.into_iter().filter(|p| p.source == source) {
+                let (firsts, seconds) = match (glyph_chars.get(&pair.left), glyph_chars.get(&pair.right)) {
+                    (Some(firsts), Some(seconds)) => (firsts, seconds),
+                    _ => continue
+                };
+                // Convert from font units to 26.6 pixels. `metrics` is scaled to the bitmap strike
+                // selected by `StrikeSelection::Nearest`, so this scales the kerning the same way
+                // `render_image` scales the advances.
+                let value = (pair.value as i64 * metrics.x_scale as i64 / 0x10000) as i32;
+                for &first in firsts {
+                    for &second in seconds {
+                        *amounts.entry((first, second)).or_insert(0) += value;
+                    }
+                }
+            }
+
+            for ((first, second), value) in amounts {
+                let amount = (value + 32) >> 6;
+                if !fits_i16(amount) {
+                    return Err(Error::GlyphTooBig.during(Operation::BuildAtlas));
+                }
+                if amount != 0 {
+                    kernings.push(BmKerning{ first, second, amount });
+                }
+            }
+        }
+
+        Ok(BmFont {
+            face_name: face.family_name().unwrap_or_default(),
+            size: metrics.y_ppem as u32,
+            line_height: line_height as u32,
+            base: base as u32,
+            spacing: options.spacing,
+            page_dims: options.page_dims,
+            pages: packer.pages,
+            chars,
+            kernings
+        })
+    }
+
+    /// The file name the descriptor refers to page `page` by, given the descriptor's file stem.
+    pub fn page_file_name(file_stem: &str, page: usize) -> String {
+        format!("{}_{}.png", file_stem, page)
+    }
+
+    /// Write the font's descriptor. Pages are referred to with the names returned by
+    /// `page_file_name`.
+    ///
+    /// The binary format truncates values that don't fit in its fields, which can't happen for
+    /// fonts returned by `build` unless they've been modified.
+    pub fn descriptor(&self, format: BmFontFormat, file_stem: &str) -> Vec<u8> {
+        let page_names = (0..self.pages.len()).map(|i| BmFont::page_file_name(file_stem, i)).collect::<Vec<_>>();
+        match format {
+            BmFontFormat::Text => self.text_descriptor(&page_names).into_bytes(),
+            BmFontFormat::Xml => self.xml_descriptor(&page_names).into_bytes(),
+            BmFontFormat::Binary => self.binary_descriptor(&page_names),
+            BmFontFormat::Json => self.json_descriptor(&page_names).into_bytes()
+        }
+    }
+
+    fn text_descriptor(&self, page_names: &[String]) -> String {
+        let mut out = String::new();
+        writeln!(
+            out,
+            "info face=\"{}\" size={} bold=0 italic=0 charset=\"\" unicode=1 stretchH=100 smooth=1 aa=1 padding=0,0,0,0 spacing={},{} outline=0",
+            self.face_name.replace('"', "'"), self.size, self.spacing, self.spacing
+        ).unwrap();
+        writeln!(
+            out,
+            "common lineHeight={} base={} scaleW={} scaleH={} pages={} packed=0 alphaChnl=0 redChnl=4 greenChnl=4 blueChnl=4",
+            self.line_height, self.base, self.page_dims.width(), self.page_dims.height(), self.pages.len()
+        ).unwrap();
+        for (i, name) in page_names.iter().enumerate() {
+            writeln!(out, "page id={} file=\"{}\"", i, name).unwrap();
+        }
+
+        writeln!(out, "chars count={}", self.chars.len()).unwrap();
+        for c in &self.chars {
+            writeln!(
+                out,
+                "char id={} x={} y={} width={} height={} xoffset={} yoffset={} xadvance={} page={} chnl=15",
+                c.id as u32, c.x, c.y, c.width, c.height, c.x_offset, c.y_offset, c.x_advance, c.page
+            ).unwrap();
+        }
+
+        if !self.kernings.is_empty() {
+            writeln!(out, "kernings count={}", self.kernings.len()).unwrap();
+            for k in &self.kernings {
+                writeln!(out, "kerning first={} second={} amount={}", k.first as u32, k.second as u32, k.amount).unwrap();
+            }
+        }
+        out
+    }
+
+    fn xml_descriptor(&self, page_names: &[String]) -> String {
+        let mut out = String::new();
+        out.push_str("<?xml version=\"1.0\"?>\n<font>\n");
+        writeln!(
+            out,
+            "  <info face=\"{}\" size=\"{}\" bold=\"0\" italic=\"0\" charset=\"\" unicode=\"1\" stretchH=\"100\" smooth=\"1\" aa=\"1\" padding=\"0,0,0,0\" spacing=\"{},{}\" outline=\"0\"/>",
+            xml_escape(&self.face_name), self.size, self.spacing, self.spacing
+        ).unwrap();
+        writeln!(
+            out,
+            "  <common lineHeight=\"{}\" base=\"{}\" scaleW=\"{}\" scaleH=\"{}\" pages=\"{}\" packed=\"0\" alphaChnl=\"0\" redChnl=\"4\" greenChnl=\"4\" blueChnl=\"4\"/>",
+            self.line_height, self.base, self.page_dims.width(), self.page_dims.height(), self.pages.len()
+        ).unwrap();
+
+        out.push_str("  <pages>\n");
+        for (i, name) in page_names.iter().enumerate() {
+            writeln!(out, "    <page id=\"{}\" file=\"{}\"/>", i, xml_escape(name)).unwrap();
+        }
+        out.push_str("  </pages>\n");
+
+        writeln!(out, "  <chars count=\"{}\">", self.chars.len()).unwrap();
+        for c in &self.chars {
+            writeln!(
+                out,
+                "    <char id=\"{}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" xoffset=\"{}\" yoffset=\"{}\" xadvance=\"{}\" page=\"{}\" chnl=\"15\"/>",
+                c.id as u32, c.x, c.y, c.width, c.height, c.x_offset, c.y_offset, c.x_advance, c.page
+            ).unwrap();
+        }
+        out.push_str("  </chars>\n");
+
+        if !self.kernings.is_empty() {
+            writeln!(out, "  <kernings count=\"{}\">", self.kernings.len()).unwrap();
+            for k in &self.kernings {
+                writeln!(out, "    <kerning first=\"{}\" second=\"{}\" amount=\"{}\"/>", k.first as u32, k.second as u32, k.amount).unwrap();
+            }
+            out.push_str("  </kernings>\n");
+        }
+        out.push_str("</font>\n");
+        out
+    }
+
+    /// Write the version 3 binary descriptor.
+    fn binary_descriptor(&self, page_names: &[String]) -> Vec<u8> {
+        fn block(out: &mut Vec<u8>, block_type: u8, data: &[u8]) {
+            out.push(block_type);
+            out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            out.extend_from_slice(data);
+        }
+
+        let mut out = b"BMF\x03".to_vec();
+
+        let mut info = Vec::new();
+        info.extend_from_slice(&(self.size as i16).to_le_bytes());
+        // Smooth and unicode.
+        info.push(0b0000_0011);
+        // Charset, stretchH, aa, padding, spacing and outline.
+        info.push(0);
+        info.extend_from_slice(&100u16.to_le_bytes());
+        info.extend_from_slice(&[1, 0, 0, 0, 0, self.spacing as u8, self.spacing as u8, 0]);
+        info.extend_from_slice(self.face_name.as_bytes());
+        info.push(0);
+        block(&mut out, 1, &info);
+
+        let mut common = Vec::new();
+        for &value in &[self.line_height, self.base, self.page_dims.width(), self.page_dims.height(), self.pages.len() as u32] {
+            common.extend_from_slice(&(value as u16).to_le_bytes());
+        }
+        // Not packed, glyphs in alpha, and red, green and blue set to one.
+        common.extend_from_slice(&[0, 0, 4, 4, 4]);
+        block(&mut out, 2, &common);
+
+        let mut pages = Vec::new();
+        for name in page_names {
+            pages.extend_from_slice(name.as_bytes());
+            pages.push(0);
+        }
+        block(&mut out, 3, &pages);
+
+        let mut chars = Vec::with_capacity(self.chars.len() * 20);
+        for c in &self.chars {
+            chars.extend_from_slice(&(c.id as u32).to_le_bytes());
+            for &value in &[c.x, c.y, c.width, c.height] {
+                chars.extend_from_slice(&(value as u16).to_le_bytes());
+            }
+            for &value in &[c.x_offset, c.y_offset, c.x_advance] {
+                chars.extend_from_slice(&(value as i16).to_le_bytes());
+            }
+            chars.extend_from_slice(&[c.page as u8, 15]);
+        }
+        block(&mut out, 4, &chars);
+
+        if !self.kernings.is_empty() {
+            let mut kernings = Vec::with_capacity(self.kernings.len() * 10);
+            for k in &self.kernings {
+                kernings.extend_from_slice(&(k.first as u32).to_le_bytes());
+                kernings.extend_from_slice(&(k.second as u32).to_le_bytes());
+                kernings.extend_from_slice(&(k.amount as i16).to_le_bytes());
+            }
+            block(&mut out, 5, &kernings);
+        }
+
+        out
+    }
+
+    fn json_descriptor(&self, page_names: &[String]) -> String {
+        let mut out = String::new();
+        out.push_str("{\n  \"pages\": [");
+        for (i, name) in page_names.iter().enumerate() {
+            if i != 0 {
+                out.push_str(", ");
+            }
+            out.push_str(&json_string(name));
+        }
+        out.push_str("],\n");
+
+        out.push_str("  \"chars\": [");
+        for (i, c) in self.chars.iter().enumerate() {
+            out.push_str(if i == 0 {"\n"} else {",\n"});
+            write!(
+                out,
+                "    {{\"id\": {}, \"char\": {}, \"x\": {}, \"y\": {}, \"width\": {}, \"height\": {}, \"xoffset\": {}, \"yoffset\": {}, \"xadvance\": {}, \"page\": {}, \"chnl\": 15}}",
+                c.id as u32, json_string(&c.id.to_string()), c.x, c.y, c.width, c.height, c.x_offset, c.y_offset, c.x_advance, c.page
+            ).unwrap();
+        }
+        out.push_str("\n  ],\n");
+
+        writeln!(
+            out,
+            "  \"info\": {{\"face\": {}, \"size\": {}, \"bold\": 0, \"italic\": 0, \"charset\": [], \"unicode\": 1, \"stretchH\": 100, \"smooth\": 1, \"aa\": 1, \"padding\": [0, 0, 0, 0], \"spacing\": [{}, {}]}},",
+            json_string(&self.face_name), self.size, self.spacing, self.spacing
+        ).unwrap();
+        writeln!(
+            out,
+            "  \"common\": {{\"lineHeight\": {}, \"base\": {}, \"scaleW\": {}, \"scaleH\": {}, \"pages\": {}, \"packed\": 0, \"alphaChnl\": 0, \"redChnl\": 4, \"greenChnl\": 4, \"blueChnl\": 4}},",
+            self.line_height, self.base, self.page_dims.width(), self.page_dims.height(), self.pages.len()
+        ).unwrap();
+
+        out.push_str("  \"kernings\": [");
+        for (i, k) in self.kernings.iter().enumerate() {
+            out.push_str(if i == 0 {"\n"} else {",\n"});
+            write!(out, "    {{\"first\": {}, \"second\": {}, \"amount\": {}}}", k.first as u32, k.second as u32, k.amount).unwrap();
+        }
+        out.push_str(if self.kernings.is_empty() {"]\n}\n"} else {"\n  ]\n}\n"});
+        out
+    }
+}
+
+impl ShelfPacker {
+    fn new(page_dims: DimsBox<D2, u32>, spacing: u32) -> ShelfPacker {
+        ShelfPacker {
+            page_dims,
+            spacing,
+            pages: Vec::new(),
+            x: spacing,
+            y: spacing,
+            shelf_height: 0
+        }
+    }
+
+    /// Find a spot for a `width` by `height` image, returning its page and position.
+    fn place(&mut self, width: u32, height: u32) -> Option<(u32, u32, u32)> {
+        let (page_width, page_height) = (self.page_dims.width(), self.page_dims.height());
+        if width + self.spacing * 2 > page_width || height + self.spacing * 2 > page_height {
+            return None;
+        }
+
+        if self.x + width + self.spacing > page_width {
+            self.x = self.spacing;
+            self.y += self.shelf_height + self.spacing;
+            self.shelf_height = 0;
+        }
+        if self.pages.is_empty() || self.y + height + self.spacing > page_height {
+            self.pages.push(Image::new(self.page_dims, ImageFormat::Rgba8));
+            self.x = self.spacing;
+            self.y = self.spacing;
+            self.shelf_height = 0;
+        }
+
+        let position = (self.pages.len() as u32 - 1, self.x, self.y);
+        self.x += width + self.spacing;
+        self.shelf_height = self.shelf_height.max(height);
+        Some(position)
+    }
+
+    fn blit(&mut self, page: u32, x: u32, y: u32, image: &Image) {
+        let page = &mut self.pages[page as usize];
+        let row_bytes = image.pitch();
+        let x_byte = x as usize * 4;
+        for row in 0..image.dims.height() {
+            page.row_mut(y + row)[x_byte..x_byte + row_bytes].copy_from_slice(image.row(row));
+        }
+    }
+}
+
+/// The largest values that fit in the binary format's unsigned fields.
+const MAX_U8: u32 = 0xFF;
+const MAX_U16: u32 = 0xFFFF;
+
+#[inline]
+fn fits_u16(value: i32) -> bool {
+    0 <= value && value as u32 <= MAX_U16
+}
+
+#[inline]
+fn fits_i16(value: i32) -> bool {
+    i16::min_value() as i32 <= value && value <= i16::max_value() as i32
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+    out
+}
@@ -0,0 +1,481 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use {Face, FaceId, FaceSize, DPI, LoadFlags, RenderMode, Shaper, ShapeOptions, ShapedGlyph, FaceError};
+use image::GlyphImage;
+
+use std::mem;
+use std::collections::{HashMap, BTreeMap};
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct GlyphKey {
+    pub glyph_index: u32,
+    pub face_size: FaceSize,
+    pub dpi: DPI,
+    pub load_flags: LoadFlags,
+    pub render_mode: RenderMode
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64
+}
+
+/// A least-recently-used cache of rendered glyphs. Glyphs are cached separately for every face
+/// and every set of variation coordinates, so one cache can be shared between faces.
+pub struct GlyphCache {
+    entries: HashMap<CacheKey, CacheEntry>,
+    /// Maps the last time each entry was used onto that entry's key. The first entry is the least
+    /// recently used one.
+    lru: BTreeMap<u64, CacheKey>,
+    tick: u64,
+    memory_used: usize,
+    memory_budget: usize,
+    /// Holds the last rendered glyph when `memory_budget` is 0, since nothing gets cached.
+    uncached: Option<GlyphImage>,
+    stats: CacheStats
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct CacheKey {
+    face: FaceId,
+    variation_serial: u64,
+    glyph: GlyphKey
+}
+
+/// A bounded least-recently-used cache of shaping results, sitting in front of
+/// `Shaper::shape_text_with`.
+pub struct ShapeCache {
+    /// Entries bucketed by the hash of their key, so lookups can hash and compare the borrowed
+    /// text and options without copying them.
+    entries: HashMap<u64, Vec<ShapeEntry>>,
+    /// Maps the last time each entry was used onto the hash of that entry's key.
+    lru: BTreeMap<u64, u64>,
+    hasher: RandomState,
+    len: usize,
+    tick: u64,
+    capacity: usize,
+    /// Holds the last result when `capacity` is 0, since nothing gets cached.
+    uncached: Vec<ShapedGlyph>,
+    stats: CacheStats
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ShapeKey {
+    text: String,
+    face: FaceId,
+    variation_serial: u64,
+    face_size: FaceSize,
+    dpi: DPI,
+    options: ShapeOptions
+}
+
+/// A `ShapeKey` that borrows its text and options. Hashes the same as the equivalent `ShapeKey`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct ShapeKeyRef<'a> {
+    text: &'a str,
+    face: FaceId,
+    variation_serial: u64,
+    face_size: FaceSize,
+    dpi: DPI,
+    options: &'a ShapeOptions
+}
+
+struct ShapeEntry {
+    key: ShapeKey,
+    glyphs: Vec<ShapedGlyph>,
+    last_used: u64
+}
+
+struct CacheEntry {
+    image: GlyphImage,
+    last_used: u64,
+    size: usize
+}
+
+impl GlyphKey {
+    #[inline]
+    pub fn new(glyph_index: u32, face_size: FaceSize, dpi: DPI, load_flags: LoadFlags, render_mode: RenderMode) -> GlyphKey {
+        GlyphKey{ glyph_index, face_size, dpi, load_flags, render_mode }
+    }
+}
+
+impl CacheStats {
+    /// The fraction of lookups that were served from the cache.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            total => self.hits as f64 / total as f64
+        }
+    }
+}
+
+impl CacheKey {
+    #[inline]
+    fn new<B: ?Sized>(face: &Face<B>, glyph: GlyphKey) -> CacheKey {
+        CacheKey {
+            face: face.id(),
+            variation_serial: face.variation_serial(),
+            glyph
+        }
+    }
+}
+
+impl GlyphCache {
+    /// Create a cache that holds at most `memory_budget` bytes of glyph images. A cache with a
+    /// budget of 0 renders the glyph on every call.
+    pub fn new(memory_budget: usize) -> GlyphCache {
+        GlyphCache {
+            entries: HashMap::new(),
+            lru: BTreeMap::new(),
+            tick: 0,
+            memory_used: 0,
+            memory_budget,
+            uncached: None,
+            stats: CacheStats::default()
+        }
+    }
+
+    /// Retrieve the glyph described by `key`, loading and rendering it with `face` if it isn't
+    /// already in the cache.
+    pub fn get_or_render<B: ?Sized>(&mut self, face: &mut Face<B>, key: GlyphKey) -> Result<&GlyphImage, FaceError> {
+        let cache_key = CacheKey::new(face, key);
+        if self.entries.contains_key(&cache_key) {
+            self.stats.hits += 1;
+            self.touch(cache_key);
+        } else {
+            self.stats.misses += 1;
+            let image = face
+                .load_glyph(key.glyph_index, key.face_size, key.dpi, key.load_flags, key.render_mode)?
+                .render_image(key.render_mode)?;
+            if self.memory_budget == 0 {
+                return Ok(self.uncached.insert(image));
+            }
+            self.insert_entry(cache_key, image);
+        }
+
+        Ok(&self.entries[&cache_key].image)
+    }
+
+    /// Retrieve a glyph rendered with `face` from the cache without rendering it on a miss.
+    pub fn get<B: ?Sized>(&mut self, face: &Face<B>, key: GlyphKey) -> Option<&GlyphImage> {
+        let key = CacheKey::new(face, key);
+        if self.entries.contains_key(&key) {
+            self.stats.hits += 1;
+            self.touch(key);
+            Some(&self.entries[&key].image)
+        } else {
+            self.stats.misses += 1;
+            None
+        }
+    }
+
+    /// Insert a glyph rendered with `face` into the cache, evicting the least recently used glyphs
+    /// if the cache goes over budget. The inserted glyph is never evicted by its own insertion,
+    /// even if it alone is larger than the budget, unless the budget is 0.
+    pub fn insert<B: ?Sized>(&mut self, face: &Face<B>, key: GlyphKey, image: GlyphImage) {
+        if self.memory_budget != 0 {
+            self.insert_entry(CacheKey::new(face, key), image);
+        }
+    }
+
+    pub fn remove<B: ?Sized>(&mut self, face: &Face<B>, key: GlyphKey) -> Option<GlyphImage> {
+        self.remove_entry(CacheKey::new(face, key))
+    }
+
+    /// Remove every glyph that was rendered with `face`. Glyphs rendered before the face's
+    /// variation coordinates changed won't be used again, so this frees them early.
+    pub fn invalidate_face<B: ?Sized>(&mut self, face: &Face<B>) {
+        self.invalidate_face_id(face.id());
+    }
+
+    pub fn invalidate_face_id(&mut self, face: FaceId) {
+        let (lru, memory_used) = (&mut self.lru, &mut self.memory_used);
+        self.entries.retain(|key, entry| {
+            let keep = key.face != face;
+            if !keep {
+                lru.remove(&entry.last_used);
+                *memory_used -= entry.size;
+            }
+            keep
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.lru.clear();
+        self.memory_used = 0;
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The approximate number of bytes used by the cached glyphs.
+    #[inline]
+    pub fn memory_used(&self) -> usize {
+        self.memory_used
+    }
+
+    #[inline]
+    pub fn memory_budget(&self) -> usize {
+        self.memory_budget
+    }
+
+    /// Change the memory budget, evicting glyphs if the cache is now over budget.
+    pub fn set_memory_budget(&mut self, memory_budget: usize) {
+        self.memory_budget = memory_budget;
+        self.evict_to(memory_budget, 0);
+    }
+
+    #[inline]
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    #[inline]
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+
+    fn insert_entry(&mut self, key: CacheKey, image: GlyphImage) {
+        self.remove_entry(key);
+
+        self.tick += 1;
+        let size = mem::size_of::<GlyphImage>() + image.bitmap.buffer.len();
+        self.entries.insert(key, CacheEntry {
+            image,
+            last_used: self.tick,
+            size
+        });
+        self.lru.insert(self.tick, key);
+        self.memory_used += size;
+
+        self.evict_to(self.memory_budget, 1);
+    }
+
+    fn remove_entry(&mut self, key: CacheKey) -> Option<GlyphImage> {
+        let entry = self.entries.remove(&key)?;
+        self.lru.remove(&entry.last_used);
+        self.memory_used -= entry.size;
+        Some(entry.image)
+    }
+
+    fn touch(&mut self, key: CacheKey) {
+        self.tick += 1;
+        let entry = self.entries.get_mut(&key).unwrap();
+        self.lru.remove(&entry.last_used);
+        self.lru.insert(self.tick, key);
+        entry.last_used = self.tick;
+    }
+
+    /// Evict least recently used entries until the cache fits within `budget`, or until only
+    /// `keep` entries remain.
+    fn evict_to(&mut self, budget: usize, keep: usize) {
+        while self.memory_used > budget && self.entries.len() > keep {
+            let oldest = match self.lru.keys().next() {
+                Some(&tick) => tick,
+                None => break
+            };
+            let key = self.lru.remove(&oldest).unwrap();
+            let entry = self.entries.remove(&key).unwrap();
+            self.memory_used -= entry.size;
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+impl ShapeKey {
+    #[inline]
+    fn borrowed(&self) -> ShapeKeyRef {
+        ShapeKeyRef {
+            text: &self.text,
+            face: self.face,
+            variation_serial: self.variation_serial,
+            face_size: self.face_size,
+            dpi: self.dpi,
+            options: &self.options
+        }
+    }
+}
+
+impl<'a> ShapeKeyRef<'a> {
+    fn to_key(self) -> ShapeKey {
+        ShapeKey {
+            text: self.text.to_owned(),
+            face: self.face,
+            variation_serial: self.variation_serial,
+            face_size: self.face_size,
+            dpi: self.dpi,
+            options: self.options.clone()
+        }
+    }
+}
+
+impl ShapeCache {
+    /// Create a cache that holds the results of shaping at most `capacity` strings. A cache with
+    /// a capacity of 0 shapes the text on every call.
+    pub fn new(capacity: usize) -> ShapeCache {
+        ShapeCache {
+            entries: HashMap::new(),
+            lru: BTreeMap::new(),
+            hasher: RandomState::new(),
+            len: 0,
+            tick: 0,
+            capacity,
+            uncached: Vec::new(),
+            stats: CacheStats::default()
+        }
+    }
+
+    /// Retrieve the shaped glyphs for `text`, shaping it with `shaper` if it isn't already in the
+    /// cache.
+    pub fn shape<B: ?Sized>(
+        &mut self,
+        shaper: &mut Shaper,
+        text: &str,
+        face: &mut Face<B>,
+        face_size: FaceSize,
+        dpi: DPI,
+        options: &ShapeOptions
+    ) -> Result<&[ShapedGlyph], FaceError>
+    {
+        let key = ShapeKeyRef {
+            text,
+            face: face.id(),
+            variation_serial: face.variation_serial(),
+            face_size,
+            dpi,
+            options
+        };
+        let hash = self.hasher.hash_one(key);
+
+        self.tick += 1;
+        let found = self.entries.get(&hash).and_then(|bucket| bucket.iter().position(|entry| entry.key.borrowed() == key));
+        let index = match found {
+            Some(index) => {
+                self.stats.hits += 1;
+                let entry = &mut self.entries.get_mut(&hash).unwrap()[index];
+                self.lru.remove(&entry.last_used);
+                self.lru.insert(self.tick, hash);
+                entry.last_used = self.tick;
+                index
+            },
+            None => {
+                self.stats.misses += 1;
+                let glyphs = shaper.shape_text_with(text, face, face_size, dpi, options)?.collect();
+                if self.capacity == 0 {
+                    self.uncached = glyphs;
+                    return Ok(&self.uncached);
+                }
+
+                while self.len >= self.capacity {
+                    self.evict_oldest();
+                }
+                self.lru.insert(self.tick, hash);
+                self.len += 1;
+                let bucket = self.entries.entry(hash).or_default();
+                bucket.push(ShapeEntry {
+                    key: key.to_key(),
+                    glyphs,
+                    last_used: self.tick
+                });
+                bucket.len() - 1
+            }
+        };
+
+        Ok(&self.entries[&hash][index].glyphs)
+    }
+
+    /// Remove every cached result that was shaped with `face`. This should be called after
+    /// changing the face's variation coordinates, since those results won't be used again.
+    pub fn invalidate_face<B: ?Sized>(&mut self, face: &Face<B>) {
+        self.invalidate_face_id(face.id());
+    }
+
+    pub fn invalidate_face_id(&mut self, face: FaceId) {
+        let (lru, len) = (&mut self.lru, &mut self.len);
+        self.entries.retain(|_, bucket| {
+            bucket.retain(|entry| {
+                let keep = entry.key.face != face;
+                if !keep {
+                    lru.remove(&entry.last_used);
+                    *len -= 1;
+                }
+                keep
+            });
+            !bucket.is_empty()
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.lru.clear();
+        self.len = 0;
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    #[inline]
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    #[inline]
+    pub fn reset_stats(&mut self) {
+        self.stats = CacheStats::default();
+    }
+
+    fn evict_oldest(&mut self) {
+        let (tick, hash) = match self.lru.iter().next() {
+            Some((&tick, &hash)) => (tick, hash),
+            None => return
+        };
+        self.lru.remove(&tick);
+
+        let bucket_empty = {
+            let bucket = self.entries.get_mut(&hash).unwrap();
+            bucket.retain(|entry| entry.last_used != tick);
+            bucket.is_empty()
+        };
+        if bucket_empty {
+            self.entries.remove(&hash);
+        }
+        self.len -= 1;
+        self.stats.evictions += 1;
+    }
+}
@@ -0,0 +1,321 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use ft::FT_Error;
+
+use std::{fmt, error};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Error)]
+pub enum Error {
+    Ok,
+    CannotOpenResource,
+    UnknownFileFormat,
+    InvalidFileFormat,
+    InvalidVersion,
+    LowerModuleVersion,
+    InvalidArgument,
+    UnimplementedFeature,
+    InvalidTable,
+    InvalidOffset,
+    ArrayTooLarge,
+    MissingModule,
+    MissingProperty,
+    InvalidGlyphIndex,
+    InvalidCharacterCode,
+    InvalidGlyphFormat,
+    CannotRenderGlyph,
+    InvalidOutline,
+    InvalidComposite,
+    TooManyHints,
+    InvalidPixelSize,
+    InvalidHandle,
+    InvalidLibraryHandle,
+    InvalidDriverHandle,
+    InvalidFaceHandle,
+    InvalidSizeHandle,
+    InvalidSlotHandle,
+    InvalidCharMapHandle,
+    InvalidCacheHandle,
+    InvalidStreamHandle,
+
+    TooManyDrivers,
+    TooManyExtensions,
+
+    OutOfMemory,
+    UnlistedObject,
+
+    CannotOpenStream,
+    InvalidStreamSeek,
+    InvalidStreamSkip,
+    InvalidStreamRead,
+    InvalidStreamOperation,
+    InvalidFrameOperation,
+    NestedFrameAccess,
+    InvalidFrameRead,
+
+    RasterUninitialized,
+    RasterCorrupted,
+    RasterOverflow,
+    RasterNegativeHeight,
+
+    TooManyCaches,
+
+    InvalidOpcode,
+    TooFewArguments,
+    StackOverflow,
+    CodeOverflow,
+    BadArgument,
+    DivideByZero,
+    InvalidReference,
+    DebugOpCode,
+    ENDFInExecStream,
+    NestedDEFS,
+    InvalidCodeRange,
+    ExecutionTooLong,
+    TooManyFunctionDefs,
+    TooManyInstructionDefs,
+    TableMissing,
+    HorizHeaderMissing,
+    LocationsMissing,
+    NameTableMissing,
+    CMapTableMissing,
+    HmtxTableMissing,
+    PostTableMissing,
+    InvalidHorizMetrics,
+    InvalidCharMapFormat,
+    InvalidPPem,
+    InvalidVertMetrics,
+    CouldNotFindContext,
+    InvalidPostTableFormat,
+    InvalidPostTable,
+    DEFInGlyfBytecode,
+    MissingBitmap,
+    SyntaxError,
+    StackUnderflow,
+    Ignore,
+    NoUnicodeGlyphName,
+    GlyphTooBig,
+
+    MissingStartfontField,
+    MissingFontField,
+    MissingSizeField,
+    MissingFontboundingboxField,
+    MissingCharsField,
+    MissingStartcharField,
+    MissingEncodingField,
+    MissingBbxField,
+    BbxTooBig,
+    CorruptedFontHeader,
+    CorruptedFontGlyphs,
+    Max,
+
+    /// A FreeType error code this crate doesn't know about
+    #[error(no_from, non_std)]
+    Unknown(i32),
+    /// Harfbuzz failed to allocate memory
+    HbAllocationFailed,
+    /// Harfbuzz failed to shape text
+    ShapingFailed,
+    /// The library is shared with a face or another handle, so its properties can't be changed
+    LibraryInUse,
+}
+
+/// The operation that was being performed when a `FaceError` occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    OpenFace,
+    Resize,
+    SetVariations,
+    LoadGlyph,
+    GlyphAdvance,
+    Kerning,
+    RenderGlyph,
+    Shape,
+    BuildAtlas
+}
+
+/// An error that occurred while working with a face, along with what was being done when it
+/// occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FaceError {
+    pub operation: Operation,
+    /// The glyph being loaded or rendered, if any.
+    pub glyph_index: Option<u32>,
+    pub error: Error
+}
+
+impl Error {
+    /// Convert a FreeType error code into an `Error`. Codes this crate doesn't recognize, such as
+    /// ones added in newer FreeType versions, become `Error::Unknown`.
+    pub fn from_raw(err: FT_Error) -> Error {
+        match err.0 {
+            0 => Error::Ok,
+            1 => Error::CannotOpenResource,
+            2 => Error::UnknownFileFormat,
+            3 => Error::InvalidFileFormat,
+            4 => Error::InvalidVersion,
+            5 => Error::LowerModuleVersion,
+            6 => Error::InvalidArgument,
+            7 => Error::UnimplementedFeature,
+            8 => Error::InvalidTable,
+            9 => Error::InvalidOffset,
+            10 => Error::ArrayTooLarge,
+            11 => Error::MissingModule,
+            12 => Error::MissingProperty,
+            16 => Error::InvalidGlyphIndex,
+            17 => Error::InvalidCharacterCode,
+            18 => Error::InvalidGlyphFormat,
+            19 => Error::CannotRenderGlyph,
+            20 => Error::InvalidOutline,
+            21 => Error::InvalidComposite,
+            22 => Error::TooManyHints,
+            23 => Error::InvalidPixelSize,
+            32 => Error::InvalidHandle,
+            33 => Error::InvalidLibraryHandle,
+            34 => Error::InvalidDriverHandle,
+            35 => Error::InvalidFaceHandle,
+            36 => Error::InvalidSizeHandle,
+            37 => Error::InvalidSlotHandle,
+            38 => Error::InvalidCharMapHandle,
+            39 => Error::InvalidCacheHandle,
+            40 => Error::InvalidStreamHandle,
+            48 => Error::TooManyDrivers,
+            49 => Error::TooManyExtensions,
+            64 => Error::OutOfMemory,
+            65 => Error::UnlistedObject,
+            81 => Error::CannotOpenStream,
+            82 => Error::InvalidStreamSeek,
+            83 => Error::InvalidStreamSkip,
+            84 => Error::InvalidStreamRead,
+            85 => Error::InvalidStreamOperation,
+            86 => Error::InvalidFrameOperation,
+            87 => Error::NestedFrameAccess,
+            88 => Error::InvalidFrameRead,
+            96 => Error::RasterUninitialized,
+            97 => Error::RasterCorrupted,
+            98 => Error::RasterOverflow,
+            99 => Error::RasterNegativeHeight,
+            112 => Error::TooManyCaches,
+            128 => Error::InvalidOpcode,
+            129 => Error::TooFewArguments,
+            130 => Error::StackOverflow,
+            131 => Error::CodeOverflow,
+            132 => Error::BadArgument,
+            133 => Error::DivideByZero,
+            134 => Error::InvalidReference,
+            135 => Error::DebugOpCode,
+            136 => Error::ENDFInExecStream,
+            137 => Error::NestedDEFS,
+            138 => Error::InvalidCodeRange,
+            139 => Error::ExecutionTooLong,
+            140 => Error::TooManyFunctionDefs,
+            141 => Error::TooManyInstructionDefs,
+            142 => Error::TableMissing,
+            143 => Error::HorizHeaderMissing,
+            144 => Error::LocationsMissing,
+            145 => Error::NameTableMissing,
+            146 => Error::CMapTableMissing,
+            147 => Error::HmtxTableMissing,
+            148 => Error::PostTableMissing,
+            149 => Error::InvalidHorizMetrics,
+            150 => Error::InvalidCharMapFormat,
+            151 => Error::InvalidPPem,
+            152 => Error::InvalidVertMetrics,
+            153 => Error::CouldNotFindContext,
+            154 => Error::InvalidPostTableFormat,
+            155 => Error::InvalidPostTable,
+            156 => Error::DEFInGlyfBytecode,
+            157 => Error::MissingBitmap,
+            160 => Error::SyntaxError,
+            161 => Error::StackUnderflow,
+            162 => Error::Ignore,
+            163 => Error::NoUnicodeGlyphName,
+            164 => Error::GlyphTooBig,
+            176 => Error::MissingStartfontField,
+            177 => Error::MissingFontField,
+            178 => Error::MissingSizeField,
+            179 => Error::MissingFontboundingboxField,
+            180 => Error::MissingCharsField,
+            181 => Error::MissingStartcharField,
+            182 => Error::MissingEncodingField,
+            183 => Error::MissingBbxField,
+            184 => Error::BbxTooBig,
+            185 => Error::CorruptedFontHeader,
+            186 => Error::CorruptedFontGlyphs,
+            187 => Error::Max,
+            code => Error::Unknown(code as i32)
+        }
+    }
+
+    /// Convert a FreeType return value into a `Result`.
+    #[inline]
+    pub(crate) fn check(err: FT_Error) -> Result<(), Error> {
+        match err {
+            FT_Error(0) => Ok(()),
+            _ => Err(Error::from_raw(err))
+        }
+    }
+
+    #[inline]
+    pub(crate) fn during(self, operation: Operation) -> FaceError {
+        FaceError {
+            operation,
+            glyph_index: None,
+            error: self
+        }
+    }
+
+    #[inline]
+    pub(crate) fn during_glyph(self, operation: Operation, glyph_index: u32) -> FaceError {
+        FaceError {
+            operation,
+            glyph_index: Some(glyph_index),
+            error: self
+        }
+    }
+}
+
+impl fmt::Display for FaceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operation = match self.operation {
+            Operation::OpenFace => "open face",
+            Operation::Resize => "resize face",
+            Operation::SetVariations => "set variation coordinates",
+            Operation::LoadGlyph => "load glyph",
+            Operation::GlyphAdvance => "get advance of glyph",
+            Operation::Kerning => "get kerning for glyph",
+            Operation::RenderGlyph => "render glyph",
+            Operation::Shape => "shape text",
+            Operation::BuildAtlas => "build atlas"
+        };
+        write!(f, "failed to {}", operation)?;
+        if let Some(glyph_index) = self.glyph_index {
+            write!(f, " {}", glyph_index)?;
+        }
+        write!(f, ": {}", self.error)
+    }
+}
+
+impl error::Error for FaceError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<FaceError> for Error {
+    #[inline]
+    fn from(err: FaceError) -> Error {
+        err.error
+    }
+}
@@ -0,0 +1,175 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Declarations for FreeType and Harfbuzz functions that aren't exposed by the `-sys` crates.
+#![allow(non_camel_case_types, non_snake_case)]
+
+use ft::{FT_Face, FT_Library, FT_Error, FT_Fixed, FT_UInt, FT_ULong, FT_Long, FT_UShort, FT_Byte, FT_String, FT_CharMap};
+use harfbuzz_sys::{hb_font_t, hb_face_t, hb_tag_t, hb_bool_t};
+#[cfg(feature = "feature-names")]
+use harfbuzz_sys::{hb_codepoint_t, hb_language_t};
+
+use std::os::raw::{c_uint, c_float};
+#[cfg(feature = "feature-names")]
+use std::os::raw::c_char;
+
+pub const FT_VALIDATE_BASE: FT_UInt = 0x0100;
+pub const FT_VALIDATE_GDEF: FT_UInt = 0x0200;
+pub const FT_VALIDATE_GPOS: FT_UInt = 0x0400;
+pub const FT_VALIDATE_GSUB: FT_UInt = 0x0800;
+pub const FT_VALIDATE_JSTF: FT_UInt = 0x1000;
+pub const FT_VALIDATE_OT: FT_UInt = FT_VALIDATE_BASE | FT_VALIDATE_GDEF | FT_VALIDATE_GPOS | FT_VALIDATE_GSUB | FT_VALIDATE_JSTF;
+
+pub const FT_OPEN_STREAM: FT_UInt = 0x2;
+
+pub const HB_OT_LAYOUT_DEFAULT_LANGUAGE_INDEX: c_uint = 0xFFFF;
+#[cfg(feature = "feature-names")]
+pub const HB_OT_NAME_ID_INVALID: hb_ot_name_id_t = 0xFFFF;
+
+#[cfg(feature = "feature-names")]
+pub type hb_ot_name_id_t = c_uint;
+
+#[repr(C)]
+pub struct FT_Var_Axis {
+    pub name: *mut FT_String,
+    pub minimum: FT_Fixed,
+    pub def: FT_Fixed,
+    pub maximum: FT_Fixed,
+    pub tag: FT_ULong,
+    pub strid: FT_UInt
+}
+
+#[repr(C)]
+pub struct FT_Var_Named_Style {
+    pub coords: *mut FT_Fixed,
+    pub strid: FT_UInt,
+    pub psid: FT_UInt
+}
+
+#[repr(C)]
+pub struct FT_MM_Var {
+    pub num_axis: FT_UInt,
+    pub num_designs: FT_UInt,
+    pub num_namedstyles: FT_UInt,
+    pub axis: *mut FT_Var_Axis,
+    pub namedstyle: *mut FT_Var_Named_Style
+}
+
+#[repr(C)]
+pub struct FT_SfntName {
+    pub platform_id: FT_UShort,
+    pub encoding_id: FT_UShort,
+    pub language_id: FT_UShort,
+    pub name_id: FT_UShort,
+    pub string: *mut FT_Byte,
+    pub string_len: FT_UInt
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct hb_variation_t {
+    pub tag: hb_tag_t,
+    pub value: c_float
+}
+
+extern "C" {
+    pub fn FT_Get_MM_Var(face: FT_Face, amaster: *mut *mut FT_MM_Var) -> FT_Error;
+    pub fn FT_Done_MM_Var(library: FT_Library, amaster: *mut FT_MM_Var) -> FT_Error;
+    pub fn FT_Set_Var_Design_Coordinates(face: FT_Face, num_coords: FT_UInt, coords: *mut FT_Fixed) -> FT_Error;
+    pub fn FT_OpenType_Validate(
+        face: FT_Face,
+        validation_flags: FT_UInt,
+        BASE_table: *mut *const u8,
+        GDEF_table: *mut *const u8,
+        GPOS_table: *mut *const u8,
+        GSUB_table: *mut *const u8,
+        JSTF_table: *mut *const u8
+    ) -> FT_Error;
+    pub fn FT_OpenType_Free(face: FT_Face, table: *const u8);
+    pub fn FT_Get_Sfnt_Name_Count(face: FT_Face) -> FT_UInt;
+    pub fn FT_Get_Sfnt_Name(face: FT_Face, idx: FT_UInt, aname: *mut FT_SfntName) -> FT_Error;
+    pub fn FT_Get_CMap_Format(charmap: FT_CharMap) -> FT_Long;
+
+    pub fn hb_font_set_variations(font: *mut hb_font_t, variations: *const hb_variation_t, variations_length: c_uint);
+
+    pub fn hb_ot_layout_table_get_script_tags(
+        face: *mut hb_face_t,
+        table_tag: hb_tag_t,
+        start_offset: c_uint,
+        script_count: *mut c_uint,
+        script_tags: *mut hb_tag_t
+    ) -> c_uint;
+    pub fn hb_ot_layout_script_get_language_tags(
+        face: *mut hb_face_t,
+        table_tag: hb_tag_t,
+        script_index: c_uint,
+        start_offset: c_uint,
+        language_count: *mut c_uint,
+        language_tags: *mut hb_tag_t
+    ) -> c_uint;
+    pub fn hb_ot_layout_table_get_feature_tags(
+        face: *mut hb_face_t,
+        table_tag: hb_tag_t,
+        start_offset: c_uint,
+        feature_count: *mut c_uint,
+        feature_tags: *mut hb_tag_t
+    ) -> c_uint;
+    pub fn hb_ot_layout_language_get_feature_tags(
+        face: *mut hb_face_t,
+        table_tag: hb_tag_t,
+        script_index: c_uint,
+        language_index: c_uint,
+        start_offset: c_uint,
+        feature_count: *mut c_uint,
+        feature_tags: *mut hb_tag_t
+    ) -> c_uint;
+    pub fn hb_ot_layout_language_get_required_feature(
+        face: *mut hb_face_t,
+        table_tag: hb_tag_t,
+        script_index: c_uint,
+        language_index: c_uint,
+        feature_index: *mut c_uint,
+        feature_tag: *mut hb_tag_t
+    ) -> hb_bool_t;
+}
+
+// Added in Harfbuzz 2.0 and 2.1, which are newer than the copy harfbuzz-sys bundles.
+#[cfg(feature = "feature-names")]
+extern "C" {
+    pub fn hb_ot_layout_feature_get_name_ids(
+        face: *mut hb_face_t,
+        table_tag: hb_tag_t,
+        feature_index: c_uint,
+        label_id: *mut hb_ot_name_id_t,
+        tooltip_id: *mut hb_ot_name_id_t,
+        sample_id: *mut hb_ot_name_id_t,
+        num_named_parameters: *mut c_uint,
+        first_param_id: *mut hb_ot_name_id_t
+    ) -> hb_bool_t;
+    pub fn hb_ot_layout_feature_get_characters(
+        face: *mut hb_face_t,
+        table_tag: hb_tag_t,
+        feature_index: c_uint,
+        start_offset: c_uint,
+        char_count: *mut c_uint,
+        characters: *mut hb_codepoint_t
+    ) -> c_uint;
+    pub fn hb_ot_name_get_utf8(
+        face: *mut hb_face_t,
+        name_id: hb_ot_name_id_t,
+        language: hb_language_t,
+        text_size: *mut c_uint,
+        text: *mut c_char
+    ) -> c_uint;
+}
@@ -14,26 +14,191 @@
 
 use ft::{FT_Memory, FT_MemoryRec_};
 
+use std::alloc::{GlobalAlloc, Layout};
 use std::os::raw::{c_void, c_long};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use libc;
 
-pub fn alloc_mem_rec() -> *mut FT_MemoryRec_ {
+/// Every allocation is prefixed with a header recording its size, so that frees can be accounted
+/// for. The header is large enough to keep the returned pointer aligned for any type.
+const HEADER_SIZE: usize = 16;
+const ALIGN: usize = 16;
+
+pub struct AllocState {
+    live: AtomicUsize,
+    peak: AtomicUsize,
+    /// `usize::max_value()` if there's no limit.
+    limit: AtomicUsize,
+    allocator: Option<&'static (dyn GlobalAlloc + Sync)>
+}
+
+impl AllocState {
+    pub fn new(limit: Option<usize>, allocator: Option<&'static (dyn GlobalAlloc + Sync)>) -> AllocState {
+        AllocState {
+            live: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            limit: AtomicUsize::new(limit.unwrap_or(usize::max_value())),
+            allocator
+        }
+    }
+
+    #[inline]
+    pub fn live(&self) -> usize {
+        self.live.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn reset_peak(&self) {
+        self.peak.store(self.live(), Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn limit(&self) -> Option<usize> {
+        match self.limit.load(Ordering::Relaxed) {
+            l if l == usize::max_value() => None,
+            l => Some(l)
+        }
+    }
+
+    #[inline]
+    pub fn set_limit(&self, limit: Option<usize>) {
+        self.limit.store(limit.unwrap_or(usize::max_value()), Ordering::Relaxed);
+    }
+
+    /// Account for `size` more live bytes, failing if that would exceed the limit.
+    fn reserve(&self, size: usize) -> bool {
+        let limit = self.limit.load(Ordering::Relaxed);
+        let reserved = self.live.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |live| {
+            live.checked_add(size).filter(|&live| live <= limit)
+        });
+        match reserved {
+            Ok(live) => {
+                self.peak.fetch_max(live + size, Ordering::Relaxed);
+                true
+            },
+            Err(_) => false
+        }
+    }
+
+    #[inline]
+    fn release(&self, size: usize) {
+        self.live.fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+pub fn alloc_mem_rec(state: Arc<AllocState>) -> *mut FT_MemoryRec_ {
     Box::into_raw(Box::new(FT_MemoryRec_ {
-        user: 0 as *mut c_void,
+        user: Arc::into_raw(state) as *mut c_void,
         alloc: Some(ft_alloc),
         free: Some(ft_free),
         realloc: Some(ft_realloc)
     }))
 }
 
-unsafe extern "C" fn ft_alloc(_: FT_Memory, size: c_long) -> *mut c_void {
-    libc::malloc(size as usize) as *mut c_void
+/// Free a memory record created by `alloc_mem_rec`. Must only be called once the library using
+/// the record has been destroyed.
+pub unsafe fn free_mem_rec(memory: *mut FT_MemoryRec_) {
+    let memory = Box::from_raw(memory);
+    Arc::from_raw(memory.user as *const AllocState);
+}
+
+#[inline]
+unsafe fn state<'a>(memory: FT_Memory) -> &'a AllocState {
+    &*((*memory).user as *const AllocState)
 }
 
-unsafe extern "C" fn ft_free(_: FT_Memory, ptr: *mut c_void) {
-    libc::free(ptr as *mut _);
+/// The layout of a block holding `size` bytes after its header, or `None` if that's too large.
+#[inline]
+fn layout(size: usize) -> Option<Layout> {
+    size.checked_add(HEADER_SIZE).and_then(|size| Layout::from_size_align(size, ALIGN).ok())
 }
 
-unsafe extern "C" fn ft_realloc(_: FT_Memory, _: c_long, new_size: c_long, block: *mut c_void) -> *mut c_void {
-    libc::realloc(block as *mut _, new_size as usize) as *mut c_void
+unsafe extern "C" fn ft_alloc(memory: FT_Memory, size: c_long) -> *mut c_void {
+    let state = state(memory);
+    if size < 0 {
+        return 0 as *mut c_void;
+    }
+    let size = size as usize;
+    let layout = match layout(size) {
+        Some(layout) => layout,
+        None => return 0 as *mut c_void
+    };
+    if !state.reserve(size) {
+        return 0 as *mut c_void;
+    }
+
+    let block = match state.allocator {
+        Some(allocator) => allocator.alloc(layout),
+        None => libc::malloc(layout.size()) as *mut u8
+    };
+    if block.is_null() {
+        state.release(size);
+        return 0 as *mut c_void;
+    }
+
+    *(block as *mut usize) = size;
+    block.offset(HEADER_SIZE as isize) as *mut c_void
+}
+
+unsafe extern "C" fn ft_free(memory: FT_Memory, ptr: *mut c_void) {
+    if ptr.is_null() {
+        return;
+    }
+
+    let state = state(memory);
+    let block = (ptr as *mut u8).offset(-(HEADER_SIZE as isize));
+    let size = *(block as *const usize);
+    state.release(size);
+
+    match (state.allocator, layout(size)) {
+        (Some(allocator), Some(layout)) => allocator.dealloc(block, layout),
+        // The size was checked when the block was allocated, so this can't happen.
+        (Some(_), None) => (),
+        (None, _) => libc::free(block as *mut _)
+    }
+}
+
+unsafe extern "C" fn ft_realloc(memory: FT_Memory, _: c_long, new_size: c_long, ptr: *mut c_void) -> *mut c_void {
+    if ptr.is_null() {
+        return ft_alloc(memory, new_size);
+    }
+
+    let state = state(memory);
+    if new_size < 0 {
+        return 0 as *mut c_void;
+    }
+    let new_size = new_size as usize;
+    let block = (ptr as *mut u8).offset(-(HEADER_SIZE as isize));
+    let old_size = *(block as *const usize);
+
+    let (old_layout, new_layout) = match (layout(old_size), layout(new_size)) {
+        (Some(old_layout), Some(new_layout)) => (old_layout, new_layout),
+        _ => return 0 as *mut c_void
+    };
+    if new_size > old_size && !state.reserve(new_size - old_size) {
+        return 0 as *mut c_void;
+    }
+
+    let new_block = match state.allocator {
+        Some(allocator) => allocator.realloc(block, old_layout, new_layout.size()),
+        None => libc::realloc(block as *mut _, new_layout.size()) as *mut u8
+    };
+    if new_block.is_null() {
+        if new_size > old_size {
+            state.release(new_size - old_size);
+        }
+        return 0 as *mut c_void;
+    }
+
+    if new_size < old_size {
+        state.release(old_size - new_size);
+    }
+    *(new_block as *mut usize) = new_size;
+    new_block.offset(HEADER_SIZE as isize) as *mut c_void
 }
@@ -0,0 +1,270 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use {SubpixelOrder, Error};
+use render::Rgba;
+
+/// Adjustments applied to glyph coverage before it gets blended.
+///
+/// `contrast` boosts partial coverage along the glyph's edges, making stems look heavier.
+/// `gamma` thins coverage for dark text and thickens it for light text, compensating for the way
+/// sRGB blending makes dark-on-light text look heavy and light-on-dark text look thin. A `gamma`
+/// of `1.0` and `contrast` of `0.0` leave coverage untouched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoverageAdjust {
+    gamma: f32,
+    contrast: f32
+}
+
+/// A precomputed lookup table mapping raw coverage values to adjusted coverage values, for a
+/// specific text luminance.
+#[derive(Clone)]
+pub struct CoverageLut {
+    table: [u8; 256]
+}
+
+/// The color space text is blended in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlendSpace {
+    /// Blend the sRGB-encoded values directly. Cheap, and how most software renders text.
+    Srgb,
+    /// Convert colors into linear light before blending, and back into sRGB afterwards.
+    Linear
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BlendOptions {
+    pub coverage: CoverageAdjust,
+    pub space: BlendSpace,
+    /// The subpixel layout of the display, used when compositing `Lcd` and `LcdV` glyphs into
+    /// `Rgba8` images.
+    pub subpixel_order: SubpixelOrder
+}
+
+impl CoverageAdjust {
+    pub const NONE: CoverageAdjust = CoverageAdjust{ gamma: 1.0, contrast: 0.0 };
+
+    /// Create a coverage adjustment. Returns `Error::InvalidArgument` unless `gamma` is positive
+    /// and `contrast` is greater than `-1.0`, since other values produce NaN or negative coverage.
+    pub fn new(gamma: f32, contrast: f32) -> Result<CoverageAdjust, Error> {
+        match gamma > 0.0 && gamma.is_finite() && contrast > -1.0 && contrast.is_finite() {
+            true => Ok(CoverageAdjust{ gamma, contrast }),
+            false => Err(Error::InvalidArgument)
+        }
+    }
+
+    #[inline]
+    pub fn gamma(&self) -> f32 {
+        self.gamma
+    }
+
+    #[inline]
+    pub fn contrast(&self) -> f32 {
+        self.contrast
+    }
+
+    /// Adjust a single coverage value, given in the range `0.0..=1.0`. `text_luminance` is the
+    /// relative luminance of the text color, also in `0.0..=1.0`.
+    pub fn adjust(&self, coverage: f32, text_luminance: f32) -> f32 {
+        let coverage = coverage.clamp(0.0, 1.0);
+        let c = self.contrast;
+        let contrasted = match c == 0.0 {
+            true => coverage,
+            false => coverage * (c + 1.0) / (coverage * c + 1.0)
+        };
+
+        // Interpolate the exponent between `gamma` for black text and `1 / gamma` for white text.
+        let exponent = self.gamma.powf(1.0 - 2.0 * text_luminance.clamp(0.0, 1.0));
+        contrasted.powf(exponent)
+    }
+
+    /// Build a lookup table for adjusting coverage of text drawn with the given color.
+    pub fn lut(&self, text_color: Rgba) -> CoverageLut {
+        let mut table = [0; 256];
+        let luminance = text_color.luminance();
+        if *self == CoverageAdjust::NONE {
+            for (i, t) in table.iter_mut().enumerate() {
+                *t = i as u8;
+            }
+        } else {
+            for (i, t) in table.iter_mut().enumerate() {
+                *t = (self.adjust(i as f32 / 255.0, luminance) * 255.0 + 0.5) as u8;
+            }
+        }
+        CoverageLut{ table }
+    }
+}
+
+impl CoverageLut {
+    #[inline]
+    pub fn get(&self, coverage: u8) -> u8 {
+        self.table[coverage as usize]
+    }
+
+    /// Adjust every coverage value in `coverage` in-place. Useful for `Gray` bitmaps returned from
+    /// `GlyphSlot::render_glyph`.
+    pub fn apply(&self, coverage: &mut [u8]) {
+        for c in coverage {
+            *c = self.table[*c as usize];
+        }
+    }
+}
+
+impl BlendOptions {
+    /// Unadjusted coverage, blended in sRGB space.
+    pub const NAIVE: BlendOptions = BlendOptions {
+        coverage: CoverageAdjust::NONE,
+        space: BlendSpace::Srgb,
+        subpixel_order: SubpixelOrder::Rgb
+    };
+    /// The blending recommended by the documentation of FreeType's `FT_Render_Glyph`: unadjusted
+    /// coverage, blended in linear space.
+    pub const FREETYPE: BlendOptions = BlendOptions {
+        coverage: CoverageAdjust::NONE,
+        space: BlendSpace::Linear,
+        subpixel_order: SubpixelOrder::Rgb
+    };
+}
+
+impl Default for CoverageAdjust {
+    #[inline]
+    fn default() -> CoverageAdjust {
+        CoverageAdjust::NONE
+    }
+}
+
+impl Default for BlendOptions {
+    #[inline]
+    fn default() -> BlendOptions {
+        BlendOptions::NAIVE
+    }
+}
+
+impl Rgba {
+    /// The relative luminance of the color, in the range `0.0..=1.0`.
+    pub fn luminance(&self) -> f32 {
+        0.2126 * srgb_to_linear(self.r) + 0.7152 * srgb_to_linear(self.g) + 0.0722 * srgb_to_linear(self.b)
+    }
+}
+
+lazy_static!{
+    static ref SRGB_TO_LINEAR: [f32; 256] = {
+        let mut table = [0.0; 256];
+        for (i, t) in table.iter_mut().enumerate() {
+            let c = i as f32 / 255.0;
+            *t = match c <= 0.04045 {
+                true => c / 12.92,
+                false => ((c + 0.055) / 1.055).powf(2.4)
+            };
+        }
+        table
+    };
+    static ref LINEAR_TO_SRGB: [u8; 4096] = {
+        let mut table = [0; 4096];
+        for (i, t) in table.iter_mut().enumerate() {
+            let c = i as f32 / 4095.0;
+            let srgb = match c <= 0.0031308 {
+                true => c * 12.92,
+                false => 1.055 * c.powf(1.0 / 2.4) - 0.055
+            };
+            *t = (srgb * 255.0 + 0.5) as u8;
+        }
+        table
+    };
+}
+
+#[inline]
+pub(crate) fn srgb_to_linear(c: u8) -> f32 {
+    SRGB_TO_LINEAR[c as usize]
+}
+
+#[inline]
+pub(crate) fn linear_to_srgb(c: f32) -> u8 {
+    LINEAR_TO_SRGB[(c.clamp(0.0, 1.0) * 4095.0 + 0.5) as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(expected: f32, actual: f32) {
+        assert!((expected - actual).abs() < 1e-4, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn reject_invalid() {
+        assert_eq!(Ok(CoverageAdjust::NONE), CoverageAdjust::new(1.0, 0.0));
+        assert!(CoverageAdjust::new(0.5, -0.5).is_ok());
+        for &(gamma, contrast) in &[(0.0, 0.0), (-1.0, 0.0), (f32::NAN, 0.0), (f32::INFINITY, 0.0), (1.0, -1.0), (1.0, f32::NAN)] {
+            assert_eq!(Err(Error::InvalidArgument), CoverageAdjust::new(gamma, contrast), "gamma {}, contrast {}", gamma, contrast);
+        }
+    }
+
+    #[test]
+    fn adjust() {
+        let none = CoverageAdjust::NONE;
+        for &coverage in &[0.0, 0.25, 0.5, 1.0] {
+            assert_close(coverage, none.adjust(coverage, 0.0));
+            assert_close(coverage, none.adjust(coverage, 1.0));
+        }
+        assert_close(0.0, none.adjust(-1.0, 0.0));
+        assert_close(1.0, none.adjust(2.0, 0.0));
+
+        // Gamma thins dark text, thickens light text and leaves mid-gray text alone.
+        let gamma = CoverageAdjust::new(2.0, 0.0).unwrap();
+        assert_close(0.25, gamma.adjust(0.5, 0.0));
+        assert_close(0.5f32.sqrt(), gamma.adjust(0.5, 1.0));
+        assert_close(0.5, gamma.adjust(0.5, 0.5));
+
+        // Contrast boosts partial coverage, but keeps the ends in place.
+        let contrast = CoverageAdjust::new(1.0, 1.0).unwrap();
+        assert_close(2.0 / 3.0, contrast.adjust(0.5, 0.0));
+        assert_close(0.0, contrast.adjust(0.0, 0.0));
+        assert_close(1.0, contrast.adjust(1.0, 0.0));
+    }
+
+    #[test]
+    fn lut() {
+        let (black, white) = (Rgba::new(0, 0, 0, 255), Rgba::new(255, 255, 255, 255));
+        let identity = CoverageAdjust::NONE.lut(black);
+        for c in 0..=255 {
+            assert_eq!(c, identity.get(c));
+        }
+
+        let adjust = CoverageAdjust::new(1.8, 0.5).unwrap();
+        for &color in &[black, white] {
+            let lut = adjust.lut(color);
+            assert_eq!((0, 255), (lut.get(0), lut.get(255)));
+            for c in 0..=255u8 {
+                assert_eq!((adjust.adjust(c as f32 / 255.0, color.luminance()) * 255.0).round() as u8, lut.get(c));
+                if c > 0 {
+                    assert!(lut.get(c - 1) <= lut.get(c));
+                }
+            }
+        }
+        assert!(adjust.lut(black).get(128) < adjust.lut(white).get(128));
+
+        let mut coverage = [0, 64, 128, 255];
+        adjust.lut(black).apply(&mut coverage);
+        assert_eq!([0, adjust.lut(black).get(64), adjust.lut(black).get(128), 255], coverage);
+    }
+
+    #[test]
+    fn presets() {
+        assert_eq!(BlendOptions::NAIVE, BlendOptions::default());
+        assert_eq!(CoverageAdjust::NONE, CoverageAdjust::default());
+        assert_eq!((CoverageAdjust::NONE, BlendSpace::Srgb), (BlendOptions::NAIVE.coverage, BlendOptions::NAIVE.space));
+        assert_eq!((CoverageAdjust::NONE, BlendSpace::Linear), (BlendOptions::FREETYPE.coverage, BlendOptions::FREETYPE.space));
+    }
+}
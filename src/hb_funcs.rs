@@ -12,11 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use GLYPH_NAME_BUF_LEN;
 use ft::*;
 use harfbuzz_sys::*;
 
 use std::os::raw::{c_void, c_uint, c_int, c_char};
-use std::{ptr, mem};
+use std::{ptr, slice};
+use std::ffi::{CStr, CString};
 
 pub unsafe fn set_for_font(hb_font: *mut hb_font_t, ft_face: FT_Face) {
     hb_font_set_funcs(
@@ -52,7 +54,7 @@ lazy_static!{
         hb_font_funcs_set_glyph_extents_func(funcs, Some(get_extents), ptr::null_mut(), None);
         hb_font_funcs_set_glyph_contour_point_func(funcs, Some(get_contour_point), ptr::null_mut(), None);
         hb_font_funcs_set_glyph_name_func(funcs, Some(get_glyph_name), ptr::null_mut(), None);
-        // hb_font_funcs_set_glyph_from_name_func(funcs, None, ptr::null_mut(), None);
+        hb_font_funcs_set_glyph_from_name_func(funcs, Some(get_glyph_from_name), ptr::null_mut(), None);
 
         hb_font_funcs_make_immutable(funcs);
         FontFuncsWrapper(funcs)
@@ -221,7 +223,7 @@ unsafe extern "C" fn get_h_kerning(
 {
     let ffd = &*(font_data as *const FontFuncData);
 
-    let mut kerningv = mem::uninitialized();
+    let mut kerningv = FT_Vector{ x: 0, y: 0 };
     let mode = match (*(*ffd.ft_face).size).metrics.x_ppem {
         0 => FT_Kerning_Mode__FT_KERNING_UNFITTED,
         _ => FT_Kerning_Mode__FT_KERNING_DEFAULT
@@ -308,8 +310,42 @@ unsafe extern "C" fn get_glyph_name(
     let ffd = &*(font_data as *const FontFuncData);
 
     let mut ret = FT_Error(0) == FT_Get_Glyph_Name(ffd.ft_face, glyph, name as *mut _, size);
-    if ret && (size != 0 && *name != 0) {
+    if ret && (size != 0 && *name == 0) {
         ret = false;
     }
     ret as hb_bool_t
 }
+
+unsafe extern "C" fn get_glyph_from_name(
+    _: *mut hb_font_t,
+    font_data: *mut c_void,
+    name: *const c_char,
+    len: c_int,
+    glyph: *mut hb_codepoint_t,
+    _: *mut c_void
+) -> hb_bool_t
+{
+    let ffd = &*(font_data as *const FontFuncData);
+
+    // `name` isn't null-terminated if `len` is non-negative.
+    let name = match len {
+        len if len < 0 => CStr::from_ptr(name).to_bytes(),
+        len => slice::from_raw_parts(name as *const u8, len as usize)
+    };
+    let name_c = match CString::new(name) {
+        Ok(name_c) => name_c,
+        Err(_) => return 0
+    };
+    *glyph = FT_Get_Name_Index(ffd.ft_face, name_c.as_ptr() as *mut _);
+
+    if *glyph == 0 {
+        // Check whether the given name was actually the name of glyph 0.
+        let mut buf = [0u8; GLYPH_NAME_BUF_LEN];
+        if FT_Error(0) == FT_Get_Glyph_Name(ffd.ft_face, 0, buf.as_mut_ptr() as *mut _, buf.len() as FT_UInt) {
+            let buf_len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+            return (&buf[..buf_len] == name) as hb_bool_t;
+        }
+    }
+
+    (*glyph != 0) as hb_bool_t
+}
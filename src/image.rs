@@ -0,0 +1,591 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use {Bitmap, PixelMode, SubpixelOrder, GlyphSlot, GlyphMetrics266, RenderMode, FaceError};
+use render::{ImageFormat, RenderTarget};
+
+use cgmath::Vector2;
+use cgmath_geometry::D2;
+use cgmath_geometry::rect::{DimsBox, GeoBox};
+
+/// An owned, tightly packed image with rows stored top-down.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Image {
+    pub dims: DimsBox<D2, u32>,
+    pub format: ImageFormat,
+    pub data: Vec<u8>
+}
+
+/// A copy of a glyph bitmap, which unlike `Bitmap` doesn't borrow FreeType's glyph slot.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OwnedBitmap {
+    pub dims: DimsBox<D2, u32>,
+    pub pitch: i32,
+    pub buffer: Vec<u8>,
+    pub pixel_mode: PixelMode
+}
+
+/// A rendered glyph, along with the metrics needed to place it. Owns all of its data, so it can be
+/// cached and sent across threads.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GlyphImage {
+    pub bitmap: OwnedBitmap,
+    /// The offset from the pen position to the top-left corner of the bitmap, in pixels. The Y
+    /// axis points up.
+    pub bitmap_offset: Vector2<i32>,
+    pub metrics: GlyphMetrics266,
+    /// The glyph's transformed advance, in 26.6 format.
+    pub advance: Vector2<i32>
+}
+
+impl OwnedBitmap {
+    #[inline]
+    pub fn as_bitmap(&self) -> Bitmap {
+        Bitmap {
+            dims: self.dims,
+            pitch: self.pitch,
+            buffer: &self.buffer,
+            pixel_mode: self.pixel_mode
+        }
+    }
+}
+
+impl OwnedBitmap {
+    /// Resize the bitmap by `scale`, averaging the pixels each new pixel covers.
+    ///
+    /// `Bgra` bitmaps stay `Bgra`. Every other pixel mode gets converted to `Gray` first, so
+    /// subpixel bitmaps lose their subpixel information.
+    pub fn scaled(&self, scale: f32) -> OwnedBitmap {
+        let bitmap = self.as_bitmap();
+        let src_dims = bitmap.pixel_dims();
+        let (src, channels, pixel_mode) = match self.pixel_mode {
+            PixelMode::Bgra => {
+                let mut src = Vec::with_capacity(src_dims.width() as usize * src_dims.height() as usize * 4);
+                for y in 0..src_dims.height() {
+                    src.extend_from_slice(&bitmap.row(y)[..src_dims.width() as usize * 4]);
+                }
+                (src, 4, PixelMode::Bgra)
+            },
+            _ => (bitmap.to_a8().data, 1, PixelMode::Gray)
+        };
+
+        let scale_len = |len: u32| match len {
+            0 => 0,
+            _ => ((len as f32 * scale).round() as u32).max(1)
+        };
+        let dims = DimsBox::new2(scale_len(src_dims.width()), scale_len(src_dims.height()));
+        OwnedBitmap {
+            dims,
+            pitch: (dims.width() * channels) as i32,
+            buffer: resample(&src, src_dims, dims, channels as usize),
+            pixel_mode
+        }
+    }
+}
+
+impl<'a> From<Bitmap<'a>> for OwnedBitmap {
+    #[inline]
+    fn from(bitmap: Bitmap<'a>) -> OwnedBitmap {
+        bitmap.into_owned()
+    }
+}
+
+impl<'a> GlyphSlot<'a> {
+    /// Render the glyph and copy the result out of the glyph slot, so that it stays valid after the
+    /// next call to `load_glyph`.
+    pub fn render_image(&mut self, render_mode: RenderMode) -> Result<GlyphImage, FaceError> {
+        let bitmap = self.render_glyph(render_mode)?.into_owned();
+        let image = GlyphImage {
+            bitmap,
+            bitmap_offset: self.bitmap_offset(),
+            metrics: self.unscaled_metrics(),
+            advance: self.unscaled_advance()
+        };
+        match self.bitmap_scale() {
+            scale if scale != 1.0 => Ok(image.scaled(scale)),
+            _ => Ok(image)
+        }
+    }
+}
+
+impl GlyphImage {
+    /// Resize the glyph by `scale`, scaling its bitmap with `OwnedBitmap::scaled` and its metrics
+    /// to match.
+    pub fn scaled(&self, scale: f32) -> GlyphImage {
+        let scale_i32 = |value: i32| (value as f32 * scale).round() as i32;
+        let scale_vector = |v: Vector2<i32>| Vector2::new(scale_i32(v.x), scale_i32(v.y));
+        GlyphImage {
+            bitmap: self.bitmap.scaled(scale),
+            bitmap_offset: scale_vector(self.bitmap_offset),
+            metrics: self.metrics.scaled(scale),
+            advance: scale_vector(self.advance)
+        }
+    }
+}
+
+impl Image {
+    /// Create a zero-filled image.
+    pub fn new(dims: DimsBox<D2, u32>, format: ImageFormat) -> Image {
+        Image {
+            dims,
+            format,
+            data: vec![0; dims.width() as usize * dims.height() as usize * format.bytes_per_pixel()]
+        }
+    }
+
+    #[inline]
+    pub fn pitch(&self) -> usize {
+        self.dims.width() as usize * self.format.bytes_per_pixel()
+    }
+
+    #[inline]
+    pub fn row(&self, y: u32) -> &[u8] {
+        let pitch = self.pitch();
+        &self.data[y as usize * pitch..(y as usize + 1) * pitch]
+    }
+
+    #[inline]
+    pub fn row_mut(&mut self, y: u32) -> &mut [u8] {
+        let pitch = self.pitch();
+        &mut self.data[y as usize * pitch..(y as usize + 1) * pitch]
+    }
+
+    /// Convert the image into another format. Converting `Rgba8` to `A8` keeps the alpha channel,
+    /// and converting `A8` to `Rgba8` produces white pixels with the original values as alpha.
+    pub fn convert(&self, format: ImageFormat) -> Image {
+        if format == self.format {
+            return self.clone();
+        }
+
+        let mut image = Image::new(self.dims, format);
+        for (src, dst) in self.data.chunks(self.format.bytes_per_pixel()).zip(image.data.chunks_mut(format.bytes_per_pixel())) {
+            match format {
+                ImageFormat::A8 => dst[0] = src[3],
+                ImageFormat::Rgba8 => {
+                    dst[0] = 255;
+                    dst[1] = 255;
+                    dst[2] = 255;
+                    dst[3] = src[0];
+                }
+            }
+        }
+        image
+    }
+
+    /// Borrow the image as a target for `render_text_to_buffer`.
+    #[inline]
+    pub fn as_render_target(&mut self) -> RenderTarget {
+        RenderTarget::new(&mut self.data, self.dims, self.format)
+    }
+}
+
+impl<'a> Bitmap<'a> {
+    /// Copy the bitmap's contents out of the glyph slot.
+    #[inline]
+    pub fn into_owned(self) -> OwnedBitmap {
+        OwnedBitmap {
+            dims: self.dims,
+            pitch: self.pitch,
+            buffer: self.buffer.to_vec(),
+            pixel_mode: self.pixel_mode
+        }
+    }
+
+    /// Convert the bitmap into an owned image in the given format.
+    #[inline]
+    pub fn to_image(&self, format: ImageFormat) -> Image {
+        match format {
+            ImageFormat::A8 => self.to_a8(),
+            ImageFormat::Rgba8 => self.to_rgba8()
+        }
+    }
+
+    /// Convert the bitmap into an `A8` coverage image.
+    ///
+    /// Subpixel bitmaps are collapsed into one value per pixel by averaging their subpixels, and
+    /// color bitmaps are reduced to their alpha channel.
+    pub fn to_a8(&self) -> Image {
+        let dims = self.pixel_dims();
+        let mut image = Image::new(dims, ImageFormat::A8);
+
+        for y in 0..dims.height() {
+            let dst_row = image.row_mut(y);
+            match self.pixel_mode {
+                PixelMode::Gray => dst_row.copy_from_slice(&self.row(y)[..dims.width() as usize]),
+                _ => for x in 0..dims.width() {
+                    dst_row[x as usize] = self.coverage(x, y);
+                }
+            }
+        }
+
+        image
+    }
+
+    /// Convert the bitmap into an `Rgba8` image with straight alpha.
+    ///
+    /// Color bitmaps get converted from premultiplied BGRA. Coverage bitmaps become white, with the
+    /// coverage stored in the alpha channel; use `lcd_coverage` to keep subpixel information.
+    pub fn to_rgba8(&self) -> Image {
+        let dims = self.pixel_dims();
+        let mut image = Image::new(dims, ImageFormat::Rgba8);
+
+        for y in 0..dims.height() {
+            let dst_row = image.row_mut(y);
+            for x in 0..dims.width() {
+                let dst = &mut dst_row[x as usize * 4..x as usize * 4 + 4];
+                match self.pixel_mode {
+                    PixelMode::Bgra => {
+                        let bgra = &self.row(y)[x as usize * 4..x as usize * 4 + 4];
+                        let unpremul = |c: u8| match bgra[3] {
+                            0 => 0,
+                            a => ((c as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8
+                        };
+                        dst[0] = unpremul(bgra[2]);
+                        dst[1] = unpremul(bgra[1]);
+                        dst[2] = unpremul(bgra[0]);
+                        dst[3] = bgra[3];
+                    },
+                    _ => {
+                        dst[0] = 255;
+                        dst[1] = 255;
+                        dst[2] = 255;
+                        dst[3] = self.coverage(x, y);
+                    }
+                }
+            }
+        }
+
+        image
+    }
+
+    /// Convert the bitmap into per-channel coverage, suitable for subpixel text rendering with
+    /// dual-source blending.
+    ///
+    /// The result is an `Rgba8` image where the red, green and blue channels hold the coverage of
+    /// the corresponding subpixel, and the alpha channel holds their average for use when
+    /// dual-source blending isn't available. `Lcd` and `LcdV` bitmaps get split into their
+    /// subpixels according to `order`; all other pixel modes have their coverage copied into every
+    /// channel.
+    pub fn lcd_coverage(&self, order: SubpixelOrder) -> Image {
+        let dims = self.pixel_dims();
+        let mut image = Image::new(dims, ImageFormat::Rgba8);
+
+        for y in 0..dims.height() {
+            let dst_row = image.row_mut(y);
+            for x in 0..dims.width() {
+                let [r, g, b] = self.subpixels(x, y, order);
+                let dst = &mut dst_row[x as usize * 4..x as usize * 4 + 4];
+                dst[0] = r;
+                dst[1] = g;
+                dst[2] = b;
+                dst[3] = ((r as u32 + g as u32 + b as u32) / 3) as u8;
+            }
+        }
+
+        image
+    }
+
+    /// Retrieve the coverage of a single pixel's red, green and blue subpixels. Pixel modes without
+    /// subpixels return the pixel's coverage in every channel.
+    pub fn subpixels(&self, x: u32, y: u32, order: SubpixelOrder) -> [u8; 3] {
+        let subpixels = match self.pixel_mode {
+            PixelMode::Lcd => {
+                let row = self.row(y);
+                let x = x as usize * 3;
+                [row[x], row[x + 1], row[x + 2]]
+            },
+            PixelMode::LcdV => {
+                let x = x as usize;
+                [self.row(y * 3)[x], self.row(y * 3 + 1)[x], self.row(y * 3 + 2)[x]]
+            },
+            _ => return [self.coverage(x, y); 3]
+        };
+        match order {
+            SubpixelOrder::Rgb => subpixels,
+            SubpixelOrder::Bgr => [subpixels[2], subpixels[1], subpixels[0]]
+        }
+    }
+
+    /// Retrieve the coverage of a single pixel. For subpixel bitmaps this is the average of the
+    /// pixel's subpixels, and for color bitmaps this is the alpha channel.
+    pub fn coverage(&self, x: u32, y: u32) -> u8 {
+        match self.pixel_mode {
+            PixelMode::Mono => ((self.row(y)[x as usize / 8] >> (7 - x % 8)) & 1) * 255,
+            PixelMode::Gray2 => ((self.row(y)[x as usize / 4] >> (6 - 2 * (x % 4))) & 0b11) * 85,
+            PixelMode::Gray4 => ((self.row(y)[x as usize / 2] >> (4 - 4 * (x % 2))) & 0b1111) * 17,
+            PixelMode::Gray => self.row(y)[x as usize],
+            PixelMode::Lcd |
+            PixelMode::LcdV => {
+                let subpixels = self.subpixels(x, y, SubpixelOrder::Rgb);
+                ((subpixels[0] as u32 + subpixels[1] as u32 + subpixels[2] as u32) / 3) as u8
+            },
+            PixelMode::Bgra => self.row(y)[x as usize * 4 + 3]
+        }
+    }
+}
+
+/// Resample tightly packed pixels with `channels` bytes each from `src_dims` to `dst_dims`, using
+/// a box filter.
+fn resample(src: &[u8], src_dims: DimsBox<D2, u32>, dst_dims: DimsBox<D2, u32>, channels: usize) -> Vec<u8> {
+    let (src_width, src_height) = (src_dims.width() as usize, src_dims.height() as usize);
+    let (dst_width, dst_height) = (dst_dims.width() as usize, dst_dims.height() as usize);
+    let x_weights = box_weights(src_width, dst_width);
+    let y_weights = box_weights(src_height, dst_height);
+
+    // Scale horizontally, and then vertically.
+    let mut rows = vec![0.0f32; dst_width * src_height * channels];
+    for y in 0..src_height {
+        for (x, weights) in x_weights.iter().enumerate() {
+            for c in 0..channels {
+                rows[(y * dst_width + x) * channels + c] = weights.iter()
+                    .map(|&(src_x, weight)| src[(y * src_width + src_x) * channels + c] as f32 * weight)
+                    .sum();
+            }
+        }
+    }
+
+    let mut dst = vec![0; dst_width * dst_height * channels];
+    for (y, weights) in y_weights.iter().enumerate() {
+        for x in 0..dst_width {
+            for c in 0..channels {
+                let value: f32 = weights.iter()
+                    .map(|&(src_y, weight)| rows[(src_y * dst_width + x) * channels + c] * weight)
+                    .sum();
+                dst[(y * dst_width + x) * channels + c] = value.round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+    dst
+}
+
+/// For each destination pixel along an axis, find the source pixels it covers and how much of the
+/// destination pixel each one makes up.
+fn box_weights(src_len: usize, dst_len: usize) -> Vec<Vec<(usize, f32)>> {
+    let ratio = src_len as f32 / dst_len as f32;
+    (0..dst_len).map(|d| {
+        let (start, end) = (d as f32 * ratio, (d + 1) as f32 * ratio);
+        (start.floor() as usize..(end.ceil() as usize).min(src_len))
+            .map(|s| (s, (end.min(s as f32 + 1.0) - start.max(s as f32)) / ratio))
+            .filter(|&(_, weight)| weight > 0.0)
+            .collect()
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bitmap(dims: (u32, u32), pitch: i32, buffer: &[u8], pixel_mode: PixelMode) -> Bitmap {
+        Bitmap {
+            dims: DimsBox::new2(dims.0, dims.1),
+            pitch,
+            buffer,
+            pixel_mode
+        }
+    }
+
+    /// Check the conversions of `bitmap`, and of the same bitmap stored bottom-up with a negative
+    /// pitch.
+    fn check_conversions(bitmap: Bitmap, a8: &[u8], rgba8: &[u8]) {
+        let pitch = bitmap.pitch as usize;
+        let flipped = bitmap.buffer.chunks(pitch).rev().flat_map(|row| row.iter().cloned()).collect::<Vec<_>>();
+        let flipped = Bitmap{ pitch: -bitmap.pitch, buffer: &flipped, ..bitmap };
+
+        for bitmap in &[bitmap, flipped] {
+            let dims = bitmap.pixel_dims();
+            assert_eq!(Image{ dims, format: ImageFormat::A8, data: a8.to_vec() }, bitmap.to_a8());
+            assert_eq!(Image{ dims, format: ImageFormat::Rgba8, data: rgba8.to_vec() }, bitmap.to_rgba8());
+        }
+    }
+
+    /// The `Rgba8` image a coverage bitmap converts into.
+    fn white(coverage: &[u8]) -> Vec<u8> {
+        coverage.iter().flat_map(|&a| vec![255, 255, 255, a]).collect()
+    }
+
+    #[test]
+    fn mono() {
+        // Ten pixels per row, so the last byte is only partially used. The unused bits are set to
+        // make sure they're ignored.
+        let buffer = [
+            0b1010_0000, 0b1011_1111,
+            0b0000_0001, 0b0111_1111
+        ];
+        let a8 = [
+            255, 0, 255, 0, 0, 0, 0, 0, 255, 0,
+            0, 0, 0, 0, 0, 0, 0, 255, 0, 255
+        ];
+        check_conversions(bitmap((10, 2), 2, &buffer, PixelMode::Mono), &a8, &white(&a8));
+    }
+
+    #[test]
+    fn gray2() {
+        let buffer = [
+            0b00_01_10_11, 0b01_11_11_11,
+            0b11_10_01_00, 0b10_11_11_11
+        ];
+        let a8 = [
+            0, 85, 170, 255, 85,
+            255, 170, 85, 0, 170
+        ];
+        check_conversions(bitmap((5, 2), 2, &buffer, PixelMode::Gray2), &a8, &white(&a8));
+    }
+
+    #[test]
+    fn gray4() {
+        let buffer = [
+            0x0F, 0x8F,
+            0xF1, 0x2F
+        ];
+        let a8 = [
+            0, 255, 136,
+            255, 17, 34
+        ];
+        check_conversions(bitmap((3, 2), 2, &buffer, PixelMode::Gray4), &a8, &white(&a8));
+    }
+
+    #[test]
+    fn gray() {
+        // Rows padded out to four bytes.
+        let buffer = [
+            1, 2, 9, 9,
+            3, 4, 9, 9
+        ];
+        let a8 = [1, 2, 3, 4];
+        check_conversions(bitmap((2, 2), 4, &buffer, PixelMode::Gray), &a8, &white(&a8));
+    }
+
+    #[test]
+    fn lcd() {
+        let buffer = [
+            30, 60, 90, 255, 0, 0, 9, 9,
+            0, 0, 0, 3, 3, 3, 9, 9
+        ];
+        let a8 = [
+            60, 85,
+            0, 3
+        ];
+        check_conversions(bitmap((6, 2), 8, &buffer, PixelMode::Lcd), &a8, &white(&a8));
+    }
+
+    #[test]
+    fn lcd_v() {
+        let buffer = [
+            30, 255, 9,
+            60, 0, 9,
+            90, 0, 9,
+            0, 3, 9,
+            0, 3, 9,
+            0, 3, 9
+        ];
+        let a8 = [
+            60, 85,
+            0, 3
+        ];
+        check_conversions(bitmap((2, 6), 3, &buffer, PixelMode::LcdV), &a8, &white(&a8));
+    }
+
+    #[test]
+    fn bgra() {
+        // Premultiplied BGRA, with padding at the end of each row.
+        let buffer = [
+            0, 64, 128, 128, 10, 20, 30, 0, 9,
+            255, 255, 255, 255, 0, 0, 51, 51, 9
+        ];
+        let a8 = [
+            128, 0,
+            255, 51
+        ];
+        let rgba8 = [
+            255, 128, 0, 128, 0, 0, 0, 0,
+            255, 255, 255, 255, 255, 0, 0, 51
+        ];
+        check_conversions(bitmap((2, 2), 9, &buffer, PixelMode::Bgra), &a8, &rgba8);
+    }
+
+    fn owned_gray(dims: (u32, u32), buffer: Vec<u8>) -> OwnedBitmap {
+        OwnedBitmap {
+            dims: DimsBox::new2(dims.0, dims.1),
+            pitch: dims.0 as i32,
+            buffer,
+            pixel_mode: PixelMode::Gray
+        }
+    }
+
+    #[test]
+    fn scale_identity() {
+        let bitmap = owned_gray((3, 2), vec![0, 50, 100, 150, 200, 250]);
+        assert_eq!(bitmap, bitmap.scaled(1.0));
+    }
+
+    #[test]
+    fn scale_up_and_down() {
+        let small = owned_gray((2, 1), vec![0, 255]);
+        let large = owned_gray((4, 2), vec![
+            0, 0, 255, 255,
+            0, 0, 255, 255
+        ]);
+        assert_eq!(large, small.scaled(2.0));
+        assert_eq!(small, large.scaled(0.5));
+
+        let checkered = owned_gray((2, 2), vec![0, 255, 255, 0]);
+        assert_eq!(owned_gray((1, 1), vec![128]), checkered.scaled(0.5));
+    }
+
+    #[test]
+    fn scale_preserves_coverage() {
+        let src = owned_gray((7, 5), (0..35).map(|i| (i * 7) as u8).collect());
+        let src_total: u32 = src.buffer.iter().map(|&c| c as u32).sum();
+
+        for &scale in &[0.3, 0.7, 1.3, 2.5] {
+            let dst = src.scaled(scale);
+            let (width, height) = (dst.dims.width(), dst.dims.height());
+            assert_eq!(((7.0 * scale).round() as u32, (5.0 * scale).round() as u32), (width, height));
+            assert_eq!(width as usize * height as usize, dst.buffer.len());
+
+            // Every destination pixel holds the average of the area it covers, give or take
+            // rounding.
+            let dst_total: u32 = dst.buffer.iter().map(|&c| c as u32).sum();
+            let expected = src_total as f32 * (width * height) as f32 / 35.0;
+            assert!((dst_total as f32 - expected).abs() <= (width * height) as f32 * 0.5, "{} at scale {}", dst_total, scale);
+        }
+    }
+
+    #[test]
+    fn scale_converts_to_gray() {
+        let buffer = [0b1100_0000, 0b0011_0000];
+        let mono = bitmap((4, 2), 1, &buffer, PixelMode::Mono).into_owned();
+        assert_eq!(owned_gray((2, 1), vec![128, 128]), mono.scaled(0.5));
+
+        let buffer = [30, 60, 90, 0, 0, 0];
+        let lcd = bitmap((6, 1), 6, &buffer, PixelMode::Lcd).into_owned();
+        assert_eq!(owned_gray((4, 2), vec![60, 60, 0, 0, 60, 60, 0, 0]), lcd.scaled(2.0));
+    }
+
+    #[test]
+    fn scale_bgra() {
+        let buffer = [
+            0, 0, 255, 255, 255, 0, 0, 255,
+            0, 0, 0, 0, 0, 0, 0, 0
+        ];
+        let bgra = bitmap((2, 2), 8, &buffer, PixelMode::Bgra).into_owned();
+        let expected = OwnedBitmap {
+            dims: DimsBox::new2(1, 1),
+            pitch: 4,
+            buffer: vec![64, 0, 64, 128],
+            pixel_mode: PixelMode::Bgra
+        };
+        assert_eq!(expected, bgra.scaled(0.5));
+    }
+}
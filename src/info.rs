@@ -0,0 +1,440 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use {Face, Tag};
+use ffi;
+use ft;
+use harfbuzz_sys::{hb_font_get_face, hb_face_t, hb_tag_t};
+
+use std::{ptr, slice};
+use std::ffi::CStr;
+use std::ops::RangeInclusive;
+use std::os::raw::{c_char, c_uint};
+
+/// An entry in the font's `name` table.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NameEntry {
+    pub name_id: u16,
+    pub platform_id: u16,
+    pub encoding_id: u16,
+    pub language_id: u16,
+    /// The decoded name, if it's stored in an encoding this crate understands.
+    pub value: Option<String>,
+    pub bytes: Vec<u8>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CharMapInfo {
+    pub platform_id: u16,
+    pub encoding_id: u16,
+    /// FreeType's tag for the encoding, such as `unic` or `symb`.
+    pub encoding: Tag,
+    /// The `cmap` subtable format, or `None` if the font isn't an sfnt font.
+    pub format: Option<u32>
+}
+
+/// One of a variable font's axes of variation, in design units.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariationAxis {
+    pub tag: Tag,
+    pub name: String,
+    pub minimum: f32,
+    pub default: f32,
+    pub maximum: f32
+}
+
+/// An embedded bitmap strike.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Strike {
+    /// The average width of the strike's glyphs, in pixels.
+    pub width: u16,
+    /// The vertical distance between baselines, in pixels.
+    pub height: u16,
+    /// The nominal size of the strike, in 26.6 points.
+    pub size: i32,
+    /// The horizontal ppem, in 26.6 pixels.
+    pub x_ppem: i32,
+    /// The vertical ppem, in 26.6 pixels.
+    pub y_ppem: i32
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LayoutTable {
+    Gsub,
+    Gpos
+}
+
+/// A script listed in a GSUB or GPOS table, along with the language systems it defines.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LayoutScript {
+    pub tag: Tag,
+    pub languages: Vec<LanguageSystem>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LanguageSystem {
+    /// The language's tag, or `None` for the script's default language system.
+    pub tag: Option<Tag>,
+    /// A feature that's always applied when shaping text in this language system.
+    pub required_feature: Option<Tag>,
+    pub features: Vec<Tag>
+}
+
+/// The user-facing names a font gives to a feature. Only stylistic sets (`ss01` through `ss20`)
+/// and character variants (`cv01` through `cv99`) can be named.
+#[cfg(feature = "feature-names")]
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct FeatureNames {
+    pub label: Option<String>,
+    /// A longer description of the feature. Only used by character variants.
+    pub tooltip: Option<String>,
+    /// Sample text showing off the feature. Only used by character variants.
+    pub sample: Option<String>,
+    /// The names of the feature's alternates, in order. Only used by character variants.
+    pub parameters: Vec<String>,
+    /// The characters the feature affects. Only used by character variants.
+    pub characters: Vec<char>
+}
+
+impl<B: ?Sized> Face<B> {
+    /// The number of faces in the font file this face was loaded from.
+    #[inline]
+    pub fn num_faces(&self) -> u32 {
+        unsafe{ (*self.ft_face).num_faces as u32 }
+    }
+
+    /// The index of this face in its font file.
+    #[inline]
+    pub fn face_index(&self) -> i32 {
+        unsafe{ ((*self.ft_face).face_index & 0xFFFF) as i32 }
+    }
+
+    #[inline]
+    pub fn num_glyphs(&self) -> u32 {
+        unsafe{ (*self.ft_face).num_glyphs as u32 }
+    }
+
+    pub fn family_name(&self) -> Option<String> {
+        unsafe{ c_string((*self.ft_face).family_name) }
+    }
+
+    pub fn style_name(&self) -> Option<String> {
+        unsafe{ c_string((*self.ft_face).style_name) }
+    }
+
+    pub fn postscript_name(&self) -> Option<String> {
+        unsafe{ c_string(ft::FT_Get_Postscript_Name(self.ft_face)) }
+    }
+
+    /// Every entry in the font's `name` table.
+    pub fn names(&self) -> Vec<NameEntry> {
+        unsafe {
+            let count = ffi::FT_Get_Sfnt_Name_Count(self.ft_face);
+            (0..count).filter_map(|i| {
+                let mut name = ffi::FT_SfntName {
+                    platform_id: 0,
+                    encoding_id: 0,
+                    language_id: 0,
+                    name_id: 0,
+                    string: ptr::null_mut(),
+                    string_len: 0
+                };
+                if ft::FT_Error(0) != ffi::FT_Get_Sfnt_Name(self.ft_face, i, &mut name) {
+                    return None;
+                }
+
+                let bytes = match name.string.is_null() {
+                    true => Vec::new(),
+                    false => slice::from_raw_parts(name.string, name.string_len as usize).to_vec()
+                };
+                Some(NameEntry {
+                    name_id: name.name_id,
+                    platform_id: name.platform_id,
+                    encoding_id: name.encoding_id,
+                    language_id: name.language_id,
+                    value: decode_name(name.platform_id, name.encoding_id, &bytes),
+                    bytes
+                })
+            }).collect()
+        }
+    }
+
+    pub fn charmaps(&self) -> Vec<CharMapInfo> {
+        unsafe {
+            let charmaps = match (*self.ft_face).charmaps.is_null() {
+                true => &[][..],
+                false => slice::from_raw_parts((*self.ft_face).charmaps, (*self.ft_face).num_charmaps as usize)
+            };
+            charmaps.iter().map(|&charmap| CharMapInfo {
+                platform_id: (*charmap).platform_id,
+                encoding_id: (*charmap).encoding_id,
+                encoding: Tag::from_u32((*charmap).encoding as u32),
+                format: match ffi::FT_Get_CMap_Format(charmap) {
+                    -1 => None,
+                    format => Some(format as u32)
+                }
+            }).collect()
+        }
+    }
+
+    /// The ranges of character codes mapped to glyphs by the active charmap, which FreeType picks
+    /// to be a Unicode charmap if the font has one.
+    pub fn coverage(&self) -> Vec<RangeInclusive<u32>> {
+        let mut ranges: Vec<RangeInclusive<u32>> = Vec::new();
+        unsafe {
+            let mut glyph_index = 0;
+            let mut char_code = ft::FT_Get_First_Char(self.ft_face, &mut glyph_index);
+            while glyph_index != 0 {
+                let code = char_code as u32;
+                match ranges.last_mut() {
+                    Some(range) if range.end().checked_add(1) == Some(code) => *range = *range.start()..=code,
+                    _ => ranges.push(code..=code)
+                }
+                char_code = ft::FT_Get_Next_Char(self.ft_face, char_code, &mut glyph_index);
+            }
+        }
+        ranges
+    }
+
+    /// The script tags listed in one of the font's OpenType layout tables.
+    pub fn layout_scripts(&self, table: LayoutTable) -> Vec<Tag> {
+        unsafe {
+            let hb_face = hb_font_get_face(self.hb_font);
+            hb_tag_list(|start, count, tags| ffi::hb_ot_layout_table_get_script_tags(hb_face, table.tag(), start, count, tags))
+        }
+    }
+
+    /// The language tags listed for the script at `script_index` in `layout_scripts`. This doesn't
+    /// include the script's default language system.
+    pub fn layout_languages(&self, table: LayoutTable, script_index: u32) -> Vec<Tag> {
+        unsafe {
+            let hb_face = hb_font_get_face(self.hb_font);
+            hb_tag_list(|start, count, tags| ffi::hb_ot_layout_script_get_language_tags(hb_face, table.tag(), script_index, start, count, tags))
+        }
+    }
+
+    /// The feature tags listed in one of the font's OpenType layout tables.
+    pub fn layout_features(&self, table: LayoutTable) -> Vec<Tag> {
+        unsafe {
+            let hb_face = hb_font_get_face(self.hb_font);
+            hb_tag_list(|start, count, tags| ffi::hb_ot_layout_table_get_feature_tags(hb_face, table.tag(), start, count, tags))
+        }
+    }
+
+    /// Every script in one of the font's OpenType layout tables, along with the language systems
+    /// and features each one defines. A script's default language system comes first, if it has
+    /// one.
+    pub fn layout_systems(&self, table: LayoutTable) -> Vec<LayoutScript> {
+        unsafe {
+            let hb_face = hb_font_get_face(self.hb_font);
+            let table_tag = table.tag();
+
+            let language_system = |script_index: c_uint, language_index: c_uint, tag: Option<Tag>| {
+                let mut required_index = 0;
+                let mut required_tag = 0;
+                let has_required = ffi::hb_ot_layout_language_get_required_feature(
+                    hb_face, table_tag, script_index, language_index, &mut required_index, &mut required_tag
+                );
+                LanguageSystem {
+                    tag,
+                    required_feature: match has_required {
+                        0 => None,
+                        _ => Some(Tag::from_u32(required_tag))
+                    },
+                    features: hb_tag_list(|start, count, tags|
+                        ffi::hb_ot_layout_language_get_feature_tags(hb_face, table_tag, script_index, language_index, start, count, tags)
+                    )
+                }
+            };
+
+            self.layout_scripts(table).into_iter().enumerate().map(|(script_index, tag)| {
+                let script_index = script_index as c_uint;
+                let default_language = language_system(script_index, ffi::HB_OT_LAYOUT_DEFAULT_LANGUAGE_INDEX, None);
+
+                let mut languages = Vec::new();
+                // Harfbuzz reports an empty language system for scripts without a default one.
+                if default_language.required_feature.is_some() || !default_language.features.is_empty() {
+                    languages.push(default_language);
+                }
+                for (language_index, language_tag) in self.layout_languages(table, script_index).into_iter().enumerate() {
+                    languages.push(language_system(script_index, language_index as c_uint, Some(language_tag)));
+                }
+
+                LayoutScript{ tag, languages }
+            }).collect()
+        }
+    }
+
+    /// Look up the names the font gives a feature in its `name` table. Returns `None` if the
+    /// feature isn't in the table, or doesn't have any names.
+    ///
+    /// Requires the `feature-names` feature, since this needs Harfbuzz 2.1 or newer.
+    #[cfg(feature = "feature-names")]
+    pub fn feature_names(&self, table: LayoutTable, feature: Tag) -> Option<FeatureNames> {
+        unsafe {
+            let hb_face = hb_font_get_face(self.hb_font);
+            let table_tag = table.tag();
+
+            // A feature tag can appear several times in the feature list, once for each set of
+            // lookups it applies, so use the first instance that's been given names.
+            self.layout_features(table).into_iter().enumerate()
+                .filter(|&(_, tag)| tag == feature)
+                .filter_map(|(feature_index, _)| {
+                    let feature_index = feature_index as c_uint;
+                    let (mut label_id, mut tooltip_id, mut sample_id) = (0, 0, 0);
+                    let (mut num_parameters, mut first_parameter_id) = (0, 0);
+                    let has_names = ffi::hb_ot_layout_feature_get_name_ids(
+                        hb_face, table_tag, feature_index,
+                        &mut label_id, &mut tooltip_id, &mut sample_id, &mut num_parameters, &mut first_parameter_id
+                    );
+                    if has_names == 0 {
+                        return None;
+                    }
+
+                    let name = |name_id| hb_name(hb_face, name_id);
+                    let characters = hb_list(|start, count, characters|
+                        ffi::hb_ot_layout_feature_get_characters(hb_face, table_tag, feature_index, start, count, characters)
+                    );
+                    Some(FeatureNames {
+                        label: name(label_id),
+                        tooltip: name(tooltip_id),
+                        sample: name(sample_id),
+                        parameters: (0..num_parameters)
+                            .map(|i| name(first_parameter_id + i).unwrap_or_default())
+                            .collect(),
+                        characters: characters.into_iter().filter_map(::std::char::from_u32).collect()
+                    })
+                })
+                .next()
+        }
+    }
+
+    /// The axes of a variable font. Empty if the font isn't variable.
+    pub fn variation_axes(&self) -> Vec<VariationAxis> {
+        unsafe {
+            let mut mm_var = ptr::null_mut();
+            if ft::FT_Error(0) != ffi::FT_Get_MM_Var(self.ft_face, &mut mm_var) {
+                return Vec::new();
+            }
+
+            let axes = slice::from_raw_parts((*mm_var).axis, (*mm_var).num_axis as usize).iter().map(|axis| VariationAxis {
+                tag: Tag::from_u32(axis.tag as u32),
+                name: c_string(axis.name).unwrap_or_default(),
+                minimum: axis.minimum as f32 / 65536.0,
+                default: axis.def as f32 / 65536.0,
+                maximum: axis.maximum as f32 / 65536.0
+            }).collect();
+
+            let _lock = self._lib.lock();
+            ffi::FT_Done_MM_Var(self._lib.raw(), mm_var);
+            axes
+        }
+    }
+
+    /// The face's embedded bitmap strikes.
+    pub fn strikes(&self) -> Vec<Strike> {
+        unsafe {
+            let sizes = match (*self.ft_face).available_sizes.is_null() {
+                true => &[][..],
+                false => slice::from_raw_parts((*self.ft_face).available_sizes, (*self.ft_face).num_fixed_sizes as usize)
+            };
+            sizes.iter().map(|size| Strike {
+                width: size.width as u16,
+                height: size.height as u16,
+                size: size.size as i32,
+                x_ppem: size.x_ppem as i32,
+                y_ppem: size.y_ppem as i32
+            }).collect()
+        }
+    }
+}
+
+impl LayoutTable {
+    fn tag(self) -> hb_tag_t {
+        match self {
+            LayoutTable::Gsub => Tag(*b"GSUB").to_u32(),
+            LayoutTable::Gpos => Tag(*b"GPOS").to_u32()
+        }
+    }
+}
+
+/// Retrieve a list from a Harfbuzz function following the usual
+/// `(start_offset, count, items) -> total` convention.
+unsafe fn hb_list<T, F>(f: F) -> Vec<T>
+    where T: Copy + Default,
+          F: Fn(c_uint, *mut c_uint, *mut T) -> c_uint
+{
+    let mut count = 0;
+    let total = f(0, &mut count, ptr::null_mut());
+
+    let mut items = vec![T::default(); total as usize];
+    count = total;
+    f(0, &mut count, items.as_mut_ptr());
+    items.truncate(count as usize);
+    items
+}
+
+unsafe fn hb_tag_list<F>(f: F) -> Vec<Tag>
+    where F: Fn(c_uint, *mut c_uint, *mut hb_tag_t) -> c_uint
+{
+    hb_list(f).into_iter().map(Tag::from_u32).collect()
+}
+
+/// Look up an entry in the `name` table, in the default language.
+#[cfg(feature = "feature-names")]
+unsafe fn hb_name(hb_face: *mut hb_face_t, name_id: ffi::hb_ot_name_id_t) -> Option<String> {
+    if name_id == ffi::HB_OT_NAME_ID_INVALID {
+        return None;
+    }
+
+    let mut size = 0;
+    let len = ffi::hb_ot_name_get_utf8(hb_face, name_id, ptr::null(), &mut size, ptr::null_mut());
+    if len == 0 {
+        return None;
+    }
+
+    // The buffer needs room for the null terminator.
+    let mut buf = vec![0u8; len as usize + 1];
+    size = buf.len() as c_uint;
+    let len = ffi::hb_ot_name_get_utf8(hb_face, name_id, ptr::null(), &mut size, buf.as_mut_ptr() as *mut c_char);
+    buf.truncate(len as usize);
+    Some(String::from_utf8_lossy(&buf).into_owned())
+}
+
+unsafe fn c_string(s: *const c_char) -> Option<String> {
+    match s.is_null() {
+        true => None,
+        false => Some(CStr::from_ptr(s).to_string_lossy().into_owned())
+    }
+}
+
+/// Decode a `name` table string, if it's UTF-16 or Mac Roman encoded. Non-ASCII Mac Roman
+/// characters become replacement characters.
+fn decode_name(platform_id: u16, encoding_id: u16, bytes: &[u8]) -> Option<String> {
+    match (platform_id, encoding_id) {
+        // Unicode, and Windows' Symbol, Unicode BMP and Unicode full repertoire encodings.
+        (0, _) | (3, 0) | (3, 1) | (3, 10) => {
+            let units = bytes.chunks(2)
+                .filter(|c| c.len() == 2)
+                .map(|c| (c[0] as u16) << 8 | c[1] as u16)
+                .collect::<Vec<_>>();
+            Some(String::from_utf16_lossy(&units))
+        },
+        (1, 0) => Some(bytes.iter().map(|&b| match b < 0x80 {
+            true => b as char,
+            false => '\u{FFFD}'
+        }).collect()),
+        _ => None
+    }
+}
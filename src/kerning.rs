@@ -0,0 +1,726 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use {Face, FaceSize, DPI, Tag, Error, Operation, FaceError};
+use ft::{self, FT_Error, FT_Long, FT_ULong, FT_UInt};
+
+use std::{ptr, vec};
+use std::ops::Range;
+use std::collections::HashMap;
+
+use cgmath::Vector2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KerningMode {
+    /// Scaled to the face size and rounded to whole pixels, in 26.6 format.
+    Default,
+    /// Scaled to the face size without rounding, in 26.6 format.
+    Unfitted,
+    /// In font units.
+    Unscaled
+}
+
+/// The table a kerning pair was read from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum KerningSource {
+    Kern,
+    /// Pair adjustments from lookups used by the GPOS table's `kern` feature.
+    Gpos
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KerningPair {
+    pub left: u32,
+    pub right: u32,
+    /// The horizontal adjustment, in font units.
+    pub value: i32,
+    pub source: KerningSource
+}
+
+/// An iterator over a face's kerning pairs, sorted by source and then by glyph indices.
+#[derive(Debug, Clone)]
+pub struct KerningPairs {
+    pairs: vec::IntoIter<KerningPair>
+}
+
+/// The most pairs read from a GPOS table. Class-based adjustments can describe billions of pairs,
+/// so this bounds the time and memory a crafted font can make `kerning_pairs` use.
+const MAX_GPOS_PAIRS: usize = 1 << 20;
+
+impl<B: ?Sized> Face<B> {
+    /// Whether the font has a `kern` table that `kerning` can read.
+    #[inline]
+    pub fn has_kerning(&self) -> bool {
+        unsafe{ (*self.ft_face).face_flags & ft::FT_FACE_FLAG_KERNING as FT_Long != 0 }
+    }
+
+    /// Retrieve the kerning between two glyphs from the font's `kern` table. Kerning from the GPOS
+    /// table only gets applied while shaping, but is listed by `kerning_pairs`.
+    ///
+    /// `face_size` and `dpi` are ignored if `mode` is `Unscaled`. Otherwise, the kerning gets
+    /// scaled along with the strike selected by `StrikeSelection::Nearest`.
+    pub fn kerning(&mut self, left: u32, right: u32, face_size: FaceSize, dpi: DPI, mode: KerningMode) -> Result<Vector2<i32>, FaceError> {
+        let ft_mode = match mode {
+            KerningMode::Default => ft::FT_Kerning_Mode__FT_KERNING_DEFAULT,
+            KerningMode::Unfitted => ft::FT_Kerning_Mode__FT_KERNING_UNFITTED,
+            KerningMode::Unscaled => ft::FT_Kerning_Mode__FT_KERNING_UNSCALED
+        };
+        if mode != KerningMode::Unscaled {
+            self.resize(face_size, dpi)?;
+        }
+
+        let mut kerning = ft::FT_Vector{ x: 0, y: 0 };
+        unsafe {
+            Error::check(ft::FT_Get_Kerning(self.ft_face, left, right, ft_mode as FT_UInt, &mut kerning))
+                .map_err(|e| e.during_glyph(Operation::Kerning, left))?;
+        }
+        let scale = match mode {
+            KerningMode::Unscaled => 1.0,
+            _ => self.bitmap_scale
+        };
+        Ok(Vector2::new((kerning.x as f32 * scale).round() as i32, (kerning.y as f32 * scale).round() as i32))
+    }
+
+    /// Every kerning pair in the font's `kern` table, and the pair adjustments in the GPOS `kern`
+    /// feature flattened into glyph pairs. Pairs with a value of zero are left out.
+    ///
+    /// Only format 0 `kern` subtables are read. GPOS pair adjustments only contribute their first
+    /// glyph's X advance, and class-based adjustments for glyphs that aren't assigned a class in
+    /// the second class definition are skipped.
+    pub fn kerning_pairs(&self) -> KerningPairs {
+        let mut pairs = Vec::new();
+        if let Some(kern) = self.sfnt_table(Tag(*b"kern")) {
+            push_pairs(&mut pairs, kern_pairs(&kern), KerningSource::Kern);
+        }
+        if let Some(gpos) = self.sfnt_table(Tag(*b"GPOS")) {
+            let num_glyphs = unsafe{ (*self.ft_face).num_glyphs }.max(0).min(0x10000) as u32;
+            push_pairs(&mut pairs, gpos_pairs(&gpos, num_glyphs), KerningSource::Gpos);
+        }
+
+        KerningPairs {
+            pairs: pairs.into_iter()
+        }
+    }
+
+    fn sfnt_table(&self, tag: Tag) -> Option<Vec<u8>> {
+        unsafe {
+            let mut len = 0;
+            if FT_Error(0) != ft::FT_Load_Sfnt_Table(self.ft_face, tag.to_u32() as FT_ULong, 0, ptr::null_mut(), &mut len) {
+                return None;
+            }
+
+            let mut buf = vec![0; len as usize];
+            if FT_Error(0) != ft::FT_Load_Sfnt_Table(self.ft_face, tag.to_u32() as FT_ULong, 0, buf.as_mut_ptr(), &mut len) {
+                return None;
+            }
+            Some(buf)
+        }
+    }
+}
+
+impl Iterator for KerningPairs {
+    type Item = KerningPair;
+
+    #[inline]
+    fn next(&mut self) -> Option<KerningPair> {
+        self.pairs.next()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.pairs.size_hint()
+    }
+}
+
+impl ExactSizeIterator for KerningPairs {}
+
+fn push_pairs(pairs: &mut Vec<KerningPair>, values: HashMap<(u16, u16), i32>, source: KerningSource) {
+    let mut values = values.into_iter().filter(|&(_, value)| value != 0).collect::<Vec<_>>();
+    values.sort();
+    pairs.extend(values.into_iter().map(|((left, right), value)| KerningPair {
+        left: left as u32,
+        right: right as u32,
+        value,
+        source
+    }));
+}
+
+/// Read the horizontal format 0 subtables of a `kern` table, in either Microsoft's or Apple's
+/// layout. Malformed subtables are skipped.
+fn kern_pairs(kern: &[u8]) -> HashMap<(u16, u16), i32> {
+    let mut values = HashMap::new();
+
+    let (apple, num_tables, mut offset) = match u16_at(kern, 0) {
+        Some(0) => (false, u16_at(kern, 2).unwrap_or(0) as u32, 4),
+        Some(1) if u16_at(kern, 2) == Some(0) => (true, u32_at(kern, 4).unwrap_or(0), 8),
+        _ => return values
+    };
+
+    for _ in 0..num_tables {
+        // Microsoft's layout keeps the coverage flags in the low byte and the format in the high
+        // byte, while Apple's does the opposite and uses different flags.
+        let header = match apple {
+            false => u16_at(kern, offset + 2).and_then(|length| {
+                let coverage = u16_at(kern, offset + 4)?;
+                let horizontal = coverage & 0x1 != 0 && coverage & 0x6 == 0;
+                Some((length as usize, 6, horizontal, coverage & 0x8 != 0, (coverage >> 8) as u8))
+            }),
+            true => u32_at(kern, offset).and_then(|length| {
+                let coverage = u16_at(kern, offset + 4)?;
+                let horizontal = coverage & 0xE000 == 0;
+                Some((length as usize, 8, horizontal, false, coverage as u8))
+            })
+        };
+        let (length, header_len, horizontal, override_values, format) = match header {
+            Some(header) => header,
+            None => break
+        };
+
+        if horizontal && format == 0 {
+            let data = &kern[(offset + header_len).min(kern.len())..];
+            let num_pairs = u16_at(data, 0).unwrap_or(0) as usize;
+            for i in 0..num_pairs {
+                let record = 8 + i * 6;
+                let pair = match (u16_at(data, record), u16_at(data, record + 2), u16_at(data, record + 4)) {
+                    (Some(left), Some(right), Some(value)) => ((left, right), value as i16 as i32),
+                    _ => break
+                };
+                match override_values {
+                    true => {values.insert(pair.0, pair.1);},
+                    false => *values.entry(pair.0).or_insert(0) += pair.1
+                }
+            }
+        }
+
+        if length < header_len {
+            break;
+        }
+        offset = match offset.checked_add(length) {
+            Some(offset) => offset,
+            None => break
+        };
+    }
+
+    values
+}
+
+/// Flatten the pair adjustment lookups used by the GPOS table's `kern` feature. Within a lookup
+/// the first subtable to adjust a pair wins, and the adjustments of separate lookups are added
+/// together, matching how they're applied while shaping. Glyphs outside of `0..num_glyphs` are
+/// skipped, and no more than `MAX_GPOS_PAIRS` pairs are read.
+fn gpos_pairs(gpos: &[u8], num_glyphs: u32) -> HashMap<(u16, u16), i32> {
+    let mut values = HashMap::new();
+    let (feature_list, lookup_list) = match (u16_at(gpos, 6), u16_at(gpos, 8)) {
+        (Some(feature_list), Some(lookup_list)) => (feature_list as usize, lookup_list as usize),
+        _ => return values
+    };
+
+    let mut lookup_indices = Vec::new();
+    for i in 0..u16_at(gpos, feature_list).unwrap_or(0) as usize {
+        let record = feature_list + 2 + i * 6;
+        if u32_at(gpos, record) != Some(Tag(*b"kern").to_u32()) {
+            continue;
+        }
+        let feature = match u16_at(gpos, record + 4) {
+            Some(feature) => feature_list + feature as usize,
+            None => break
+        };
+        for j in 0..u16_at(gpos, feature + 2).unwrap_or(0) as usize {
+            lookup_indices.extend(u16_at(gpos, feature + 4 + j * 2));
+        }
+    }
+    lookup_indices.sort();
+    lookup_indices.dedup();
+
+    for lookup_index in lookup_indices {
+        let lookup = match u16_at(gpos, lookup_list + 2 + lookup_index as usize * 2) {
+            Some(lookup) => lookup_list + lookup as usize,
+            None => continue
+        };
+        let lookup_type = u16_at(gpos, lookup).unwrap_or(0);
+
+        let mut lookup_values = HashMap::new();
+        for i in 0..u16_at(gpos, lookup + 4).unwrap_or(0) as usize {
+            let mut subtable = match u16_at(gpos, lookup + 6 + i * 2) {
+                Some(subtable) => lookup + subtable as usize,
+                None => break
+            };
+            // Extension subtables point to the real subtable with a 32-bit offset.
+            let mut subtable_type = lookup_type;
+            if lookup_type == 9 {
+                match (u16_at(gpos, subtable + 2), u32_at(gpos, subtable + 4)) {
+                    (Some(extension_type), Some(extension)) => match subtable.checked_add(extension as usize) {
+                        Some(extension) => {
+                            subtable_type = extension_type;
+                            subtable = extension;
+                        },
+                        None => continue
+                    },
+                    _ => continue
+                }
+            }
+
+            if subtable_type == 2 {
+                pair_pos(gpos.get(subtable..).unwrap_or(&[]), num_glyphs, &mut lookup_values);
+            }
+        }
+
+        for (pair, value) in lookup_values {
+            if values.len() < MAX_GPOS_PAIRS || values.contains_key(&pair) {
+                *values.entry(pair).or_insert(0) += value;
+            }
+        }
+    }
+
+    values
+}
+
+/// Read a PairPos subtable, adding the pairs that aren't in `values` yet. Stops once `values`
+/// holds `MAX_GPOS_PAIRS` pairs.
+fn pair_pos(subtable: &[u8], num_glyphs: u32, values: &mut HashMap<(u16, u16), i32>) -> Option<()> {
+    let coverage = coverage(subtable, u16_at(subtable, 2)? as usize, num_glyphs)?;
+    let value_format_1 = u16_at(subtable, 4)?;
+    let value_format_2 = u16_at(subtable, 6)?;
+    let record_len = value_record_len(value_format_1) + value_record_len(value_format_2);
+    // The offset of the first value record's X advance, if it has one.
+    let x_advance = match value_format_1 & 0x4 {
+        0 => None,
+        _ => Some(value_record_len(value_format_1 & 0x3))
+    };
+
+    match u16_at(subtable, 0)? {
+        1 => {
+            let num_pair_sets = u16_at(subtable, 8)? as usize;
+            for &(left, coverage_index) in coverage.iter().filter(|&&(_, i)| i < num_pair_sets) {
+                let pair_set = u16_at(subtable, 10 + coverage_index * 2)? as usize;
+                for j in 0..u16_at(subtable, pair_set)? as usize {
+                    let record = pair_set + 2 + j * (2 + record_len);
+                    let right = u16_at(subtable, record)?;
+                    let value = match x_advance {
+                        Some(x_advance) => u16_at(subtable, record + 2 + x_advance)? as i16 as i32,
+                        None => 0
+                    };
+                    if (right as u32) < num_glyphs {
+                        insert_pair(values, (left, right), value)?;
+                    }
+                }
+            }
+        },
+        2 => {
+            let class_1_count = u16_at(subtable, 12)? as usize;
+            let class_2_count = u16_at(subtable, 14)? as usize;
+            let class_def_1 = class_def(subtable, u16_at(subtable, 8)? as usize, num_glyphs)?.into_iter().collect::<HashMap<_, _>>();
+            let mut class_def_2 = class_def(subtable, u16_at(subtable, 10)? as usize, num_glyphs)?;
+            class_def_2.retain(|&(_, class_2)| (class_2 as usize) < class_2_count);
+
+            for &(left, _) in &coverage {
+                let class_1 = class_def_1.get(&left).cloned().unwrap_or(0) as usize;
+                if class_1_count <= class_1 {
+                    continue;
+                }
+                for &(right, class_2) in &class_def_2 {
+                    let record = 16 + (class_1 * class_2_count + class_2 as usize) * record_len;
+                    let value = match x_advance {
+                        Some(x_advance) => u16_at(subtable, record + x_advance)? as i16 as i32,
+                        None => 0
+                    };
+                    insert_pair(values, (left, right), value)?;
+                }
+            }
+        },
+        _ => ()
+    }
+    Some(())
+}
+
+/// Add a pair unless an earlier subtable already adjusted it. Returns `None` once `values` is full.
+#[inline]
+fn insert_pair(values: &mut HashMap<(u16, u16), i32>, pair: (u16, u16), value: i32) -> Option<()> {
+    if values.len() >= MAX_GPOS_PAIRS {
+        return None;
+    }
+    values.entry(pair).or_insert(value);
+    Some(())
+}
+
+/// The glyphs in a coverage table along with their coverage indices, leaving out glyphs that
+/// aren't in the font. A glyph that's listed more than once keeps its first index.
+fn coverage(data: &[u8], offset: usize, num_glyphs: u32) -> Option<Vec<(u16, usize)>> {
+    let count = u16_at(data, offset + 2)? as usize;
+    let mut glyphs = Vec::new();
+    match u16_at(data, offset)? {
+        1 => for i in 0..count {
+            glyphs.push((u16_at(data, offset + 4 + i * 2)?, i));
+        },
+        2 => {
+            let mut ranges = GlyphRanges::new(num_glyphs);
+            for i in 0..count {
+                let record = offset + 4 + i * 6;
+                let (start, end, start_index) = (u16_at(data, record)?, u16_at(data, record + 2)?, u16_at(data, record + 4)?);
+                glyphs.extend(ranges.expand(start, end)?.map(|glyph| (glyph as u16, start_index as usize + (glyph - start as u32) as usize)));
+            }
+        },
+        _ => return None
+    }
+    Some(dedup_glyphs(glyphs, num_glyphs))
+}
+
+/// The glyphs assigned a non-zero class by a class definition table, along with their classes.
+/// Glyphs that aren't in the font are left out, and a glyph that's listed more than once keeps
+/// its first class.
+fn class_def(data: &[u8], offset: usize, num_glyphs: u32) -> Option<Vec<(u16, u16)>> {
+    let mut classes = Vec::new();
+    match u16_at(data, offset)? {
+        1 => {
+            let start = u16_at(data, offset + 2)?;
+            for i in 0..u16_at(data, offset + 4)? as usize {
+                let class = u16_at(data, offset + 6 + i * 2)?;
+                if class != 0 {
+                    classes.push((start.checked_add(i as u16)?, class));
+                }
+            }
+        },
+        2 => {
+            let mut ranges = GlyphRanges::new(num_glyphs);
+            for i in 0..u16_at(data, offset + 2)? as usize {
+                let record = offset + 4 + i * 6;
+                let (start, end, class) = (u16_at(data, record)?, u16_at(data, record + 2)?, u16_at(data, record + 4)?);
+                let glyphs = ranges.expand(start, end)?;
+                if class != 0 {
+                    classes.extend(glyphs.map(|glyph| (glyph as u16, class)));
+                }
+            }
+        },
+        _ => return None
+    }
+    Some(dedup_glyphs(classes, num_glyphs))
+}
+
+/// Expands the glyph ranges of a coverage or class definition table. Well-formed ranges don't
+/// overlap, so a table whose ranges cover more glyphs than the font has is rejected rather than
+/// expanded into an arbitrarily large list.
+struct GlyphRanges {
+    num_glyphs: u32,
+    remaining: u32
+}
+
+impl GlyphRanges {
+    fn new(num_glyphs: u32) -> GlyphRanges {
+        GlyphRanges{ num_glyphs, remaining: num_glyphs }
+    }
+
+    /// The glyphs from `start` through `end` that are in the font, or `None` if the table's ranges
+    /// have covered too many glyphs.
+    fn expand(&mut self, start: u16, end: u16) -> Option<Range<u32>> {
+        let start = (start as u32).min(self.num_glyphs);
+        let end = (end as u32 + 1).max(start).min(self.num_glyphs);
+        self.remaining = self.remaining.checked_sub(end - start)?;
+        Some(start..end)
+    }
+}
+
+/// Remove glyphs that aren't in the font, and all but the first entry for every glyph.
+fn dedup_glyphs<T>(mut entries: Vec<(u16, T)>, num_glyphs: u32) -> Vec<(u16, T)> {
+    let mut seen = vec![false; num_glyphs as usize];
+    entries.retain(|&(glyph, _)| match seen.get_mut(glyph as usize) {
+        Some(seen) if !*seen => {
+            *seen = true;
+            true
+        },
+        _ => false
+    });
+    entries
+}
+
+/// The size of a GPOS value record, in bytes.
+#[inline]
+fn value_record_len(value_format: u16) -> usize {
+    (value_format & 0xFF).count_ones() as usize * 2
+}
+
+#[inline]
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    data.get(offset..offset.checked_add(2)?).map(|b| (b[0] as u16) << 8 | b[1] as u16)
+}
+
+#[inline]
+fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    Some((u16_at(data, offset)? as u32) << 16 | u16_at(data, offset.checked_add(2)?)? as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NUM_GLYPHS: u32 = 100;
+
+    /// A `kern` subtable's coverage field and its pairs.
+    type KernSubtable<'a> = (u16, &'a [(u16, u16, i16)]);
+
+    fn bytes(words: &[u16]) -> Vec<u8> {
+        words.iter().flat_map(|&w| vec![(w >> 8) as u8, w as u8]).collect()
+    }
+
+    /// A format 0 subtable body: the binary search header followed by the pairs.
+    fn kern_format_0(pairs: &[(u16, u16, i16)]) -> Vec<u16> {
+        let mut words = vec![pairs.len() as u16, 0, 0, 0];
+        for &(left, right, value) in pairs {
+            words.extend(&[left, right, value as u16]);
+        }
+        words
+    }
+
+    fn ms_kern(subtables: &[KernSubtable]) -> Vec<u8> {
+        let mut words = vec![0, subtables.len() as u16];
+        for &(coverage, pairs) in subtables {
+            let body = kern_format_0(pairs);
+            words.extend(&[0, 6 + body.len() as u16 * 2, coverage]);
+            words.extend(body);
+        }
+        bytes(&words)
+    }
+
+    fn apple_kern(subtables: &[KernSubtable]) -> Vec<u8> {
+        let mut words = vec![1, 0, 0, subtables.len() as u16];
+        for &(coverage, pairs) in subtables {
+            let body = kern_format_0(pairs);
+            words.extend(&[0, 8 + body.len() as u16 * 2, coverage, 0]);
+            words.extend(body);
+        }
+        bytes(&words)
+    }
+
+    /// A GPOS table with a `kern` feature using every lookup in `lookups`, given as a lookup type
+    /// and its subtables.
+    fn gpos(lookups: &[(u16, Vec<Vec<u8>>)]) -> Vec<u8> {
+        let lookup_count = lookups.len() as u16;
+        let feature_list = 10;
+        let lookup_list = feature_list + 12 + lookup_count * 2;
+
+        let mut words = vec![1, 0, 0, feature_list, lookup_list];
+        // The feature list, with one `kern` feature record followed by the feature.
+        words.extend(&[1, 0x6B65, 0x726E, 8, 0, lookup_count]);
+        words.extend(0..lookup_count);
+
+        // The lookup list, with every lookup's subtables placed right after it.
+        words.push(lookup_count);
+        let mut lookup_offset = 2 + lookup_count as usize * 2;
+        let mut lookup_data = Vec::new();
+        for &(lookup_type, ref subtables) in lookups {
+            words.push(lookup_offset as u16);
+
+            let mut header = vec![lookup_type, 0, subtables.len() as u16];
+            let mut subtable_offset = 6 + subtables.len() * 2;
+            for subtable in subtables {
+                header.push(subtable_offset as u16);
+                subtable_offset += subtable.len();
+            }
+            lookup_data.extend(bytes(&header));
+            for subtable in subtables {
+                lookup_data.extend(subtable);
+            }
+            lookup_offset += subtable_offset;
+        }
+
+        let mut data = bytes(&words);
+        data.extend(lookup_data);
+        data
+    }
+
+    /// PairPos format 1 with X advances for the first glyph, covering `pair_sets`' first glyphs.
+    fn pair_pos_1(pair_sets: &[(u16, &[(u16, i16)])]) -> Vec<u8> {
+        let mut header = vec![1, 0, 0x0004, 0, pair_sets.len() as u16];
+        let mut sets = Vec::new();
+        let mut offset = 10 + pair_sets.len() * 2;
+        for &(_, pairs) in pair_sets {
+            header.push(offset as u16);
+            sets.push(pairs.len() as u16);
+            for &(right, value) in pairs {
+                sets.extend(&[right, value as u16]);
+            }
+            offset += 2 + pairs.len() * 4;
+        }
+        header[1] = offset as u16;
+        header.extend(sets);
+        header.extend(&[1, pair_sets.len() as u16]);
+        header.extend(pair_sets.iter().map(|&(left, _)| left));
+        bytes(&header)
+    }
+
+    /// A format 2 PairPos subtable where every glyph in `coverage` kerns against every glyph in the
+    /// `class_2` ranges by `value`.
+    fn pair_pos_2(coverage: &[(u16, u16)], class_2: &[(u16, u16)], value: i16) -> Vec<u8> {
+        // One class 1 record, holding X advances for class 2 values 0 and 1.
+        let mut words = vec![2, 0, 0x0004, 0, 0, 0, 1, 2, 0, value as u16];
+        words[1] = words.len() as u16 * 2;
+        words.extend(&[2, coverage.len() as u16]);
+        for &(start, end) in coverage {
+            words.extend(&[start, end, 0]);
+        }
+        // An empty class definition 1, which puts every glyph in class 0.
+        words[4] = words.len() as u16 * 2;
+        words.extend(&[2, 0]);
+        words[5] = words.len() as u16 * 2;
+        words.extend(&[2, class_2.len() as u16]);
+        for &(start, end) in class_2 {
+            words.extend(&[start, end, 1]);
+        }
+        bytes(&words)
+    }
+
+    fn pair_map(pairs: &[((u16, u16), i32)]) -> HashMap<(u16, u16), i32> {
+        pairs.iter().cloned().collect()
+    }
+
+    #[test]
+    fn ms_kern_format_0() {
+        let kern = ms_kern(&[
+            (0x0001, &[(1, 2, -50), (3, 4, 20)]),
+            // Cross-stream, which isn't kerning.
+            (0x0005, &[(5, 6, 7)]),
+            // Format 2, which isn't read.
+            (0x0201, &[(7, 8, 9)]),
+            (0x0001, &[(3, 4, 5)]),
+            // Overrides rather than adds to the accumulated values.
+            (0x0009, &[(1, 2, -10)])
+        ]);
+        assert_eq!(pair_map(&[((1, 2), -10), ((3, 4), 25)]), kern_pairs(&kern));
+    }
+
+    #[test]
+    fn apple_kern_format_0() {
+        let kern = apple_kern(&[
+            (0x0000, &[(1, 2, -30), (1, 3, 12)]),
+            // Vertical and cross-stream subtables.
+            (0x8000, &[(4, 5, 6)]),
+            (0x4000, &[(4, 5, 6)]),
+            (0x0000, &[(1, 3, -2)])
+        ]);
+        assert_eq!(pair_map(&[((1, 2), -30), ((1, 3), 10)]), kern_pairs(&kern));
+    }
+
+    #[test]
+    fn gpos_pair_pos_format_1() {
+        let gpos = gpos(&[
+            (2, vec![
+                pair_pos_1(&[(10, &[(20, -40)]), (11, &[(20, 15), (21, -5)])]),
+                // Pairs already adjusted by an earlier subtable of the same lookup are ignored.
+                pair_pos_1(&[(10, &[(20, -100), (22, -1)])])
+            ]),
+            // Separate lookups add up.
+            (2, vec![pair_pos_1(&[(11, &[(21, -5)])])])
+        ]);
+        assert_eq!(
+            pair_map(&[((10, 20), -40), ((10, 22), -1), ((11, 20), 15), ((11, 21), -10)]),
+            gpos_pairs(&gpos, NUM_GLYPHS)
+        );
+    }
+
+    #[test]
+    fn gpos_pair_pos_format_2() {
+        let subtable = bytes(&[
+            2, 32, 0x0005, 0, 42, 50, 2, 2,
+            // Class 1 records, with an X placement and X advance for each class 2.
+            0, 0, 0, -7i16 as u16,
+            0, 0, 99, -30i16 as u16,
+            // Coverage format 2: glyphs 30 and 31.
+            2, 1, 30, 31, 0,
+            // Class definition 1, format 1: glyph 31 is class 1.
+            1, 31, 1, 1,
+            // Class definition 2, format 2: glyphs 40 and 41 are class 1.
+            2, 1, 40, 41, 1
+        ]);
+        assert_eq!(
+            pair_map(&[((30, 40), -7), ((30, 41), -7), ((31, 40), -30), ((31, 41), -30)]),
+            gpos_pairs(&gpos(&[(2, vec![subtable])]), NUM_GLYPHS)
+        );
+    }
+
+    #[test]
+    fn gpos_extension() {
+        let mut extension = bytes(&[1, 2, 0, 8]);
+        extension.extend(pair_pos_1(&[(10, &[(20, -40)])]));
+        assert_eq!(pair_map(&[((10, 20), -40)]), gpos_pairs(&gpos(&[(9, vec![extension])]), NUM_GLYPHS));
+    }
+
+    #[test]
+    fn out_of_range_offsets() {
+        let gpos = gpos(&[(2, vec![vec![0xFF; 4]]), (2, vec![pair_pos_1(&[(10, &[(20, -40)])])])]);
+        let mut broken = gpos.clone();
+        // Point the first lookup past the end of the table.
+        let lookup_list = u16_at(&gpos, 8).unwrap() as usize;
+        broken[lookup_list + 2] = 0xFF;
+        assert_eq!(pair_map(&[((10, 20), -40)]), gpos_pairs(&broken, NUM_GLYPHS));
+        assert!(gpos_pairs(&gpos[..gpos.len() - 1], NUM_GLYPHS).is_empty());
+    }
+
+    #[test]
+    fn malformed_tables() {
+        let mut extension = bytes(&[1, 2, 0, 8]);
+        extension.extend(pair_pos_1(&[(10, &[(20, -40)])]));
+        let format_2 = bytes(&[2, 32, 0x0005, 0, 42, 50, 2, 2, 0, 0, 0, 1, 0, 0, 2, 3, 2, 1, 30, 31, 0, 1, 31, 1, 1, 2, 1, 40, 41, 1]);
+        let tables = [
+            ms_kern(&[(0x0001, &[(1, 2, -50), (3, 4, 20)]), (0x0009, &[(1, 2, -10)])]),
+            apple_kern(&[(0x0000, &[(1, 2, -30)])]),
+            gpos(&[(2, vec![pair_pos_1(&[(10, &[(20, -40)]), (11, &[(20, 15)])]), format_2]), (9, vec![extension])])
+        ];
+
+        // Every truncation, and every 16-bit value replaced with 0xFFFF, has to be handled
+        // without panicking.
+        for table in &tables {
+            for len in 0..table.len() {
+                kern_pairs(&table[..len]);
+                gpos_pairs(&table[..len], NUM_GLYPHS);
+            }
+            for i in 0..table.len() - 1 {
+                let mut broken = table.clone();
+                broken[i] = 0xFF;
+                broken[i + 1] = 0xFF;
+                kern_pairs(&broken);
+                gpos_pairs(&broken, NUM_GLYPHS);
+            }
+        }
+    }
+
+    #[test]
+    fn ranges_clamped_to_font() {
+        let subtable = pair_pos_2(&[(30, 0xFFFF)], &[(20, 0xFFFF)], -5);
+        let expected = (30..32).flat_map(|left| (20..32).map(move |right| ((left, right), -5))).collect::<Vec<_>>();
+        assert_eq!(pair_map(&expected), gpos_pairs(&gpos(&[(2, vec![subtable])]), 32));
+    }
+
+    #[test]
+    fn overlapping_ranges() {
+        // Ranges covering more glyphs than the font has are rejected outright.
+        let coverage = pair_pos_2(&[(0, 19), (10, 29)], &[(0, 0)], -5);
+        let class_2 = pair_pos_2(&[(0, 0)], &[(0, 19), (10, 29)], -5);
+        assert!(gpos_pairs(&gpos(&[(2, vec![coverage, class_2])]), 30).is_empty());
+
+        // Repeated glyphs keep their first coverage index.
+        let repeated = pair_pos_1(&[(10, &[(20, -40)]), (10, &[(20, -100), (21, -3)])]);
+        assert_eq!(pair_map(&[((10, 20), -40)]), gpos_pairs(&gpos(&[(2, vec![repeated])]), NUM_GLYPHS));
+    }
+
+    #[test]
+    fn pair_limit() {
+        // Both lookups describe more pairs than are read.
+        let gpos = gpos(&[
+            (2, vec![pair_pos_2(&[(0, 0xFFFF)], &[(0, 0xFFFF)], -1)]),
+            (2, vec![pair_pos_1(&[(0xFFFF, &[(0xFFFF, -1)])])])
+        ]);
+        let pairs = gpos_pairs(&gpos, 0x10000);
+        assert_eq!(MAX_GPOS_PAIRS, pairs.len());
+        assert!(!pairs.contains_key(&(0xFFFF, 0xFFFF)));
+    }
+}
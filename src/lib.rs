@@ -29,46 +29,166 @@ extern crate derive_error;
 
 mod hb_funcs;
 mod ft_alloc;
+mod ffi;
+mod render;
+mod gamma;
+mod image;
+mod cache;
+mod pool;
+mod props;
+mod error;
+mod validate;
+mod serialize;
+mod info;
+mod bmfont;
+mod kerning;
+mod stream;
+
+pub use render::*;
+pub use gamma::*;
+pub use image::*;
+pub use cache::*;
+pub use pool::*;
+pub use props::*;
+pub use error::*;
+pub use validate::*;
+pub use serialize::*;
+pub use info::*;
+pub use bmfont::*;
+pub use kerning::*;
+pub use stream::*;
 
 use std::os::raw::{c_void, c_uint, c_int, c_char};
 use freetype::freetype as ft;
-use ft::{FT_Face, FT_Library, FT_Error, FT_Size_RequestRec_, FT_Size_Request_Type__FT_SIZE_REQUEST_TYPE_NOMINAL, FT_ULong, FT_Long};
+use ft::{FT_Face, FT_Library, FT_Error, FT_Size_RequestRec_, FT_ULong, FT_Long, FT_UInt, FT_Fixed};
 
 use harfbuzz_sys::*;
 
 use stable_deref_trait::StableDeref;
 
-use std::{mem, slice, ptr};
+use std::{fmt, mem, slice, ptr, cmp, char};
 use std::path::Path;
 use std::ops::Deref;
 use std::ffi::CString;
+use std::alloc::GlobalAlloc;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ft_alloc::AllocState;
 
 use cgmath::{Point2, Vector2};
 use cgmath_geometry::{cgmath, D2};
 use cgmath_geometry::rect::{DimsBox, GeoBox};
 
-
-#[derive(Debug)]
+/// The size of the buffer glyph names get read into, including the null terminator. Longer names
+/// get truncated by FreeType.
+const GLYPH_NAME_BUF_LEN: usize = 256;
+
+/// A handle to a FreeType library instance.
+///
+/// Clones of an `FTLib` share the same underlying library. Creating and destroying faces takes a
+/// lock internally, so `FTLib` is both `Send` and `Sync`. Each `Face` owns its own FreeType face,
+/// so faces may be moved onto other threads and used there concurrently, although a single face
+/// can't be shared between threads. Use `FacePool` to hand out separate faces to worker threads.
+///
+/// FreeType reads library properties, like the LCD filter and the hinting settings, while loading
+/// and rendering glyphs. Changing them therefore needs exclusive access to the library: the
+/// property setters take `&mut self`, and return `Error::LibraryInUse` if any clone of the library
+/// or any face created with it is still alive.
+#[derive(Clone)]
 pub struct FTLib {
-    lib: FT_Library
+    inner: Arc<LibInner>
+}
+
+struct LibInner {
+    lib: FT_Library,
+    memory: *mut ft::FT_MemoryRec_,
+    alloc_state: Arc<AllocState>,
+    lock: Mutex<()>
+}
+
+/// Controls how FreeType allocates memory for a library, and every face created with it.
+#[derive(Clone, Copy, Default)]
+pub struct MemoryConfig {
+    /// The maximum number of bytes FreeType may have allocated at once. Allocations past this limit
+    /// fail, which makes the FreeType call that needed them return `Error::OutOfMemory`.
+    pub limit: Option<usize>,
+    /// The allocator FreeType's memory is taken from. Uses `malloc` if `None`.
+    pub allocator: Option<&'static (dyn GlobalAlloc + Sync)>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MemoryUsage {
+    /// The number of bytes currently allocated by FreeType.
+    pub live: usize,
+    /// The largest value `live` has reached.
+    pub peak: usize
 }
 
 pub struct Face<B: ?Sized> {
     ft_face: FT_Face,
-    ft_size_request: FT_Size_RequestRec_,
+    /// The size most recently requested from FreeType.
+    size_request: Option<(FaceSize, DPI)>,
+    /// The factor bitmaps from the selected strike get scaled by, when a strike was picked with
+    /// `StrikeSelection::Nearest`.
+    bitmap_scale: f32,
     hb_font: *mut hb_font_t,
+    id: FaceId,
+    variation_serial: u64,
     _lib: FTLib,
     _font_buffer: B
 }
 
+/// Uniquely identifies a `Face` for as long as the program runs. Cloning a face produces a face
+/// with a new ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FaceId(usize);
+
 pub struct Shaper {
     hb_buf: *mut hb_buffer_t
 }
 
+/// The size to render a face at. Unless `pixels` is set, `width` and `height` are in 26.6 points
+/// and get converted to pixels with the `DPI` the face is used at.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct FaceSize {
     pub width: u32,
-    pub height: u32
+    pub height: u32,
+    /// What part of the font `width` and `height` set the size of.
+    pub request_type: SizeRequestType,
+    /// Whether `width` and `height` are in 26.6 pixels, in which case the DPI is ignored.
+    pub pixels: bool,
+    /// Use one of the font's embedded bitmap strikes instead of scaling its outlines.
+    pub strike: Option<StrikeSelection>
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StrikeSelection {
+    /// Select the strike at this index in `Face::strikes`, ignoring the rest of the `FaceSize`.
+    Fixed(u32),
+    /// Select the strike whose vertical ppem is closest to the requested size, and scale its
+    /// bitmaps, metrics and shaping positions to that size. Fonts without strikes get sized
+    /// normally.
+    Nearest
+}
+
+/// Which font metric a `FaceSize` sets the size of. Fonts whose ascenders and descenders take up
+/// very different proportions of the em come out at more consistent line heights when sized by
+/// `RealDim` or `Cell` than by `Nominal`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SizeRequestType {
+    /// The size of the em square. This is the size a font's point size usually refers to.
+    Nominal,
+    /// The distance from the ascender to the descender.
+    RealDim,
+    /// The size of the font's bounding box.
+    BBox,
+    /// The average character width and the distance from the ascender to the descender.
+    Cell,
+    /// `width` and `height` are 16.16 scaling factors from font units to 26.6 pixels, rather than
+    /// sizes.
+    Scales
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -77,6 +197,47 @@ pub struct DPI {
     pub vert: u32
 }
 
+/// A four-byte OpenType tag, such as a feature, script or variation axis tag.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Tag(pub [u8; 4]);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Ltr,
+    Rtl,
+    Ttb,
+    Btt
+}
+
+/// An OpenType feature to apply while shaping. `start` and `end` are byte indices into the shaped
+/// text, and the feature is applied to every cluster between them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Feature {
+    pub tag: Tag,
+    pub value: u32,
+    pub start: usize,
+    pub end: usize
+}
+
+/// A position along one of a variable font's axes, in the axis' design units.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Variation {
+    pub tag: Tag,
+    pub value: f32
+}
+
+/// Controls how `Shaper` shapes text. Any segment property left as `None` gets guessed from the
+/// text itself.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub struct ShapeOptions {
+    pub features: Vec<Feature>,
+    pub direction: Option<Direction>,
+    /// The ISO 15924 script tag, such as `Latn` or `Arab`.
+    pub script: Option<Tag>,
+    /// The BCP 47 language tag, such as `en` or `ar`.
+    pub language: Option<String>
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum BreakType {
     Soft,
@@ -135,7 +296,9 @@ pub struct FontMetrics266 {
 }
 
 pub struct GlyphSlot<'a> {
-    glyph_slot: &'a mut ft::FT_GlyphSlotRec_
+    glyph_slot: &'a mut ft::FT_GlyphSlotRec_,
+    glyph_index: u32,
+    bitmap_scale: f32
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -173,6 +336,24 @@ pub enum RenderMode {
     LcdV = ft::FT_Render_Mode__FT_RENDER_MODE_LCD_V as isize,
 }
 
+/// The filter FreeType applies to LCD-rendered glyphs in order to reduce color fringes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LcdFilter {
+    None,
+    Default,
+    Light,
+    Legacy,
+    /// Custom 5-tap filter weights, which should add up to roughly 256.
+    Custom([u8; 5])
+}
+
+/// The order of the subpixels on an LCD panel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SubpixelOrder {
+    Rgb,
+    Bgr
+}
+
 bitflags!{
     pub struct LoadFlags: c_uint {
         const NO_SCALE = ft::FT_LOAD_NO_SCALE;
@@ -197,140 +378,200 @@ bitflags!{
 
 
 impl FTLib {
+    /// Create a new library instance.
+    ///
+    /// # Panics
+    /// Panics if FreeType fails to initialize. Use `try_new` to handle that failure.
     pub fn new() -> FTLib {
+        FTLib::try_new().expect("failed to initialize FreeType")
+    }
+
+    pub fn try_new() -> Result<FTLib, Error> {
+        FTLib::try_with_memory(MemoryConfig::default())
+    }
+
+    /// Create a new library instance, with memory accounting configured by `config`.
+    pub fn try_with_memory(config: MemoryConfig) -> Result<FTLib, Error> {
+        let alloc_state = Arc::new(AllocState::new(config.limit, config.allocator));
+        let memory = ft_alloc::alloc_mem_rec(alloc_state.clone());
+
         let mut lib = ptr::null_mut();
         unsafe {
-            assert_eq!(FT_Error(0), ft::FT_New_Library(ft_alloc::alloc_mem_rec(), &mut lib));
+            let error = ft::FT_New_Library(memory, &mut lib);
+            if let Err(error) = Error::check(error) {
+                ft_alloc::free_mem_rec(memory);
+                return Err(error);
+            }
             ft::FT_Add_Default_Modules(lib);
         }
 
-        FTLib{ lib }
+        Ok(FTLib {
+            inner: Arc::new(LibInner {
+                lib,
+                memory,
+                alloc_state,
+                lock: Mutex::new(())
+            })
+        })
+    }
+
+    /// Retrieve how much memory FreeType has allocated for this library and its faces.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            live: self.inner.alloc_state.live(),
+            peak: self.inner.alloc_state.peak()
+        }
+    }
+
+    /// Reset the peak memory usage to the current live memory usage.
+    pub fn reset_peak_memory(&self) {
+        self.inner.alloc_state.reset_peak();
+    }
+
+    #[inline]
+    pub fn memory_limit(&self) -> Option<usize> {
+        self.inner.alloc_state.limit()
+    }
+
+    /// Change the memory limit. Lowering the limit below the current usage doesn't free anything,
+    /// but causes all further allocations to fail until usage drops below the limit.
+    pub fn set_memory_limit(&self, limit: Option<usize>) {
+        self.inner.alloc_state.set_limit(limit);
+    }
+
+    /// Lock the library for operations that FreeType doesn't allow to run concurrently.
+    fn lock(&self) -> MutexGuard<()> {
+        // The lock doesn't guard any data, so a poisoned lock is still usable.
+        self.inner.lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[inline]
+    fn raw(&self) -> FT_Library {
+        self.inner.lib
+    }
+
+    /// Retrieve the library for changing its properties, which is only allowed while nothing else
+    /// can be using it.
+    fn raw_exclusive(&mut self) -> Result<FT_Library, Error> {
+        Arc::get_mut(&mut self.inner).map(|inner| inner.lib).ok_or(Error::LibraryInUse)
     }
 }
 
 impl Face<()> {
-    pub fn new_path<P: AsRef<Path>>(path: P, face_index: i32, lib: &FTLib) -> Result<Face<()>, Error> {
-        unsafe {
-            let path_canon = path.as_ref().canonicalize().map_err(|_| Error::CannotOpenResource)?;
-            let path_c = CString::new(path_canon.to_str().ok_or(Error::CannotOpenResource)?.to_owned()).map_err(|_| Error::CannotOpenResource)?;
+    pub fn new_path<P: AsRef<Path>>(path: P, face_index: i32, lib: &FTLib) -> Result<Face<()>, FaceError> {
+        let open_error = Error::CannotOpenResource.during(Operation::OpenFace);
+        let path_canon = path.as_ref().canonicalize().map_err(|_| open_error)?;
+        let path_c = CString::new(path_canon.to_str().ok_or(open_error)?.to_owned()).map_err(|_| open_error)?;
 
+        unsafe {
             let mut ft_face = ptr::null_mut();
 
             // Allocate the face in freetype, and ensure that it was created successfully
-            let err_raw = ft::FT_New_Face(
-                lib.lib,
-                path_c.as_ptr(),
-                face_index as FT_Long,
-                &mut ft_face
-            );
+            let err_raw = {
+                let _lock = lib.lock();
+                ft::FT_New_Face(
+                    lib.raw(),
+                    path_c.as_ptr(),
+                    face_index as FT_Long,
+                    &mut ft_face
+                )
+            };
+            Error::check(err_raw).map_err(|e| e.during(Operation::OpenFace))?;
 
-            match Error::from_raw(err_raw).unwrap() {
-                Error::Ok => {
-                    unsafe extern "C" fn reference_table(_: *mut hb_face_t, tag: hb_tag_t, user_data: *mut c_void) -> *mut hb_blob_t {
-                        let ft_face = user_data as FT_Face;
-                        let mut len = 0;
-
-                        if FT_Error(0) != ft::FT_Load_Sfnt_Table(ft_face, tag as FT_ULong, 0, ptr::null_mut(), &mut len) {
-                            return ptr::null_mut();
-                        }
-
-                        let mut buf = vec![0; len as usize];
-                        if FT_Error(0) != ft::FT_Load_Sfnt_Table(ft_face, tag as FT_ULong, 0, buf.as_mut_ptr() as *mut ft::FT_Byte, &mut len) {
-                            return ptr::null_mut();
-                        }
-
-                        hb_blob_create(
-                            buf.as_mut_ptr(), len as c_uint, HB_MEMORY_MODE_WRITABLE,
-                            Box::into_raw(Box::new(buf)) as *mut c_void, Some(free_ref_table)
-                        )
-                    }
-                    unsafe extern "C" fn free_ref_table(table: *mut c_void) {
-                        Box::from_raw(table as *mut Vec<c_char>);
-                    }
+            // Create the harfbuzz font
+            let hb_face = hb_face_create_for_tables(Some(reference_table), ft_face as *mut c_void, None);
+            Face::from_ft_face(ft_face, hb_face, (), lib)
+        }
+    }
+}
 
-                    // Create the harfbuzz font
-                    let hb_face = hb_face_create_for_tables(Some(reference_table), ft_face as *mut c_void, None);
-                    hb_face_set_upem(hb_face, (*ft_face).units_per_EM as c_uint);
-                    let hb_font = hb_font_create(hb_face);
-                    hb_funcs::set_for_font(hb_font, ft_face);
-
-                    // Harfbuzz font creation cleanup
-                    hb_face_destroy(hb_face);
-
-
-                    Ok(Face {
-                        ft_face,
-                        hb_font,
-                        ft_size_request: FT_Size_RequestRec_ {
-                            type_: FT_Size_Request_Type__FT_SIZE_REQUEST_TYPE_NOMINAL,
-                            width: -1,
-                            height: -1,
-                            horiResolution: 0,
-                            vertResolution: 0
-                        },
-
-                        _font_buffer: (),
-                        _lib: lib.clone()
-                    })
-                },
-                err => Err(err)
-            }
+/// Harfbuzz table loader for faces that aren't backed by an in-memory buffer, which fetches tables
+/// through FreeType on demand.
+unsafe extern "C" fn reference_table(_: *mut hb_face_t, tag: hb_tag_t, user_data: *mut c_void) -> *mut hb_blob_t {
+    let ft_face = user_data as FT_Face;
+    let mut len = 0;
+
+    if FT_Error(0) != ft::FT_Load_Sfnt_Table(ft_face, tag as FT_ULong, 0, ptr::null_mut(), &mut len) {
+        return ptr::null_mut();
+    }
+
+    let mut buf = vec![0; len as usize];
+    if FT_Error(0) != ft::FT_Load_Sfnt_Table(ft_face, tag as FT_ULong, 0, buf.as_mut_ptr() as *mut ft::FT_Byte, &mut len) {
+        return ptr::null_mut();
+    }
+
+    hb_blob_create(
+        buf.as_mut_ptr(), len as c_uint, HB_MEMORY_MODE_WRITABLE,
+        Box::into_raw(Box::new(buf)) as *mut c_void, Some(free_ref_table)
+    )
+}
+unsafe extern "C" fn free_ref_table(table: *mut c_void) {
+    Box::from_raw(table as *mut Vec<c_char>);
+}
+
+impl<B> Face<B> {
+    /// Finish creating a face out of a freshly opened FreeType face, and the Harfbuzz face created
+    /// for it. Takes ownership of both, destroying them if creation fails.
+    unsafe fn from_ft_face(ft_face: FT_Face, hb_face: *mut hb_face_t, font_buffer: B, lib: &FTLib) -> Result<Face<B>, FaceError> {
+        hb_face_set_upem(hb_face, (*ft_face).units_per_EM as c_uint);
+        let hb_font = hb_font_create(hb_face);
+
+        // Harfbuzz font creation cleanup
+        hb_face_destroy(hb_face);
+
+        // Harfbuzz hands out its inert empty font instead of failing outright.
+        if hb_font == hb_font_get_empty() {
+            let _lock = lib.lock();
+            ft::FT_Done_Face(ft_face);
+            return Err(Error::HbAllocationFailed.during(Operation::OpenFace));
         }
+        hb_funcs::set_for_font(hb_font, ft_face);
+
+        Ok(Face {
+            ft_face,
+            hb_font,
+            id: FaceId::next(),
+            variation_serial: 0,
+            size_request: None,
+            bitmap_scale: 1.0,
+
+            _font_buffer: font_buffer,
+            _lib: lib.clone()
+        })
     }
 }
 
 impl<B> Face<B>
     where B: StableDeref + Deref<Target=[u8]>
 {
-    pub fn new(font_buffer: B, face_index: i32, lib: &FTLib) -> Result<Face<B>, Error> {
+    pub fn new(font_buffer: B, face_index: i32, lib: &FTLib) -> Result<Face<B>, FaceError> {
         let mut ft_face = ptr::null_mut();
         unsafe {
             // Allocate the face in freetype, and ensure that it was created successfully
-            let err_raw = ft::FT_New_Memory_Face(
-                lib.lib,
-                font_buffer.as_ptr(),
-                font_buffer.len() as FT_Long,
-                face_index as FT_Long,
-                &mut ft_face
+            let err_raw = {
+                let _lock = lib.lock();
+                ft::FT_New_Memory_Face(
+                    lib.raw(),
+                    font_buffer.as_ptr(),
+                    font_buffer.len() as FT_Long,
+                    face_index as FT_Long,
+                    &mut ft_face
+                )
+            };
+            Error::check(err_raw).map_err(|e| e.during(Operation::OpenFace))?;
+
+            // Create the harfbuzz font
+            let hb_blob = hb_blob_create(
+                font_buffer.as_ptr() as *const c_char,
+                font_buffer.len() as c_uint,
+                HB_MEMORY_MODE_READONLY,
+                ptr::null_mut(),
+                None
             );
+            let hb_face = hb_face_create(hb_blob, face_index as c_uint);
+            hb_blob_destroy(hb_blob);
 
-            match Error::from_raw(err_raw).unwrap() {
-                Error::Ok => {
-                    // Create the harfbuzz font
-                    let hb_blob = hb_blob_create(
-                        font_buffer.as_ptr() as *const c_char,
-                        font_buffer.len() as c_uint,
-                        HB_MEMORY_MODE_READONLY,
-                        ptr::null_mut(),
-                        None
-                    );
-                    let hb_face = hb_face_create(hb_blob, face_index as c_uint);
-                    hb_face_set_upem(hb_face, (*ft_face).units_per_EM as c_uint);
-                    let hb_font = hb_font_create(hb_face);
-                    hb_funcs::set_for_font(hb_font, ft_face);
-
-                    // Harfbuzz font creation cleanup
-                    hb_face_destroy(hb_face);
-                    hb_blob_destroy(hb_blob);
-
-
-                    Ok(Face {
-                        ft_face,
-                        hb_font,
-                        ft_size_request: FT_Size_RequestRec_ {
-                            type_: FT_Size_Request_Type__FT_SIZE_REQUEST_TYPE_NOMINAL,
-                            width: -1,
-                            height: -1,
-                            horiResolution: 0,
-                            vertResolution: 0
-                        },
-
-                        _font_buffer: font_buffer,
-                        _lib: lib.clone()
-                    })
-                },
-                err => Err(err)
-            }
+            Face::from_ft_face(ft_face, hb_face, font_buffer, lib)
         }
     }
 
@@ -338,9 +579,69 @@ impl<B> Face<B>
     pub fn buffer(&self) -> &B {
         &self._font_buffer
     }
+
+    /// Create a new face from the same font buffer and face index. Unlike `clone`, this reports
+    /// failure instead of panicking.
+    ///
+    /// The new face has its own FreeType face, and so can be sent to a different thread than this
+    /// one. It doesn't inherit this face's variation coordinates.
+    pub fn try_clone(&self) -> Result<Face<B>, FaceError>
+        where B: Clone
+    {
+        let buf = self._font_buffer.clone();
+        Face::new(buf, unsafe{ (*self.ft_face).face_index as i32 }, &self._lib)
+    }
 }
 
 impl<B: ?Sized> Face<B> {
+    #[inline]
+    pub fn id(&self) -> FaceId {
+        self.id
+    }
+
+    /// A counter that gets incremented every time the face's variation coordinates change.
+    #[inline]
+    pub fn variation_serial(&self) -> u64 {
+        self.variation_serial
+    }
+
+    /// Set the face's position in the design space of a variable font. Axes that aren't mentioned
+    /// in `variations` get reset to their default values, and values outside of an axis' range get
+    /// clamped to that range.
+    ///
+    /// Any shaping results cached with `ShapeCache` for this face should be invalidated afterwards.
+    pub fn set_variations(&mut self, variations: &[Variation]) -> Result<(), FaceError> {
+        unsafe {
+            let mut mm_var = ptr::null_mut();
+            Error::check(ffi::FT_Get_MM_Var(self.ft_face, &mut mm_var))
+                .map_err(|e| e.during(Operation::SetVariations))?;
+
+            let axes = slice::from_raw_parts((*mm_var).axis, (*mm_var).num_axis as usize);
+            let mut coords = axes.iter().map(|axis| {
+                variations.iter().rev()
+                    .find(|v| v.tag.to_u32() as FT_ULong == axis.tag)
+                    .map(|v| ((v.value * 65536.0) as FT_Fixed).max(axis.minimum).min(axis.maximum))
+                    .unwrap_or(axis.def)
+            }).collect::<Vec<FT_Fixed>>();
+            {
+                let _lock = self._lib.lock();
+                ffi::FT_Done_MM_Var(self._lib.raw(), mm_var);
+            }
+
+            Error::check(ffi::FT_Set_Var_Design_Coordinates(self.ft_face, coords.len() as FT_UInt, coords.as_mut_ptr()))
+                .map_err(|e| e.during(Operation::SetVariations))?;
+
+            let hb_variations = variations.iter().map(|v| ffi::hb_variation_t {
+                tag: v.tag.to_u32(),
+                value: v.value
+            }).collect::<Vec<_>>();
+            ffi::hb_font_set_variations(self.hb_font, hb_variations.as_ptr(), hb_variations.len() as c_uint);
+        }
+
+        self.variation_serial += 1;
+        Ok(())
+    }
+
     pub fn load_glyph<'a>(
         &'a mut self,
         glyph_index: u32,
@@ -348,21 +649,22 @@ impl<B: ?Sized> Face<B> {
         dpi: DPI,
         load_flags: LoadFlags,
         hint_algo: RenderMode
-    ) -> Result<GlyphSlot<'a>, Error>
+    ) -> Result<GlyphSlot<'a>, FaceError>
     {
         self.resize(face_size, dpi)?;
         let mut load_flags = load_flags.bits as c_int;
 
         unsafe {
-            load_flags |= (mem::transmute::<_, c_int>(hint_algo) & 16) << 16;
-
-            let error = ft::FT_Load_Glyph(self.ft_face, glyph_index, load_flags);
-            match error {
-                FT_Error(0) => Ok(GlyphSlot {
-                    glyph_slot: &mut *(*self.ft_face).glyph
-                }),
-                FT_Error(_) => Err(Error::from_raw(error).unwrap())
-            }
+            // FT_LOAD_TARGET_(mode), which keeps the low four bits of the render mode.
+            load_flags |= (mem::transmute::<_, c_int>(hint_algo) & 15) << 16;
+
+            Error::check(ft::FT_Load_Glyph(self.ft_face, glyph_index, load_flags))
+                .map_err(|e| e.during_glyph(Operation::LoadGlyph, glyph_index))?;
+            Ok(GlyphSlot {
+                glyph_slot: &mut *(*self.ft_face).glyph,
+                glyph_index,
+                bitmap_scale: self.bitmap_scale
+            })
         }
     }
 
@@ -371,20 +673,97 @@ impl<B: ?Sized> Face<B> {
         unsafe{ ft::FT_Get_Char_Index(self.ft_face, c as FT_ULong) }
     }
 
+    /// Look up a glyph's name in the font's `post` or `CFF` table. Returns `None` if the font
+    /// doesn't name its glyphs.
+    pub fn glyph_name(&self, glyph_index: u32) -> Option<String> {
+        unsafe {
+            if !self.has_glyph_names() {
+                return None;
+            }
+
+            let mut buf = [0u8; GLYPH_NAME_BUF_LEN];
+            let error = ft::FT_Get_Glyph_Name(self.ft_face, glyph_index, buf.as_mut_ptr() as *mut c_void, buf.len() as FT_UInt);
+            if FT_Error(0) != error {
+                return None;
+            }
+            match buf.iter().position(|&b| b == 0).unwrap_or(buf.len()) {
+                0 => None,
+                len => String::from_utf8(buf[..len].to_vec()).ok()
+            }
+        }
+    }
+
+    /// The names of every glyph in the face, indexed by glyph index. Every entry is `None` if the
+    /// font doesn't name its glyphs.
+    pub fn glyph_names(&self) -> Vec<Option<String>> {
+        let num_glyphs = unsafe{ (*self.ft_face).num_glyphs as u32 };
+        match self.has_glyph_names() {
+            true => (0..num_glyphs).map(|i| self.glyph_name(i)).collect(),
+            false => vec![None; num_glyphs as usize]
+        }
+    }
+
+    /// Find a glyph by name. Along with the names in the font, this understands the conventions
+    /// Harfbuzz uses for unnamed glyphs: `gid<index>` for glyph indices, and `uni<hex>` or
+    /// `u<hex>` for the glyph mapped to a Unicode character.
+    pub fn glyph_from_name(&self, name: &str) -> Option<u32> {
+        if self.has_glyph_names() {
+            if let Ok(name_c) = CString::new(name) {
+                match unsafe{ ft::FT_Get_Name_Index(self.ft_face, name_c.as_ptr() as *mut _) } {
+                    // FreeType also returns 0 for unknown names, so check whether glyph 0 was
+                    // actually the one asked for.
+                    0 if self.glyph_name(0).as_ref().map(|n| &**n) == Some(name) => return Some(0),
+                    0 => (),
+                    glyph_index => return Some(glyph_index)
+                }
+            }
+        }
+
+        let num_glyphs = unsafe{ (*self.ft_face).num_glyphs as u32 };
+        if name.starts_with("gid") {
+            let index = &name[3..];
+            return match !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()) {
+                true => index.parse().ok().filter(|&i| i < num_glyphs),
+                false => None
+            };
+        }
+
+        let hex = match name.starts_with("uni") {
+            true => &name[3..],
+            false if name.starts_with('u') => &name[1..],
+            false => return None
+        };
+        if hex.is_empty() || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return None;
+        }
+        match u32::from_str_radix(hex, 16).ok().and_then(char::from_u32).map(|c| self.char_index(c)) {
+            Some(0) | None => None,
+            Some(glyph_index) => Some(glyph_index)
+        }
+    }
+
+    #[inline]
+    fn has_glyph_names(&self) -> bool {
+        unsafe{ (*self.ft_face).face_flags & ft::FT_FACE_FLAG_GLYPH_NAMES as FT_Long != 0 }
+    }
+
     /// Retrieve the glyph advance. If scaling is performed (based on the value of `load_flags`),
-    /// the advance value is in 16.16 format. Otherwise, it is in font units.
-    pub fn glyph_advance(&mut self, glyph_index: u32, face_size: FaceSize, dpi: DPI, load_flags: LoadFlags) -> Result<i32, Error> {
-        if !load_flags.contains(LoadFlags::NO_SCALE) {
+    /// the advance value is in 16.16 format, and bitmap strikes are scaled by the strike's
+    /// `bitmap_scale`. Otherwise, it is in font units.
+    pub fn glyph_advance(&mut self, glyph_index: u32, face_size: FaceSize, dpi: DPI, load_flags: LoadFlags) -> Result<i32, FaceError> {
+        let scaled = !load_flags.contains(LoadFlags::NO_SCALE);
+        if scaled {
             self.resize(face_size, dpi)?;
         }
 
+        let mut advance = 0;
         unsafe {
-            let mut advance = 0;
-            let error = ft::FT_Get_Advance(self.ft_face, glyph_index, mem::transmute(load_flags), &mut advance);
-            match error {
-                FT_Error(0) => Ok(advance as i32),
-                _ => Err(Error::from_raw(error).unwrap())
-            }
+            Error::check(ft::FT_Get_Advance(self.ft_face, glyph_index, mem::transmute(load_flags), &mut advance))
+                .map_err(|e| e.during_glyph(Operation::GlyphAdvance, glyph_index))?;
+        }
+        match scaled && self.bitmap_scale != 1.0 {
+            true => Ok((advance as f64 * self.bitmap_scale as f64).round() as i32),
+            false => Ok(advance as i32)
         }
     }
 
@@ -404,57 +783,104 @@ impl<B: ?Sized> Face<B> {
     }
 
     #[inline]
-    pub fn metrics_sized(&mut self, face_size: FaceSize, dpi: DPI) -> Result<FontMetrics266, Error> {
+    pub fn metrics_sized(&mut self, face_size: FaceSize, dpi: DPI) -> Result<FontMetrics266, FaceError> {
         self.resize(face_size, dpi)?;
 
         let size_metrics = unsafe{ &(*(*self.ft_face).size).metrics };
+        let scale = |value: FT_Long| (value as f32 * self.bitmap_scale).round() as i32;
         Ok(FontMetrics266 {
-            x_ppem: size_metrics.x_ppem,
-            y_ppem: size_metrics.y_ppem,
-            x_scale: size_metrics.x_scale as i32,
-            y_scale: size_metrics.y_scale as i32,
-            ascender: size_metrics.ascender as i32,
-            descender: size_metrics.descender as i32,
-            height: size_metrics.height as i32,
-            max_advance: size_metrics.max_advance as i32,
+            x_ppem: (size_metrics.x_ppem as f32 * self.bitmap_scale).round() as u16,
+            y_ppem: (size_metrics.y_ppem as f32 * self.bitmap_scale).round() as u16,
+            x_scale: scale(size_metrics.x_scale),
+            y_scale: scale(size_metrics.y_scale),
+            ascender: scale(size_metrics.ascender),
+            descender: scale(size_metrics.descender),
+            height: scale(size_metrics.height),
+            max_advance: scale(size_metrics.max_advance),
         })
     }
 
-    fn resize(&mut self, face_size: FaceSize, dpi: DPI) -> Result<(), Error> {
+    fn resize(&mut self, face_size: FaceSize, dpi: DPI) -> Result<(), FaceError> {
         // Determine if we need to change the freetype font size, and change it if necessary
-        let old_size_request = (
-            FaceSize {
-                width: self.ft_size_request.width as u32,
-                height: self.ft_size_request.height as u32
-            },
-            DPI {
-                hori: self.ft_size_request.horiResolution,
-                vert: self.ft_size_request.vertResolution
-            }
-        );
-        if (face_size, dpi) != old_size_request {
-            // Change freetype font size
-            let mut size_request = FT_Size_RequestRec_ {
-                type_: FT_Size_Request_Type__FT_SIZE_REQUEST_TYPE_NOMINAL,
-                width: face_size.width as FT_Long,
-                height: face_size.height as FT_Long,
-                horiResolution: dpi.hori,
-                vertResolution: dpi.vert
-            };
-            let error = unsafe{ ft::FT_Request_Size(self.ft_face, &mut size_request) };
-            if FT_Error(0) != error {
-                return Err(Error::from_raw(error).unwrap());
+        if self.size_request == Some((face_size, dpi)) {
+            return Ok(());
+        }
+
+        let strike = match face_size.strike {
+            Some(StrikeSelection::Fixed(index)) => Some((index, 1.0)),
+            Some(StrikeSelection::Nearest) => self.nearest_strike(face_size, dpi),
+            None => None
+        };
+        self.bitmap_scale = 1.0;
+
+        let error = unsafe {
+            match strike {
+                Some((index, _)) => ft::FT_Select_Size(self.ft_face, index as c_int),
+                None => {
+                    // FreeType treats sizes as 26.6 pixels when the resolution is zero.
+                    let (hori, vert) = match face_size.pixels {
+                        true => (0, 0),
+                        false => (dpi.hori, dpi.vert)
+                    };
+                    let mut size_request = FT_Size_RequestRec_ {
+                        type_: face_size.request_type.to_ft(),
+                        width: face_size.width as FT_Long,
+                        height: face_size.height as FT_Long,
+                        horiResolution: hori,
+                        vertResolution: vert
+                    };
+                    ft::FT_Request_Size(self.ft_face, &mut size_request)
+                }
             }
+        };
+        // If the request failed, FreeType may have left the face at some other size.
+        self.size_request = None;
+        Error::check(error).map_err(|e| e.during(Operation::Resize))?;
+        self.size_request = Some((face_size, dpi));
+
+        // Harfbuzz scales the positions it reads from tables like GPOS from font units to the
+        // font's scale, which has to match the 26.6 values the font functions return.
+        unsafe {
+            let metrics = (*(*self.ft_face).size).metrics;
+            let upem = (*self.ft_face).units_per_EM as i64;
+            let scale = |ft_scale: FT_Fixed| ((ft_scale as i64 * upem + (1 << 15)) >> 16) as c_int;
+            hb_font_set_scale(self.hb_font, scale(metrics.x_scale), scale(metrics.y_scale));
+        }
+        if let Some((_, scale)) = strike {
+            self.bitmap_scale = scale;
         }
         Ok(())
     }
+
+    /// Find the strike closest to `face_size`'s height, and the scale needed to bring it to that
+    /// height.
+    fn nearest_strike(&self, face_size: FaceSize, dpi: DPI) -> Option<(u32, f32)> {
+        let requested_ppem = match face_size.pixels {
+            true => face_size.height as i64,
+            false => face_size.height as i64 * dpi.vert as i64 / 72
+        };
+
+        self.strikes().iter().enumerate()
+            .filter(|&(_, strike)| strike.y_ppem > 0)
+            .min_by_key(|&(_, strike)| (strike.y_ppem as i64 - requested_ppem).abs())
+            .map(|(index, strike)| (index as u32, requested_ppem as f32 / strike.y_ppem as f32))
+    }
 }
 
 impl Shaper {
     pub fn new() -> Shaper {
+        Shaper::try_new().expect("failed to allocate Harfbuzz buffer")
+    }
+
+    pub fn try_new() -> Result<Shaper, Error> {
         unsafe {
-            Shaper {
-                hb_buf: hb_buffer_create()
+            let hb_buf = hb_buffer_create();
+            match hb_buffer_allocation_successful(hb_buf) {
+                0 => {
+                    hb_buffer_destroy(hb_buf);
+                    Err(Error::HbAllocationFailed)
+                },
+                _ => Ok(Shaper{ hb_buf })
             }
         }
     }
@@ -466,18 +892,73 @@ impl Shaper {
         face: &mut Face<B>,
         face_size: FaceSize,
         dpi: DPI,
-    ) -> Result<ShapedGlyphIter<'a>, Error>
+    ) -> Result<ShapedGlyphIter<'a>, FaceError>
+    {
+        self.shape_text_with(text, face, face_size, dpi, &ShapeOptions::default())
+    }
+
+    /// Shape text with the given features and segment properties.
+    pub fn shape_text_with<'a, B: ?Sized>(
+        &'a mut self,
+        text: &str,
+        face: &mut Face<B>,
+        face_size: FaceSize,
+        dpi: DPI,
+        options: &ShapeOptions
+    ) -> Result<ShapedGlyphIter<'a>, FaceError>
     {
         face.resize(face_size, dpi)?;
 
         let hb_buf = self.hb_buf;
+        if text.len() > i32::max_value() as usize {
+            return Err(Error::InvalidArgument.during(Operation::Shape));
+        }
+
+        let features = options.features.iter().map(|feature| hb_feature_t {
+            tag: feature.tag.to_u32(),
+            value: feature.value,
+            start: cmp::min(feature.start, c_uint::max_value() as usize) as c_uint,
+            end: cmp::min(feature.end, c_uint::max_value() as usize) as c_uint
+        }).collect::<Vec<_>>();
 
         unsafe{
             // Add the word to the harfbuzz buffer, and shape it.
             hb_buffer_clear_contents(hb_buf);
             hb_buffer_add_utf8(hb_buf, text.as_ptr() as *const c_char, text.len() as i32, 0, text.len() as i32);
+            if 0 == hb_buffer_allocation_successful(hb_buf) {
+                return Err(Error::HbAllocationFailed.during(Operation::Shape));
+            }
+
+            if let Some(direction) = options.direction {
+                hb_buffer_set_direction(hb_buf, direction.to_hb());
+            }
+            if let Some(script) = options.script {
+                hb_buffer_set_script(hb_buf, hb_script_from_iso15924_tag(script.to_u32()));
+            }
+            if let Some(ref language) = options.language {
+                hb_buffer_set_language(hb_buf, hb_language_from_string(language.as_ptr() as *const c_char, language.len() as c_int));
+            }
             hb_buffer_guess_segment_properties(hb_buf);
-            hb_shape(face.hb_font, hb_buf, ptr::null(), 0);
+
+            let shaped = hb_shape_full(face.hb_font, hb_buf, features.as_ptr(), features.len() as c_uint, ptr::null());
+            if 0 == hb_buffer_allocation_successful(hb_buf) {
+                return Err(Error::HbAllocationFailed.during(Operation::Shape));
+            }
+            if 0 == shaped {
+                return Err(Error::ShapingFailed.during(Operation::Shape));
+            }
+        }
+
+        if face.bitmap_scale != 1.0 {
+            let mut glyph_pos_count = 0;
+            let glyph_pos_ptr = unsafe{ hb_buffer_get_glyph_positions(hb_buf, &mut glyph_pos_count) };
+            let scale = |value: hb_position_t| (value as f32 * face.bitmap_scale).round() as hb_position_t;
+            for pos in unsafe{ slice::from_raw_parts_mut(glyph_pos_ptr, glyph_pos_count as usize) } {
+                pos.x_advance = scale(pos.x_advance);
+                pos.y_advance = scale(pos.y_advance);
+                pos.x_offset = scale(pos.x_offset);
+                pos.y_offset = scale(pos.y_offset);
+            }
         }
 
 
@@ -504,7 +985,7 @@ impl Iterator for ShapedGlyphIter<'_> {
         let (pos, info) = self.glyph_iter.next()?;
 
         let glyph_shaped = ShapedGlyph {
-            pos: self.cursor + Vector2::new(pos.x_offset, pos.y_offset),
+            pos: self.cursor + Vector2::new(pos.x_offset, pos.y_offset) / 64,
             advance: Vector2::new(pos.x_advance, pos.y_advance) / 64,
             glyph_index: info.codepoint,
             str_index: info.cluster as usize,
@@ -519,7 +1000,16 @@ impl Iterator for ShapedGlyphIter<'_> {
 impl ExactSizeIterator for ShapedGlyphIter<'_> {}
 
 impl<'a> GlyphSlot<'a> {
+    /// The glyph's metrics, scaled by `bitmap_scale`.
     pub fn metrics(&self) -> GlyphMetrics266 {
+        match self.bitmap_scale {
+            scale if scale != 1.0 => self.unscaled_metrics().scaled(scale),
+            _ => self.unscaled_metrics()
+        }
+    }
+
+    /// The glyph's metrics at the size of the loaded glyph, before `bitmap_scale` is applied.
+    fn unscaled_metrics(&self) -> GlyphMetrics266 {
         let ft_metrics = self.glyph_slot.metrics;
 
         GlyphMetrics266 {
@@ -531,14 +1021,51 @@ impl<'a> GlyphSlot<'a> {
         }
     }
 
-    pub fn render_glyph(&mut self, render_mode: RenderMode) -> Result<Bitmap<'a>, Error> {
+    /// The offset from the pen position to the top-left corner of the glyph's bitmap, in pixels.
+    /// The Y axis points up. Like the bitmap returned by `render_glyph`, this isn't scaled by
+    /// `bitmap_scale`.
+    #[inline]
+    pub fn bitmap_offset(&self) -> Vector2<i32> {
+        Vector2::new(self.glyph_slot.bitmap_left, self.glyph_slot.bitmap_top)
+    }
+
+    /// The glyph's advance after hinting and transformation, in 26.6 format, scaled by
+    /// `bitmap_scale`.
+    #[inline]
+    pub fn advance(&self) -> Vector2<i32> {
+        let scale = |value: ft::FT_Pos| (value as f32 * self.bitmap_scale).round() as i32;
+        Vector2::new(scale(self.glyph_slot.advance.x), scale(self.glyph_slot.advance.y))
+    }
+
+    #[inline]
+    fn unscaled_advance(&self) -> Vector2<i32> {
+        Vector2::new(self.glyph_slot.advance.x as i32, self.glyph_slot.advance.y as i32)
+    }
+
+    /// The index of the glyph loaded into this slot.
+    #[inline]
+    pub fn glyph_index(&self) -> u32 {
+        self.glyph_index
+    }
+
+    /// The factor the glyph needs to be scaled by when the face was sized with
+    /// `StrikeSelection::Nearest`, and 1 otherwise. `metrics` and `advance` apply the scale, as
+    /// does `render_image`, while `render_glyph` and `bitmap_offset` are at the strike's size.
+    #[inline]
+    pub fn bitmap_scale(&self) -> f32 {
+        self.bitmap_scale
+    }
+
+    pub fn render_glyph(&mut self, render_mode: RenderMode) -> Result<Bitmap<'a>, FaceError> {
+        let glyph_index = self.glyph_index;
         unsafe {
             let ft_render_mode = mem::transmute(render_mode);
-            match ft::FT_Render_Glyph(self.glyph_slot, ft_render_mode) {
-                FT_Error(0) => Ok(self.bitmap().expect("bad bitmap")),
-                error => Err(Error::from_raw(error).unwrap())
-            }
+            Error::check(ft::FT_Render_Glyph(self.glyph_slot, ft_render_mode))
+                .map_err(|e| e.during_glyph(Operation::RenderGlyph, glyph_index))?;
         }
+        // FreeType can report success while leaving behind a bitmap in a pixel mode we don't
+        // understand.
+        self.bitmap().ok_or(Error::CannotRenderGlyph.during_glyph(Operation::RenderGlyph, glyph_index))
     }
 
     pub fn bitmap(&self) -> Option<Bitmap<'a>> {
@@ -569,10 +1096,230 @@ impl<'a> GlyphSlot<'a> {
     }
 }
 
+impl<'a> Bitmap<'a> {
+    /// Retrieve the bytes of row `y`, counting from the top of the bitmap regardless of the sign of
+    /// `pitch`.
+    pub fn row(&self, y: u32) -> &'a [u8] {
+        let pitch = self.pitch.abs() as usize;
+        let row_index = match self.pitch < 0 {
+            false => y as usize,
+            true => self.dims.height() as usize - 1 - y as usize
+        };
+        &self.buffer[row_index * pitch..(row_index + 1) * pitch]
+    }
+
+    /// The dimensions of the bitmap in pixels, as opposed to `dims` which for `Lcd` and `LcdV`
+    /// bitmaps counts individual subpixels.
+    pub fn pixel_dims(&self) -> DimsBox<D2, u32> {
+        match self.pixel_mode {
+            PixelMode::Lcd => DimsBox::new2(self.dims.width() / 3, self.dims.height()),
+            PixelMode::LcdV => DimsBox::new2(self.dims.width(), self.dims.height() / 3),
+            _ => self.dims
+        }
+    }
+}
+
+impl GlyphMetrics266 {
+    fn scaled(&self, scale: f32) -> GlyphMetrics266 {
+        let scale_i32 = |value: i32| (value as f32 * scale).round() as i32;
+        let scale_vector = |v: Vector2<i32>| Vector2::new(scale_i32(v.x), scale_i32(v.y));
+        GlyphMetrics266 {
+            dims: DimsBox::new2(scale_i32(self.dims.width()), scale_i32(self.dims.height())),
+            hori_bearing: scale_vector(self.hori_bearing),
+            hori_advance: scale_i32(self.hori_advance),
+            vert_bearing: scale_vector(self.vert_bearing),
+            vert_advance: scale_i32(self.vert_advance)
+        }
+    }
+}
+
+impl FaceId {
+    fn next() -> FaceId {
+        static NEXT_FACE_ID: AtomicUsize = AtomicUsize::new(0);
+        FaceId(NEXT_FACE_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Tag {
+    #[inline]
+    pub fn new(tag: &[u8; 4]) -> Tag {
+        Tag(*tag)
+    }
+
+    #[inline]
+    pub fn from_u32(tag: u32) -> Tag {
+        Tag([(tag >> 24) as u8, (tag >> 16) as u8, (tag >> 8) as u8, tag as u8])
+    }
+
+    #[inline]
+    pub fn to_u32(self) -> u32 {
+        (self.0[0] as u32) << 24 | (self.0[1] as u32) << 16 | (self.0[2] as u32) << 8 | self.0[3] as u32
+    }
+}
+
+impl FromStr for Tag {
+    type Err = ();
+
+    /// Parse a tag from a string of up to four ASCII characters. Shorter strings get padded with
+    /// spaces.
+    fn from_str(s: &str) -> Result<Tag, ()> {
+        if s.is_empty() || 4 < s.len() || !s.is_ascii() {
+            return Err(());
+        }
+        let mut tag = [b' '; 4];
+        tag[..s.len()].copy_from_slice(s.as_bytes());
+        Ok(Tag(tag))
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for &b in &self.0 {
+            write!(f, "{}", b as char)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Tag({:?})", self.to_string())
+    }
+}
+
+impl Direction {
+    fn to_hb(self) -> hb_direction_t {
+        match self {
+            Direction::Ltr => HB_DIRECTION_LTR,
+            Direction::Rtl => HB_DIRECTION_RTL,
+            Direction::Ttb => HB_DIRECTION_TTB,
+            Direction::Btt => HB_DIRECTION_BTT
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = ();
+
+    /// Parse a direction from `ltr`, `rtl`, `ttb` or `btt`, ignoring case.
+    fn from_str(s: &str) -> Result<Direction, ()> {
+        match &*s.to_ascii_lowercase() {
+            "ltr" => Ok(Direction::Ltr),
+            "rtl" => Ok(Direction::Rtl),
+            "ttb" => Ok(Direction::Ttb),
+            "btt" => Ok(Direction::Btt),
+            _ => Err(())
+        }
+    }
+}
+
+impl Feature {
+    /// A feature applied to the entire string.
+    #[inline]
+    pub fn global(tag: Tag, value: u32) -> Feature {
+        Feature {
+            tag,
+            value,
+            start: 0,
+            end: usize::max_value()
+        }
+    }
+}
+
+impl FromStr for Feature {
+    type Err = ();
+
+    /// Parse a feature using Harfbuzz's syntax, e.g. `kern`, `-liga`, `ss01=1` or `aalt[3:5]=2`.
+    fn from_str(s: &str) -> Result<Feature, ()> {
+        unsafe {
+            let mut hb_feature = mem::zeroed::<hb_feature_t>();
+            match hb_feature_from_string(s.as_ptr() as *const c_char, s.len() as c_int, &mut hb_feature) {
+                0 => Err(()),
+                _ => Ok(Feature {
+                    tag: Tag::from_u32(hb_feature.tag),
+                    value: hb_feature.value,
+                    start: hb_feature.start as usize,
+                    end: match hb_feature.end {
+                        e if e == c_uint::max_value() => usize::max_value(),
+                        e => e as usize
+                    }
+                })
+            }
+        }
+    }
+}
+
+impl Variation {
+    #[inline]
+    pub fn new(tag: Tag, value: f32) -> Variation {
+        Variation{ tag, value }
+    }
+}
+
 impl FaceSize {
+    /// Create a nominal size in 26.6 points.
     #[inline]
     pub fn new(width: u32, height: u32) -> FaceSize {
-        FaceSize{ width, height }
+        FaceSize::from_266(width, height)
+    }
+
+    /// Create a nominal size in 26.6 points.
+    #[inline]
+    pub fn from_266(width: u32, height: u32) -> FaceSize {
+        FaceSize {
+            width,
+            height,
+            request_type: SizeRequestType::Nominal,
+            pixels: false,
+            strike: None
+        }
+    }
+
+    /// Create a nominal size in points.
+    #[inline]
+    pub fn from_points(points: f32) -> FaceSize {
+        let size = (points * 64.0).round().max(0.0) as u32;
+        FaceSize::from_266(size, size)
+    }
+
+    /// Create a nominal size in whole pixels, like `FT_Set_Pixel_Sizes`.
+    #[inline]
+    pub fn from_pixels(pixels: u32) -> FaceSize {
+        FaceSize {
+            width: pixels * 64,
+            height: pixels * 64,
+            request_type: SizeRequestType::Nominal,
+            pixels: true,
+            strike: None
+        }
+    }
+
+    /// Select the embedded bitmap strike at `index` in `Face::strikes`, like `FT_Select_Size`.
+    #[inline]
+    pub fn from_strike(index: u32) -> FaceSize {
+        FaceSize::from_266(0, 0).with_strike(Some(StrikeSelection::Fixed(index)))
+    }
+
+    #[inline]
+    pub fn with_request_type(self, request_type: SizeRequestType) -> FaceSize {
+        FaceSize{ request_type, ..self }
+    }
+
+    #[inline]
+    pub fn with_strike(self, strike: Option<StrikeSelection>) -> FaceSize {
+        FaceSize{ strike, ..self }
+    }
+}
+
+impl SizeRequestType {
+    fn to_ft(self) -> ft::FT_Size_Request_Type {
+        match self {
+            SizeRequestType::Nominal => ft::FT_Size_Request_Type__FT_SIZE_REQUEST_TYPE_NOMINAL,
+            SizeRequestType::RealDim => ft::FT_Size_Request_Type__FT_SIZE_REQUEST_TYPE_REAL_DIM,
+            SizeRequestType::BBox => ft::FT_Size_Request_Type__FT_SIZE_REQUEST_TYPE_BBOX,
+            SizeRequestType::Cell => ft::FT_Size_Request_Type__FT_SIZE_REQUEST_TYPE_CELL,
+            SizeRequestType::Scales => ft::FT_Size_Request_Type__FT_SIZE_REQUEST_TYPE_SCALES
+        }
     }
 }
 
@@ -595,27 +1342,39 @@ impl From<GlyphMetrics266> for GlyphMetricsPx {
     }
 }
 
-impl Clone for FTLib {
-    fn clone(&self) -> FTLib {
-        unsafe{ ft::FT_Reference_Library(self.lib) };
-        FTLib {
-            lib: self.lib
-        }
-    }
-}
-
 impl<B> Clone for Face<B>
-    where B: StableDeref + Deref<Target=[u8]> + Clone + ?Sized
+    where B: StableDeref + Deref<Target=[u8]> + Clone
 {
     fn clone(&self) -> Face<B> {
-        let buf = self._font_buffer.clone();
-        Face::new(buf, unsafe{ (*self.ft_face).face_index as i32 }, &self._lib).unwrap()
+        self.try_clone().unwrap()
     }
 }
 
-impl Drop for FTLib {
+impl Drop for LibInner {
     fn drop(&mut self) {
-        unsafe{ ft::FT_Done_Library(self.lib) };
+        unsafe {
+            ft::FT_Done_Library(self.lib);
+            ft_alloc::free_mem_rec(self.memory);
+        }
+    }
+}
+
+impl fmt::Debug for FTLib {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FTLib")
+            .field("lib", &self.inner.lib)
+            .field("memory_usage", &self.memory_usage())
+            .field("memory_limit", &self.memory_limit())
+            .finish()
+    }
+}
+
+impl fmt::Debug for MemoryConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MemoryConfig")
+            .field("limit", &self.limit)
+            .field("allocator", &self.allocator.map(|_| "GlobalAlloc"))
+            .finish()
     }
 }
 
@@ -623,11 +1382,22 @@ impl<B: ?Sized> Drop for Face<B> {
     fn drop(&mut self) {
         unsafe {
             hb_font_destroy(self.hb_font);
+            let _lock = self._lib.lock();
             ft::FT_Done_Face(self.ft_face);
         }
     }
 }
 
+// FreeType libraries may be used from any thread, as long as face creation and destruction are
+// serialized, which `FTLib::lock` takes care of. Property changes need the only handle to the
+// library, so they can't race with faces loading or rendering glyphs.
+unsafe impl Send for LibInner {}
+unsafe impl Sync for LibInner {}
+// Faces and shapers exclusively own their FreeType and Harfbuzz objects, apart from the library
+// handle. They aren't `Sync` since FreeType faces can't be used from multiple threads at once.
+unsafe impl<B: ?Sized + Send> Send for Face<B> {}
+unsafe impl Send for Shaper {}
+
 impl Drop for Shaper {
     fn drop(&mut self) {
         unsafe {
@@ -646,131 +1416,3 @@ impl BreakType {
         }
     }
 }
-
-#[repr(C)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Error)]
-pub enum Error {
-    Ok = 0,
-    CannotOpenResource = 1,
-    UnknownFileFormat = 2,
-    InvalidFileFormat = 3,
-    InvalidVersion = 4,
-    LowerModuleVersion = 5,
-    InvalidArgument = 6,
-    UnimplementedFeature = 7,
-    InvalidTable = 8,
-    InvalidOffset = 9,
-    ArrayTooLarge = 10,
-    MissingModule = 11,
-    MissingProperty = 12,
-    InvalidGlyphIndex = 16,
-    InvalidCharacterCode = 17,
-    InvalidGlyphFormat = 18,
-    CannotRenderGlyph = 19,
-    InvalidOutline = 20,
-    InvalidComposite = 21,
-    TooManyHints = 22,
-    InvalidPixelSize = 23,
-    InvalidHandle = 32,
-    InvalidLibraryHandle = 33,
-    InvalidDriverHandle = 34,
-    InvalidFaceHandle = 35,
-    InvalidSizeHandle = 36,
-    InvalidSlotHandle = 37,
-    InvalidCharMapHandle = 38,
-    InvalidCacheHandle = 39,
-    InvalidStreamHandle = 40,
-
-    TooManyDrivers = 48,
-    TooManyExtensions = 49,
-
-    OutOfMemory = 64,
-    UnlistedObject = 65,
-
-    CannotOpenStream = 81,
-    InvalidStreamSeek = 82,
-    InvalidStreamSkip = 83,
-    InvalidStreamRead = 84,
-    InvalidStreamOperation = 85,
-    InvalidFrameOperation = 86,
-    NestedFrameAccess = 87,
-    InvalidFrameRead = 88,
-
-    RasterUninitialized = 96,
-    RasterCorrupted = 97,
-    RasterOverflow = 98,
-    RasterNegativeHeight = 99,
-
-    TooManyCaches = 112,
-
-    InvalidOpcode = 128,
-    TooFewArguments = 129,
-    StackOverflow = 130,
-    CodeOverflow = 131,
-    BadArgument = 132,
-    DivideByZero = 133,
-    InvalidReference = 134,
-    DebugOpCode = 135,
-    ENDFInExecStream = 136,
-    NestedDEFS = 137,
-    InvalidCodeRange = 138,
-    ExecutionTooLong = 139,
-    TooManyFunctionDefs = 140,
-    TooManyInstructionDefs = 141,
-    TableMissing = 142,
-    HorizHeaderMissing = 143,
-    LocationsMissing = 144,
-    NameTableMissing = 145,
-    CMapTableMissing = 146,
-    HmtxTableMissing = 147,
-    PostTableMissing = 148,
-    InvalidHorizMetrics = 149,
-    InvalidCharMapFormat = 150,
-    InvalidPPem = 151,
-    InvalidVertMetrics = 152,
-    CouldNotFindContext = 153,
-    InvalidPostTableFormat = 154,
-    InvalidPostTable = 155,
-    DEFInGlyfBytecode = 156,
-    MissingBitmap = 157,
-    SyntaxError = 160,
-    StackUnderflow = 161,
-    Ignore = 162,
-    NoUnicodeGlyphName = 163,
-    GlyphTooBig = 164,
-
-    MissingStartfontField = 176,
-    MissingFontField = 177,
-    MissingSizeField = 178,
-    MissingFontboundingboxField = 179,
-    MissingCharsField = 180,
-    MissingStartcharField = 181,
-    MissingEncodingField = 182,
-    MissingBbxField = 183,
-    BbxTooBig = 184,
-    CorruptedFontHeader = 185,
-    CorruptedFontGlyphs = 186,
-    Max = 187,
-}
-
-impl Error {
-    pub fn from_raw(err: FT_Error) -> Option<Error> {
-        let err_in_bounds = move |left, right| left <= err.0 && err.0 <= right;
-
-        // Make sure that the error is valid before transmuting it.
-        let eib =
-            err_in_bounds(0, 49) ||
-            err_in_bounds(64, 65) ||
-            err_in_bounds(81, 88) ||
-            err_in_bounds(96, 99) ||
-            err.0 == 112 ||
-            err_in_bounds(128, 164) ||
-            err_in_bounds(176, 187);
-
-        if eib {
-            Some(unsafe{ mem::transmute(err)})
-        } else {
-            None
-        }
-    }
-}
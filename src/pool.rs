@@ -0,0 +1,125 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use {Face, FaceError};
+
+use stable_deref_trait::StableDeref;
+
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+/// Hands out independent copies of a face, so that the same font can be shaped and rendered on
+/// several threads at once.
+///
+/// Faces get created on demand by cloning the face the pool was created with, and are returned to
+/// the pool when the `PooledFace` handle is dropped. Returned faces have their variation
+/// coordinates reset to the defaults, like a fresh clone. Other state, such as the last requested
+/// size, carries over to the next user, but every method that depends on it takes it explicitly.
+pub struct FacePool<B>
+    where B: StableDeref + Deref<Target=[u8]> + Clone
+{
+    prototype: Mutex<Face<B>>,
+    idle: Mutex<Vec<Face<B>>>
+}
+
+/// A face checked out of a `FacePool`.
+pub struct PooledFace<'a, B: 'a>
+    where B: StableDeref + Deref<Target=[u8]> + Clone
+{
+    face: Option<Face<B>>,
+    pool: &'a FacePool<B>,
+    /// The face's variation serial when it was checked out.
+    variation_serial: u64
+}
+
+impl<B> FacePool<B>
+    where B: StableDeref + Deref<Target=[u8]> + Clone
+{
+    pub fn new(face: Face<B>) -> FacePool<B> {
+        FacePool {
+            prototype: Mutex::new(face),
+            idle: Mutex::new(Vec::new())
+        }
+    }
+
+    /// Take a face out of the pool, creating a new one if none are available.
+    pub fn get(&self) -> Result<PooledFace<B>, FaceError> {
+        let idle_face = self.idle.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        let face = match idle_face {
+            Some(face) => face,
+            None => self.prototype.lock().unwrap_or_else(|e| e.into_inner()).try_clone()?
+        };
+
+        Ok(PooledFace {
+            variation_serial: face.variation_serial(),
+            face: Some(face),
+            pool: self
+        })
+    }
+
+    /// The number of faces sitting in the pool, waiting to be checked out.
+    pub fn idle_count(&self) -> usize {
+        self.idle.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Destroy every face currently sitting in the pool.
+    pub fn shrink(&self) {
+        let idle = mem::replace(&mut *self.idle.lock().unwrap_or_else(|e| e.into_inner()), Vec::new());
+        drop(idle);
+    }
+}
+
+impl<'a, B> PooledFace<'a, B>
+    where B: StableDeref + Deref<Target=[u8]> + Clone
+{
+    /// Take the face out of the pool for good, so that it doesn't get returned on drop.
+    pub fn detach(mut self) -> Face<B> {
+        self.face.take().unwrap()
+    }
+}
+
+impl<'a, B> Deref for PooledFace<'a, B>
+    where B: StableDeref + Deref<Target=[u8]> + Clone
+{
+    type Target = Face<B>;
+
+    #[inline]
+    fn deref(&self) -> &Face<B> {
+        self.face.as_ref().unwrap()
+    }
+}
+
+impl<'a, B> DerefMut for PooledFace<'a, B>
+    where B: StableDeref + Deref<Target=[u8]> + Clone
+{
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Face<B> {
+        self.face.as_mut().unwrap()
+    }
+}
+
+impl<'a, B> Drop for PooledFace<'a, B>
+    where B: StableDeref + Deref<Target=[u8]> + Clone
+{
+    fn drop(&mut self) {
+        if let Some(mut face) = self.face.take() {
+            // A face whose variations can't be reset gets destroyed instead of returned.
+            if face.variation_serial() != self.variation_serial && face.set_variations(&[]).is_err() {
+                return;
+            }
+            self.pool.idle.lock().unwrap_or_else(|e| e.into_inner()).push(face);
+        }
+    }
+}
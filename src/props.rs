@@ -0,0 +1,229 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use {FTLib, LcdFilter, Error};
+use ft::{self, FT_UInt, FT_Int, FT_Bool};
+
+use std::os::raw::{c_void, c_char};
+
+/// The bytecode interpreter used for hinting TrueType fonts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TrueTypeInterpreter {
+    /// The classic interpreter, which hints in both directions like Windows 98.
+    V35,
+    /// The "Infinality" subpixel interpreter. Not available in newer FreeType versions.
+    V38,
+    /// The minimal subpixel interpreter, which only hints vertically. FreeType's default.
+    V40
+}
+
+/// The hinting engine used by the CFF, Type 1 and CID font drivers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HintingEngine {
+    FreeType,
+    Adobe
+}
+
+/// The FreeType modules that support stem darkening.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DarkeningModule {
+    Cff,
+    Type1,
+    T1Cid,
+    Autofitter
+}
+
+/// The FreeType drivers that can switch between hinting engines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HintingModule {
+    Cff,
+    Type1,
+    T1Cid
+}
+
+/// Stem darkening parameters, as four `(stem width, darkening amount)` control points. Stem widths
+/// are in font units, and darkening amounts are in font units scaled to a 1000 unit em.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DarkeningParameters {
+    pub points: [(i32, i32); 4]
+}
+
+impl FTLib {
+    pub fn set_truetype_interpreter(&mut self, interpreter: TrueTypeInterpreter) -> Result<(), Error> {
+        let version: FT_UInt = match interpreter {
+            TrueTypeInterpreter::V35 => 35,
+            TrueTypeInterpreter::V38 => 38,
+            TrueTypeInterpreter::V40 => 40
+        };
+        self.set_property(b"truetype\0", b"interpreter-version\0", &version)
+    }
+
+    /// Enable or disable the autofitter's warping mode, which snaps stems to the pixel grid by
+    /// slightly scaling and translating glyphs. Only available in FreeType builds that enable it,
+    /// and FreeType 2.11 removed it entirely. Returns `Error::MissingProperty` when it's missing.
+    pub fn set_autofit_warping(&mut self, warping: bool) -> Result<(), Error> {
+        self.set_property(b"autofitter\0", b"warping\0", &(warping as FT_Bool))
+    }
+
+    /// Set the script the autofitter assumes for glyphs that aren't covered by any of its script
+    /// modules.
+    ///
+    /// FreeType identifies scripts by their index in its internal `AF_Script` enumeration, which
+    /// is generated from `src/autofit/afscript.h`. The indices change between FreeType versions, so
+    /// look `script` up in the `afscript.h` of the FreeType being linked against.
+    pub fn set_autofit_default_script(&mut self, script: u32) -> Result<(), Error> {
+        self.set_property(b"autofitter\0", b"default-script\0", &(script as FT_UInt))
+    }
+
+    /// Get the index of the script the autofitter assumes for uncovered glyphs. FreeType defaults
+    /// to Latin.
+    pub fn autofit_default_script(&self) -> Result<u32, Error> {
+        let mut script: FT_UInt = 0;
+        self.get_property(b"autofitter\0", b"default-script\0", &mut script)?;
+        Ok(script as u32)
+    }
+
+    pub fn set_hinting_engine(&mut self, module: HintingModule, engine: HintingEngine) -> Result<(), Error> {
+        let engine: FT_UInt = match engine {
+            HintingEngine::FreeType => 0,
+            HintingEngine::Adobe => 1
+        };
+        self.set_property(module.name(), b"hinting-engine\0", &engine)
+    }
+
+    /// Enable or disable stem darkening, which emboldens glyphs at small sizes to counter the
+    /// thinning effect of linear blending.
+    pub fn set_stem_darkening(&mut self, module: DarkeningModule, darkening: bool) -> Result<(), Error> {
+        // FreeType exposes this as a `no-stem-darkening` property, hence the negation.
+        self.set_property(module.name(), b"no-stem-darkening\0", &(!darkening as FT_Bool))
+    }
+
+    pub fn set_darkening_parameters(&mut self, module: DarkeningModule, params: DarkeningParameters) -> Result<(), Error> {
+        let mut raw_params: [FT_Int; 8] = [0; 8];
+        for (i, &(stem_width, darkening)) in params.points.iter().enumerate() {
+            raw_params[i * 2] = stem_width as FT_Int;
+            raw_params[i * 2 + 1] = darkening as FT_Int;
+        }
+        self.set_property(module.name(), b"darkening-parameters\0", &raw_params)
+    }
+
+    /// Set the filter used when rendering glyphs with `RenderMode::Lcd` or `RenderMode::LcdV`.
+    ///
+    /// FreeType doesn't filter LCD-rendered glyphs by default, which produces strong color fringes,
+    /// so this should be set to something other than `LcdFilter::None` before LCD rendering.
+    pub fn set_lcd_filter(&mut self, filter: LcdFilter) -> Result<(), Error> {
+        let lib = self.raw_exclusive()?;
+        let error = unsafe {
+            match filter {
+                LcdFilter::None => ft::FT_Library_SetLcdFilter(lib, ft::FT_LcdFilter__FT_LCD_FILTER_NONE as _),
+                LcdFilter::Default => ft::FT_Library_SetLcdFilter(lib, ft::FT_LcdFilter__FT_LCD_FILTER_DEFAULT as _),
+                LcdFilter::Light => ft::FT_Library_SetLcdFilter(lib, ft::FT_LcdFilter__FT_LCD_FILTER_LIGHT as _),
+                LcdFilter::Legacy => ft::FT_Library_SetLcdFilter(lib, ft::FT_LcdFilter__FT_LCD_FILTER_LEGACY as _),
+                LcdFilter::Custom(mut weights) => ft::FT_Library_SetLcdFilterWeights(lib, weights.as_mut_ptr())
+            }
+        };
+        Error::check(error)
+    }
+
+    /// Set a FreeType module property. `module` and `property` must be nul-terminated.
+    fn set_property<T>(&mut self, module: &[u8], property: &[u8], value: &T) -> Result<(), Error> {
+        let lib = self.raw_exclusive()?;
+        let error = unsafe {
+            ft::FT_Property_Set(
+                lib,
+                module.as_ptr() as *const c_char,
+                property.as_ptr() as *const c_char,
+                value as *const T as *const c_void
+            )
+        };
+        Error::check(error)
+    }
+
+    /// Get a FreeType module property. `module` and `property` must be nul-terminated.
+    fn get_property<T>(&self, module: &[u8], property: &[u8], value: &mut T) -> Result<(), Error> {
+        let _lock = self.lock();
+        let error = unsafe {
+            ft::FT_Property_Get(
+                self.raw(),
+                module.as_ptr() as *const c_char,
+                property.as_ptr() as *const c_char,
+                value as *mut T as *mut c_void
+            )
+        };
+        Error::check(error)
+    }
+}
+
+impl DarkeningModule {
+    fn name(self) -> &'static [u8] {
+        match self {
+            DarkeningModule::Cff => b"cff\0",
+            DarkeningModule::Type1 => b"type1\0",
+            DarkeningModule::T1Cid => b"t1cid\0",
+            DarkeningModule::Autofitter => b"autofitter\0"
+        }
+    }
+}
+
+impl HintingModule {
+    fn name(self) -> &'static [u8] {
+        match self {
+            HintingModule::Cff => b"cff\0",
+            HintingModule::Type1 => b"type1\0",
+            HintingModule::T1Cid => b"t1cid\0"
+        }
+    }
+}
+
+impl Default for DarkeningParameters {
+    /// FreeType's default darkening parameters.
+    fn default() -> DarkeningParameters {
+        DarkeningParameters {
+            points: [(500, 400), (1000, 275), (1667, 275), (2333, 0)]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Face;
+
+    #[test]
+    fn autofit_default_script() {
+        let mut lib = FTLib::new();
+        let default = lib.autofit_default_script().unwrap();
+
+        lib.set_autofit_default_script(default + 1).unwrap();
+        assert_eq!(default + 1, lib.autofit_default_script().unwrap());
+        lib.set_autofit_default_script(default).unwrap();
+        assert_eq!(default, lib.autofit_default_script().unwrap());
+    }
+
+    #[test]
+    fn properties_need_exclusive_library() {
+        let mut lib = FTLib::new();
+        let script = lib.autofit_default_script().unwrap();
+
+        let clone = lib.clone();
+        assert_eq!(Err(Error::LibraryInUse), lib.set_autofit_default_script(script));
+        drop(clone);
+
+        let face = Face::new(&include_bytes!("../DejaVuSans.ttf")[..], 0, &lib).unwrap();
+        assert_eq!(Err(Error::LibraryInUse), lib.set_autofit_default_script(script));
+        drop(face);
+
+        assert_eq!(Ok(()), lib.set_autofit_default_script(script));
+    }
+}
@@ -0,0 +1,467 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use {Face, FaceSize, DPI, LoadFlags, RenderMode, ShapedGlyph, Bitmap, PixelMode, SubpixelOrder, Error, Operation, FaceError};
+use gamma::{BlendOptions, BlendSpace, CoverageLut, srgb_to_linear, linear_to_srgb};
+
+use cgmath::Point2;
+use cgmath_geometry::D2;
+use cgmath_geometry::rect::{DimsBox, GeoBox};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+    A8,
+    Rgba8
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rgba {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8
+}
+
+/// A caller-owned image that text gets composited into.
+///
+/// `pitch` is the number of bytes between the start of one row and the start of the next, and
+/// must be at least `dims.width() * format.bytes_per_pixel()`, and `buffer` must hold at least
+/// `pitch * dims.height()` bytes. Rendering into a target that doesn't fails with
+/// `Error::InvalidArgument`. Rgba8 images are treated as having straight (non-premultiplied)
+/// alpha.
+#[derive(Debug)]
+pub struct RenderTarget<'a> {
+    pub buffer: &'a mut [u8],
+    pub dims: DimsBox<D2, u32>,
+    pub pitch: usize,
+    pub format: ImageFormat
+}
+
+/// A single source pixel, read out of a glyph bitmap.
+#[derive(Debug, Clone, Copy)]
+enum SrcPixel {
+    /// Glyph coverage, to be tinted with the text color.
+    Coverage(u8),
+    /// Per-subpixel coverage from an LCD glyph, in RGB order.
+    Subpixel([u8; 3]),
+    /// Premultiplied color from a color glyph.
+    Color(Rgba)
+}
+
+/// Render a run of shaped glyphs into `target`, alpha-blending each glyph over the existing
+/// contents of the image.
+///
+/// `origin` is the position of the pen at the start of the baseline, in pixels from the top-left
+/// corner of the target. Glyphs that fall partially or entirely outside of the target are clipped.
+pub fn render_text_to_buffer<B: ?Sized, I>(
+    glyphs: I,
+    face: &mut Face<B>,
+    face_size: FaceSize,
+    dpi: DPI,
+    load_flags: LoadFlags,
+    render_mode: RenderMode,
+    target: &mut RenderTarget,
+    origin: Point2<i32>,
+    color: Rgba,
+    blend: BlendOptions
+) -> Result<(), FaceError>
+    where I: IntoIterator<Item=ShapedGlyph>
+{
+    if !target.is_valid() {
+        return Err(Error::InvalidArgument.during(Operation::RenderGlyph));
+    }
+
+    let paint = Paint::new(color, blend);
+    for glyph in glyphs {
+        let mut slot = face.load_glyph(glyph.glyph_index, face_size, dpi, load_flags, render_mode)?;
+        let scaled_image;
+        let (bitmap, bitmap_offset) = match slot.bitmap_scale() {
+            scale if scale != 1.0 => {
+                scaled_image = slot.render_image(render_mode)?;
+                (scaled_image.bitmap.as_bitmap(), scaled_image.bitmap_offset)
+            },
+            _ => (slot.render_glyph(render_mode)?, slot.bitmap_offset())
+        };
+
+        // Harfbuzz positions are Y-up, while the target image is Y-down. Glyphs whose position
+        // overflows are far outside of any target, so they get skipped.
+        let x = origin.x.checked_add(glyph.pos.x).and_then(|x| x.checked_add(bitmap_offset.x));
+        let y = origin.y.checked_sub(glyph.pos.y).and_then(|y| y.checked_sub(bitmap_offset.y));
+        let glyph_origin = match (x, y) {
+            (Some(x), Some(y)) => Point2::new(x, y),
+            _ => continue
+        };
+        composite_with_paint(&bitmap, target, glyph_origin, &paint);
+    }
+
+    Ok(())
+}
+
+/// Alpha-blend a single glyph bitmap into `target`, with the bitmap's top-left corner placed at
+/// `dst_origin`.
+pub fn composite_bitmap(bitmap: &Bitmap, target: &mut RenderTarget, dst_origin: Point2<i32>, color: Rgba, blend: BlendOptions) -> Result<(), Error> {
+    if !target.is_valid() {
+        return Err(Error::InvalidArgument);
+    }
+    composite_with_paint(bitmap, target, dst_origin, &Paint::new(color, blend));
+    Ok(())
+}
+
+/// Everything about how glyphs get drawn that stays constant across a run of text.
+struct Paint {
+    color: Rgba,
+    lut: CoverageLut,
+    space: BlendSpace,
+    subpixel_order: SubpixelOrder
+}
+
+impl Paint {
+    fn new(color: Rgba, blend: BlendOptions) -> Paint {
+        Paint {
+            color,
+            lut: blend.coverage.lut(color),
+            space: blend.space,
+            subpixel_order: blend.subpixel_order
+        }
+    }
+}
+
+fn composite_with_paint(bitmap: &Bitmap, target: &mut RenderTarget, dst_origin: Point2<i32>, paint: &Paint) {
+    let src_dims = bitmap.pixel_dims();
+    let color = paint.color;
+
+    // Clip the glyph rectangle against the target image.
+    let x_start = 0i32.saturating_sub(dst_origin.x).max(0);
+    let y_start = 0i32.saturating_sub(dst_origin.y).max(0);
+    let x_end = (src_dims.width() as i32).min((target.dims.width() as i32).saturating_sub(dst_origin.x));
+    let y_end = (src_dims.height() as i32).min((target.dims.height() as i32).saturating_sub(dst_origin.y));
+
+    let bpp = target.format.bytes_per_pixel();
+    for y in y_start..y_end {
+        let dst_row_offset = (dst_origin.y + y) as usize * target.pitch;
+        for x in x_start..x_end {
+            let dst_offset = dst_row_offset + (dst_origin.x + x) as usize * bpp;
+            let dst = &mut target.buffer[dst_offset..dst_offset + bpp];
+
+            let (src_color, src_alpha) = match src_pixel(bitmap, x as u32, y as u32, paint.subpixel_order) {
+                SrcPixel::Coverage(coverage) => {
+                    let alpha = mul_div255(paint.lut.get(coverage) as u32, color.a as u32);
+                    ([color.r, color.g, color.b], [alpha; 3])
+                },
+                SrcPixel::Subpixel(subpixels) => {
+                    let alpha = |s: u8| mul_div255(paint.lut.get(s) as u32, color.a as u32);
+                    ([color.r, color.g, color.b], [alpha(subpixels[0]), alpha(subpixels[1]), alpha(subpixels[2])])
+                },
+                // Color glyphs keep their own color, and only take the alpha of the text color.
+                SrcPixel::Color(src) => {
+                    let unpremul = |c: u8| match src.a {
+                        0 => 0,
+                        a => ((c as u32 * 255 + a as u32 / 2) / a as u32).min(255) as u8
+                    };
+                    ([unpremul(src.r), unpremul(src.g), unpremul(src.b)], [mul_div255(src.a as u32, color.a as u32); 3])
+                }
+            };
+
+            match target.format {
+                ImageFormat::A8 => {
+                    let alpha = (src_alpha[0] + src_alpha[1] + src_alpha[2]) / 3;
+                    dst[0] = blend_alpha(alpha, dst[0] as u32) as u8;
+                },
+                ImageFormat::Rgba8 => match paint.space {
+                    BlendSpace::Srgb => blend_rgba_srgb(src_color, src_alpha, dst),
+                    BlendSpace::Linear => blend_rgba_linear(src_color, src_alpha, dst)
+                }
+            }
+        }
+    }
+}
+
+fn src_pixel(bitmap: &Bitmap, x: u32, y: u32, subpixel_order: SubpixelOrder) -> SrcPixel {
+    match bitmap.pixel_mode {
+        PixelMode::Lcd |
+        PixelMode::LcdV => SrcPixel::Subpixel(bitmap.subpixels(x, y, subpixel_order)),
+        PixelMode::Bgra => {
+            let bgra = &bitmap.row(y)[x as usize * 4..x as usize * 4 + 4];
+            SrcPixel::Color(Rgba{ r: bgra[2], g: bgra[1], b: bgra[0], a: bgra[3] })
+        },
+        _ => SrcPixel::Coverage(bitmap.coverage(x, y))
+    }
+}
+
+/// Composite a straight-alpha source pixel over a straight-alpha destination pixel, operating
+/// directly on the sRGB-encoded values. Each color channel has its own source alpha, to support
+/// subpixel rendering.
+fn blend_rgba_srgb(src: [u8; 3], src_a: [u32; 3], dst: &mut [u8]) {
+    let dst_a = dst[3] as u32;
+    let mut out_a_max = 0;
+    for i in 0..3 {
+        let dst_a_scaled = mul_div255(dst_a, 255 - src_a[i]);
+        let out_a = src_a[i] + dst_a_scaled;
+        if out_a != 0 {
+            let premul = src[i] as u32 * src_a[i] + dst[i] as u32 * dst_a_scaled;
+            dst[i] = ((premul + out_a / 2) / out_a).min(255) as u8;
+        }
+        out_a_max = out_a_max.max(out_a);
+    }
+    dst[3] = out_a_max as u8;
+}
+
+/// Composite a straight-alpha source pixel over a straight-alpha destination pixel, converting the
+/// colors into linear light for the blend.
+fn blend_rgba_linear(src: [u8; 3], src_a: [u32; 3], dst: &mut [u8]) {
+    let dst_a = dst[3] as f32 / 255.0;
+    let mut out_a_max: f32 = 0.0;
+    for i in 0..3 {
+        let src_a = src_a[i] as f32 / 255.0;
+        let dst_a_scaled = dst_a * (1.0 - src_a);
+        let out_a = src_a + dst_a_scaled;
+        if out_a != 0.0 {
+            let premul = srgb_to_linear(src[i]) * src_a + srgb_to_linear(dst[i]) * dst_a_scaled;
+            dst[i] = linear_to_srgb(premul / out_a);
+        }
+        out_a_max = out_a_max.max(out_a);
+    }
+    dst[3] = (out_a_max * 255.0 + 0.5) as u8;
+}
+
+#[inline]
+fn blend_alpha(src_a: u32, dst_a: u32) -> u32 {
+    src_a + mul_div255(dst_a, 255 - src_a)
+}
+
+/// Compute `a * b / 255`, rounded to the nearest integer.
+#[inline]
+fn mul_div255(a: u32, b: u32) -> u32 {
+    let t = a * b + 128;
+    (t + (t >> 8)) >> 8
+}
+
+impl ImageFormat {
+    #[inline]
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            ImageFormat::A8 => 1,
+            ImageFormat::Rgba8 => 4
+        }
+    }
+}
+
+impl Rgba {
+    #[inline]
+    pub fn new(r: u8, g: u8, b: u8, a: u8) -> Rgba {
+        Rgba{ r, g, b, a }
+    }
+}
+
+impl<'a> RenderTarget<'a> {
+    /// Create a render target over a tightly-packed image buffer.
+    #[inline]
+    pub fn new(buffer: &'a mut [u8], dims: DimsBox<D2, u32>, format: ImageFormat) -> RenderTarget<'a> {
+        RenderTarget {
+            pitch: dims.width() as usize * format.bytes_per_pixel(),
+            buffer,
+            dims,
+            format
+        }
+    }
+
+    /// Whether `pitch` and `buffer` are large enough for `dims`, and `dims` is small enough for
+    /// pixel positions to fit in an `i32`.
+    fn is_valid(&self) -> bool {
+        let row_len = (self.dims.width() as usize).checked_mul(self.format.bytes_per_pixel());
+        let len = self.pitch.checked_mul(self.dims.height() as usize);
+        match (row_len, len) {
+            (Some(row_len), Some(len)) =>
+                row_len <= self.pitch &&
+                len <= self.buffer.len() &&
+                self.dims.width() <= i32::max_value() as u32 &&
+                self.dims.height() <= i32::max_value() as u32,
+            _ => false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Rgba = Rgba{ r: 255, g: 255, b: 255, a: 255 };
+    const BLACK: Rgba = Rgba{ r: 0, g: 0, b: 0, a: 255 };
+
+    fn gray(coverage: &[u8], width: u32) -> Bitmap {
+        Bitmap {
+            dims: DimsBox::new2(width, coverage.len() as u32 / width),
+            pitch: width as i32,
+            buffer: coverage,
+            pixel_mode: PixelMode::Gray
+        }
+    }
+
+    fn composite_a8(bitmap: &Bitmap, dims: (u32, u32), origin: (i32, i32)) -> Vec<u8> {
+        let mut buffer = vec![0; (dims.0 * dims.1) as usize];
+        composite_bitmap(
+            bitmap,
+            &mut RenderTarget::new(&mut buffer, DimsBox::new2(dims.0, dims.1), ImageFormat::A8),
+            Point2::new(origin.0, origin.1),
+            BLACK,
+            BlendOptions::NAIVE
+        ).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn clip_edges() {
+        let full = [255; 4];
+        let bitmap = gray(&full, 2);
+        assert_eq!(vec![255, 0, 0, 0, 0, 0, 0, 0, 0], composite_a8(&bitmap, (3, 3), (-1, -1)));
+        assert_eq!(vec![0, 0, 0, 0, 0, 0, 0, 0, 255], composite_a8(&bitmap, (3, 3), (2, 2)));
+        assert_eq!(vec![0, 0, 0, 0, 0, 0, 0, 255, 255], composite_a8(&bitmap, (3, 3), (1, 2)));
+        assert_eq!(vec![0, 255, 255, 0, 255, 255, 0, 0, 0], composite_a8(&bitmap, (3, 3), (1, 0)));
+
+        // Entirely outside of the target.
+        for &origin in &[(3, 0), (0, 3), (-2, 0), (0, -2), (i32::min_value(), i32::min_value()), (i32::max_value(), i32::max_value())] {
+            assert_eq!(vec![0; 9], composite_a8(&bitmap, (3, 3), origin));
+        }
+    }
+
+    #[test]
+    fn clip_to_pitch() {
+        // Bytes past the end of each row belong to the caller, and mustn't be touched.
+        let full = [255; 9];
+        let mut buffer = vec![7; 2 * 4];
+        {
+            let mut target = RenderTarget {
+                buffer: &mut buffer,
+                dims: DimsBox::new2(2, 2),
+                pitch: 4,
+                format: ImageFormat::A8
+            };
+            composite_bitmap(&gray(&full, 3), &mut target, Point2::new(0, 0), BLACK, BlendOptions::NAIVE).unwrap();
+        }
+        assert_eq!(vec![255, 255, 7, 7, 255, 255, 7, 7], buffer);
+    }
+
+    #[test]
+    fn invalid_target() {
+        let full = [255; 4];
+        let mut buffer = vec![0; 8];
+        let targets = [
+            // Too short for every row.
+            (DimsBox::new2(3, 3), 3),
+            // A pitch shorter than a row.
+            (DimsBox::new2(4, 2), 3),
+            (DimsBox::new2(1, u32::max_value()), usize::max_value())
+        ];
+        for &(dims, pitch) in &targets {
+            let mut target = RenderTarget{ buffer: &mut buffer, dims, pitch, format: ImageFormat::A8 };
+            assert_eq!(
+                Err(Error::InvalidArgument),
+                composite_bitmap(&gray(&full, 2), &mut target, Point2::new(0, 0), BLACK, BlendOptions::NAIVE)
+            );
+        }
+        assert_eq!(vec![0; 8], buffer);
+    }
+
+    #[test]
+    fn coverage_and_color_alpha() {
+        let coverage = [0, 64, 128, 255];
+        assert_eq!(vec![0, 64, 128, 255], composite_a8(&gray(&coverage, 4), (4, 1), (0, 0)));
+
+        let mut buffer = vec![0; 4];
+        composite_bitmap(
+            &gray(&coverage, 4),
+            &mut RenderTarget::new(&mut buffer, DimsBox::new2(4, 1), ImageFormat::A8),
+            Point2::new(0, 0),
+            Rgba::new(0, 0, 0, 128),
+            BlendOptions::NAIVE
+        ).unwrap();
+        assert_eq!(vec![0, 32, 64, 128], buffer);
+    }
+
+    #[test]
+    fn mul_div255_rounds() {
+        for a in 0..256 {
+            for b in 0..256 {
+                assert_eq!((a * b + 127) / 255, mul_div255(a, b), "{} * {} / 255", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn blend_srgb() {
+        // Fully transparent sources leave the destination alone, and opaque ones replace it.
+        let mut dst = [10, 20, 30, 40];
+        blend_rgba_srgb([255, 255, 255], [0; 3], &mut dst);
+        assert_eq!([10, 20, 30, 40], dst);
+        blend_rgba_srgb([1, 2, 3], [255; 3], &mut dst);
+        assert_eq!([1, 2, 3, 255], dst);
+
+        // Half-transparent red over opaque white.
+        let mut dst = [255, 255, 255, 255];
+        blend_rgba_srgb([255, 0, 0], [128; 3], &mut dst);
+        assert_eq!([255, 127, 127, 255], dst);
+
+        // Over a transparent destination, the source color is kept as-is.
+        let mut dst = [0, 0, 0, 0];
+        blend_rgba_srgb([255, 0, 0], [128; 3], &mut dst);
+        assert_eq!([255, 0, 0, 128], dst);
+
+        // Subpixel coverage blends each channel separately.
+        let mut dst = [255, 255, 255, 255];
+        blend_rgba_srgb([0, 0, 0], [255, 0, 128], &mut dst);
+        assert_eq!([0, 255, 127, 255], dst);
+    }
+
+    #[test]
+    fn blend_linear() {
+        let mut dst = [10, 20, 30, 40];
+        blend_rgba_linear([255, 255, 255], [0; 3], &mut dst);
+        assert_eq!([10, 20, 30, 40], dst);
+
+        let mut dst = [255, 255, 255, 255];
+        blend_rgba_linear([0, 0, 0], [255; 3], &mut dst);
+        assert_eq!([0, 0, 0, 255], dst);
+
+        // Half-transparent black over white is half as bright in linear light, which is much
+        // lighter than sRGB's 50% gray.
+        let mut dst = [255, 255, 255, 255];
+        blend_rgba_linear([0, 0, 0], [128; 3], &mut dst);
+        for &c in &dst[..3] {
+            assert!(186 <= c && c <= 189, "{:?}", dst);
+        }
+        assert_eq!(255, dst[3]);
+
+        let mut dst = [0, 0, 0, 0];
+        blend_rgba_linear([200, 100, 50], [128; 3], &mut dst);
+        assert_eq!([200, 100, 50, 128], dst);
+    }
+
+    #[test]
+    fn blend_rgba_target() {
+        let full = [255; 1];
+        let mut buffer = vec![255; 4];
+        composite_bitmap(
+            &gray(&full, 1),
+            &mut RenderTarget::new(&mut buffer, DimsBox::new2(1, 1), ImageFormat::Rgba8),
+            Point2::new(0, 0),
+            Rgba::new(255, 0, 0, 128),
+            BlendOptions::NAIVE
+        ).unwrap();
+        assert_eq!(vec![255, 127, 127, 255], buffer);
+
+        let mut buffer = vec![255; 4];
+        composite_bitmap(&gray(&full, 1), &mut RenderTarget::new(&mut buffer, DimsBox::new2(1, 1), ImageFormat::Rgba8), Point2::new(0, 0), WHITE, BlendOptions::NAIVE).unwrap();
+        assert_eq!(vec![255; 4], buffer);
+    }
+}
@@ -0,0 +1,542 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use {Face, ShapedGlyphIter};
+
+use std::{fmt, error};
+use std::fmt::Write;
+
+/// A glyph, identified either by its index or by its name.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GlyphRef {
+    Id(u32),
+    Name(String)
+}
+
+/// A single shaped glyph, with positions exactly as Harfbuzz produced them rather than rounded to
+/// whole pixels like `ShapedGlyph`'s. Positions are in 26.6 pixels.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct GlyphRecord {
+    pub glyph: GlyphRef,
+    pub cluster: u32,
+    pub x_advance: i32,
+    pub y_advance: i32,
+    pub x_offset: i32,
+    pub y_offset: i32
+}
+
+/// The buffer serialization formats understood by Harfbuzz, and by `hb-shape`'s
+/// `--output-format` option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SerializeFormat {
+    /// `[gid1=0+1200|gid2=1@-50,0+600]`
+    Text,
+    /// `[{"g":"gid1","cl":0,"dx":0,"dy":0,"ax":1200,"ay":0}]`
+    Json
+}
+
+bitflags!{
+    /// Fields to leave out of serialized output, matching `hb-shape`'s `--no-*` options.
+    pub struct SerializeFlags: u32 {
+        const NO_CLUSTERS = 1 << 0;
+        const NO_POSITIONS = 1 << 1;
+        const NO_GLYPH_NAMES = 1 << 2;
+        const NO_ADVANCES = 1 << 3;
+    }
+}
+
+/// An error encountered while parsing serialized glyphs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ParseGlyphsError {
+    /// The byte offset into the input at which parsing failed.
+    pub offset: usize,
+    pub expected: &'static str
+}
+
+impl GlyphRef {
+    /// Find the index of the glyph this refers to in `face`. Names are looked up with
+    /// `Face::glyph_from_name`, so `uni0041`-style names resolve even if the font doesn't name its
+    /// glyphs.
+    pub fn resolve<B: ?Sized>(&self, face: &Face<B>) -> Option<u32> {
+        match *self {
+            GlyphRef::Id(id) => Some(id),
+            GlyphRef::Name(ref name) => face.glyph_from_name(name)
+        }
+    }
+}
+
+impl<'a> ShapedGlyphIter<'a> {
+    /// Collect the remaining glyphs as unrounded `GlyphRecord`s.
+    pub fn into_records(self) -> Vec<GlyphRecord> {
+        self.glyph_iter.map(|(pos, info)| GlyphRecord {
+            glyph: GlyphRef::Id(info.codepoint),
+            cluster: info.cluster,
+            x_advance: pos.x_advance,
+            y_advance: pos.y_advance,
+            x_offset: pos.x_offset,
+            y_offset: pos.y_offset
+        }).collect()
+    }
+}
+
+/// Serialize glyphs in one of Harfbuzz's buffer formats, using the same syntax as `hb-shape`.
+///
+/// Positions are written as they're stored in the records, in 26.6 pixels, while `hb-shape` writes
+/// font units by default. To compare against `hb-shape`, run it with `--font-size` set to the
+/// face's size in pixels and `--font-subpixel-bits=6`.
+///
+/// Glyphs without names get written as `gid<index>`, like Harfbuzz does. If `NO_GLYPH_NAMES` is
+/// set, glyphs referred to by a name `face` doesn't contain get written as glyph 0. The text format
+/// writes names as they are, so a name containing one of its separators can't be parsed back,
+/// while the JSON format escapes them.
+pub fn serialize_glyphs<B: ?Sized>(records: &[GlyphRecord], face: &Face<B>, format: SerializeFormat, flags: SerializeFlags) -> String {
+    let mut out = String::new();
+    out.push('[');
+
+    for (i, record) in records.iter().enumerate() {
+        let glyph = match (&record.glyph, flags.contains(SerializeFlags::NO_GLYPH_NAMES)) {
+            (&GlyphRef::Id(id), false) => Err(face.glyph_name(id).unwrap_or_else(|| format!("gid{}", id))),
+            (GlyphRef::Name(name), false) => Err(name.clone()),
+            (glyph_ref, true) => Ok(glyph_ref.resolve(face).unwrap_or(0))
+        };
+
+        match format {
+            SerializeFormat::Text => {
+                if i != 0 {
+                    out.push('|');
+                }
+                match glyph {
+                    Ok(id) => write!(out, "{}", id).unwrap(),
+                    Err(name) => out.push_str(&name)
+                }
+                if !flags.contains(SerializeFlags::NO_CLUSTERS) {
+                    write!(out, "={}", record.cluster).unwrap();
+                }
+                if !flags.contains(SerializeFlags::NO_POSITIONS) {
+                    if record.x_offset != 0 || record.y_offset != 0 {
+                        write!(out, "@{},{}", record.x_offset, record.y_offset).unwrap();
+                    }
+                    if !flags.contains(SerializeFlags::NO_ADVANCES) {
+                        write!(out, "+{}", record.x_advance).unwrap();
+                        if record.y_advance != 0 {
+                            write!(out, ",{}", record.y_advance).unwrap();
+                        }
+                    }
+                }
+            },
+            SerializeFormat::Json => {
+                if i != 0 {
+                    out.push(',');
+                }
+                match glyph {
+                    Ok(id) => write!(out, "{{\"g\":{}", id).unwrap(),
+                    Err(name) => {
+                        out.push_str("{\"g\":\"");
+                        for c in name.chars() {
+                            match c {
+                                '"' | '\\' => {
+                                    out.push('\\');
+                                    out.push(c);
+                                },
+                                c if (c as u32) < 0x20 => write!(out, "\\u{:04x}", c as u32).unwrap(),
+                                c => out.push(c)
+                            }
+                        }
+                        out.push('"');
+                    }
+                }
+                if !flags.contains(SerializeFlags::NO_CLUSTERS) {
+                    write!(out, ",\"cl\":{}", record.cluster).unwrap();
+                }
+                if !flags.contains(SerializeFlags::NO_POSITIONS) {
+                    write!(out, ",\"dx\":{},\"dy\":{}", record.x_offset, record.y_offset).unwrap();
+                    if !flags.contains(SerializeFlags::NO_ADVANCES) {
+                        write!(out, ",\"ax\":{},\"ay\":{}", record.x_advance, record.y_advance).unwrap();
+                    }
+                }
+                out.push('}');
+            }
+        }
+    }
+
+    out.push(']');
+    out
+}
+
+/// Parse glyphs serialized by `serialize_glyphs` or `hb-shape`. Fields missing from the input are
+/// set to zero, and glyphs written as plain numbers are parsed as `GlyphRef::Id`. Names, including
+/// ones like `uni0041` and `gid5`, can be turned into glyph indices with `GlyphRef::resolve`.
+///
+/// The glyph flags and extents `hb-shape` writes with `--show-flags` and `--show-extents` aren't
+/// part of `GlyphRecord`, and are skipped.
+pub fn parse_glyphs(s: &str, format: SerializeFormat) -> Result<Vec<GlyphRecord>, ParseGlyphsError> {
+    let mut parser = Parser{ s, pos: 0 };
+    parser.skip_whitespace();
+    let records = match format {
+        SerializeFormat::Text => parser.text()?,
+        SerializeFormat::Json => parser.json()?
+    };
+    parser.skip_whitespace();
+
+    match parser.pos == s.len() {
+        true => Ok(records),
+        false => Err(parser.error("end of input"))
+    }
+}
+
+impl fmt::Display for ParseGlyphsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "expected {} at offset {}", self.expected, self.offset)
+    }
+}
+
+impl error::Error for ParseGlyphsError {}
+
+struct Parser<'a> {
+    s: &'a str,
+    pos: usize
+}
+
+impl<'a> Parser<'a> {
+    fn text(&mut self) -> Result<Vec<GlyphRecord>, ParseGlyphsError> {
+        let mut records = Vec::new();
+        // `hb-shape` brackets its output, but `hb_buffer_serialize_glyphs` alone doesn't.
+        let bracketed = self.eat('[');
+        if bracketed && self.eat(']') {
+            return Ok(records);
+        }
+
+        loop {
+            let name = self.take_while(|c| !"=@+#<|]".contains(c) && !c.is_whitespace());
+            let mut record = GlyphRecord {
+                glyph: glyph_ref(name).ok_or(self.error("glyph name or index"))?,
+                cluster: 0,
+                x_advance: 0,
+                y_advance: 0,
+                x_offset: 0,
+                y_offset: 0
+            };
+            if self.eat('=') {
+                record.cluster = self.uint()?;
+            }
+            if self.eat('@') {
+                record.x_offset = self.int()?;
+                self.expect(',')?;
+                record.y_offset = self.int()?;
+            }
+            if self.eat('+') {
+                record.x_advance = self.int()?;
+                if self.eat(',') {
+                    record.y_advance = self.int()?;
+                }
+            }
+            if self.eat('#') {
+                self.hex()?;
+            }
+            if self.eat('<') {
+                for i in 0..4 {
+                    if i != 0 {
+                        self.expect(',')?;
+                    }
+                    self.int()?;
+                }
+                self.expect('>')?;
+            }
+            records.push(record);
+
+            if !self.eat('|') {
+                break;
+            }
+        }
+
+        if bracketed {
+            self.expect(']')?;
+        }
+        Ok(records)
+    }
+
+    fn json(&mut self) -> Result<Vec<GlyphRecord>, ParseGlyphsError> {
+        let mut records = Vec::new();
+        self.expect('[')?;
+        self.skip_whitespace();
+        if self.eat(']') {
+            return Ok(records);
+        }
+
+        loop {
+            self.skip_whitespace();
+            self.expect('{')?;
+            let mut record = GlyphRecord {
+                glyph: GlyphRef::Id(0),
+                cluster: 0,
+                x_advance: 0,
+                y_advance: 0,
+                x_offset: 0,
+                y_offset: 0
+            };
+
+            loop {
+                self.skip_whitespace();
+                let key = self.string()?;
+                self.skip_whitespace();
+                self.expect(':')?;
+                self.skip_whitespace();
+                match &*key {
+                    "g" => record.glyph = match self.peek() {
+                        Some('"') => GlyphRef::Name(self.string()?),
+                        _ => GlyphRef::Id(self.uint()?)
+                    },
+                    "cl" => record.cluster = self.uint()?,
+                    "dx" => record.x_offset = self.int()?,
+                    "dy" => record.y_offset = self.int()?,
+                    "ax" => record.x_advance = self.int()?,
+                    "ay" => record.y_advance = self.int()?,
+                    // Newer versions of Harfbuzz can add glyph extents and flags, which aren't
+                    // part of `GlyphRecord`.
+                    _ => match self.peek() {
+                        Some('"') => {self.string()?;},
+                        _ => {self.int()?;}
+                    }
+                }
+                self.skip_whitespace();
+                if !self.eat(',') {
+                    break;
+                }
+            }
+
+            self.expect('}')?;
+            records.push(record);
+            self.skip_whitespace();
+            if !self.eat(',') {
+                break;
+            }
+        }
+
+        self.expect(']')?;
+        Ok(records)
+    }
+
+    fn string(&mut self) -> Result<String, ParseGlyphsError> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(string),
+                Some('\\') => match self.next() {
+                    Some(c @ '"') | Some(c @ '\\') | Some(c @ '/') => string.push(c),
+                    Some('b') => string.push('\u{8}'),
+                    Some('f') => string.push('\u{c}'),
+                    Some('n') => string.push('\n'),
+                    Some('r') => string.push('\r'),
+                    Some('t') => string.push('\t'),
+                    Some('u') => {
+                        let c = self.s.get(self.pos..self.pos + 4)
+                            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+                            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                            .and_then(char::from_u32)
+                            .ok_or(self.error("four hex digits"))?;
+                        self.pos += 4;
+                        string.push(c);
+                    },
+                    _ => return Err(self.error("escaped character"))
+                },
+                Some(c) => string.push(c),
+                None => return Err(self.error("'\"'"))
+            }
+        }
+    }
+
+    fn int(&mut self) -> Result<i32, ParseGlyphsError> {
+        let start = self.pos;
+        self.eat('-');
+        self.take_while(|c| c.is_ascii_digit());
+        self.s[start..self.pos].parse().map_err(|_| ParseGlyphsError{ offset: start, expected: "integer" })
+    }
+
+    fn uint(&mut self) -> Result<u32, ParseGlyphsError> {
+        let start = self.pos;
+        self.take_while(|c| c.is_ascii_digit());
+        self.s[start..self.pos].parse().map_err(|_| ParseGlyphsError{ offset: start, expected: "unsigned integer" })
+    }
+
+    fn hex(&mut self) -> Result<u32, ParseGlyphsError> {
+        let start = self.pos;
+        self.take_while(|c| c.is_ascii_hexdigit());
+        u32::from_str_radix(&self.s[start..self.pos], 16).map_err(|_| ParseGlyphsError{ offset: start, expected: "hexadecimal integer" })
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, f: F) -> &'a str {
+        let start = self.pos;
+        while self.peek().map(&f).unwrap_or(false) {
+            self.next();
+        }
+        &self.s[start..self.pos]
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.take_while(char::is_whitespace);
+    }
+
+    #[inline]
+    fn peek(&self) -> Option<char> {
+        self.s[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        match self.peek() == Some(c) {
+            true => {self.pos += c.len_utf8(); true},
+            false => false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), ParseGlyphsError> {
+        match self.eat(c) {
+            true => Ok(()),
+            false => Err(self.error(match c {
+                ',' => "','",
+                ']' => "']'",
+                '[' => "'['",
+                '{' => "'{'",
+                '}' => "'}'",
+                '>' => "'>'",
+                ':' => "':'",
+                '"' => "'\"'",
+                _ => "separator"
+            }))
+        }
+    }
+
+    #[inline]
+    fn error(&self, expected: &'static str) -> ParseGlyphsError {
+        ParseGlyphsError {
+            offset: self.pos,
+            expected
+        }
+    }
+}
+
+fn glyph_ref(s: &str) -> Option<GlyphRef> {
+    match s {
+        "" => None,
+        _ if s.bytes().all(|b| b.is_ascii_digit()) => s.parse().ok().map(GlyphRef::Id),
+        _ => Some(GlyphRef::Name(s.to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {FTLib, Shaper, FaceSize, DPI};
+
+    fn record(glyph: GlyphRef, cluster: u32, advance: (i32, i32), offset: (i32, i32)) -> GlyphRecord {
+        GlyphRecord {
+            glyph,
+            cluster,
+            x_advance: advance.0,
+            y_advance: advance.1,
+            x_offset: offset.0,
+            y_offset: offset.1
+        }
+    }
+
+    fn name(name: &str) -> GlyphRef {
+        GlyphRef::Name(name.to_owned())
+    }
+
+    #[test]
+    fn round_trip() {
+        let lib = FTLib::new();
+        let face = Face::new(&include_bytes!("../DejaVuSans.ttf")[..], 0, &lib).unwrap();
+        let named = vec![
+            record(name("A"), 0, (1270, 0), (0, 0)),
+            record(name("uni0301"), 0, (0, 0), (-640, 12)),
+            record(name("gid5"), 3, (-20, 1500), (0, -3))
+        ];
+        let ids = named.iter().map(|r| GlyphRecord{ glyph: GlyphRef::Id(r.glyph.resolve(&face).unwrap()), ..r.clone() }).collect::<Vec<_>>();
+
+        for &format in &[SerializeFormat::Text, SerializeFormat::Json] {
+            let text = serialize_glyphs(&named, &face, format, SerializeFlags::empty());
+            assert_eq!(named, parse_glyphs(&text, format).unwrap());
+            let text = serialize_glyphs(&ids, &face, format, SerializeFlags::NO_GLYPH_NAMES);
+            assert_eq!(ids, parse_glyphs(&text, format).unwrap());
+            assert_eq!(Vec::<GlyphRecord>::new(), parse_glyphs(&serialize_glyphs(&[], &face, format, SerializeFlags::empty()), format).unwrap());
+        }
+    }
+
+    #[test]
+    fn json_escapes() {
+        let lib = FTLib::new();
+        let face = Face::new(&include_bytes!("../DejaVuSans.ttf")[..], 0, &lib).unwrap();
+        let records = vec![record(name("a\"b\\c\n\u{1}\u{1f}é"), 0, (0, 0), (0, 0))];
+
+        let json = serialize_glyphs(&records, &face, SerializeFormat::Json, SerializeFlags::NO_POSITIONS);
+        assert_eq!(r#"[{"g":"a\"b\\c\u000a\u0001\u001fé","cl":0}]"#, json);
+        assert_eq!(records, parse_glyphs(&json, SerializeFormat::Json).unwrap());
+        assert_eq!(
+            vec![record(name("\t\r/"), 0, (0, 0), (0, 0))],
+            parse_glyphs(r#"[{"g":"\t\r\/"}]"#, SerializeFormat::Json).unwrap()
+        );
+        assert!(parse_glyphs(r#"[{"g":"\u+041"}]"#, SerializeFormat::Json).is_err());
+        assert!(parse_glyphs(r#"[{"g":"\ud800"}]"#, SerializeFormat::Json).is_err());
+    }
+
+    #[test]
+    fn flags_and_extents() {
+        // `hb-shape --show-flags --show-extents` output.
+        let text = "[A=0+1270<16,1493,1368,-1493>|V=1+1401#1<16,1493,1368,-1493>|space=2+651<0,0,0,0>]";
+        let json = r#"[{"g":"A","cl":0,"dx":0,"dy":0,"ax":1270,"ay":0,"xb":16,"yb":1493,"w":1368,"h":-1493},
+            {"g":"V","cl":1,"dx":0,"dy":0,"ax":1401,"ay":0,"fl":1,"xb":16,"yb":1493,"w":1368,"h":-1493},
+            {"g":"space","cl":2,"dx":0,"dy":0,"ax":651,"ay":0,"xb":0,"yb":0,"w":0,"h":0}]"#;
+        let expected = vec![
+            record(name("A"), 0, (1270, 0), (0, 0)),
+            record(name("V"), 1, (1401, 0), (0, 0)),
+            record(name("space"), 2, (651, 0), (0, 0))
+        ];
+        assert_eq!(expected, parse_glyphs(text, SerializeFormat::Text).unwrap());
+        assert_eq!(expected, parse_glyphs(json, SerializeFormat::Json).unwrap());
+
+        assert_eq!(ParseGlyphsError{ offset: 14, expected: "'>'" }, parse_glyphs("[A=0+1<1,2,3,4|", SerializeFormat::Text).unwrap_err());
+        assert_eq!(ParseGlyphsError{ offset: 7, expected: "hexadecimal integer" }, parse_glyphs("[A=0+1#]", SerializeFormat::Text).unwrap_err());
+    }
+
+    #[test]
+    fn matches_hb_shape() {
+        // At 32 pixels per em, DejaVu Sans' 2048 unit em makes 26.6 positions equal to font units.
+        // The expected output is from `hb-shape --font-size=32 --font-subpixel-bits=6`.
+        let text = "AVATAR fiddle";
+        let hb_shape_text = "[A=0+1270|V=1+1270|A=2+1242|T=3+1092|A=4+1401|R=5+1423|space=6+651|fi=7+1290|d=9+1300|d=10+1300|l=11+569|e=12+1260]";
+        let hb_shape_json = concat!(
+            r#"[{"g":"A","cl":0,"dx":0,"dy":0,"ax":1270,"ay":0},{"g":"V","cl":1,"dx":0,"dy":0,"ax":1270,"ay":0},"#,
+            r#"{"g":"A","cl":2,"dx":0,"dy":0,"ax":1242,"ay":0},{"g":"T","cl":3,"dx":0,"dy":0,"ax":1092,"ay":0},"#,
+            r#"{"g":"A","cl":4,"dx":0,"dy":0,"ax":1401,"ay":0},{"g":"R","cl":5,"dx":0,"dy":0,"ax":1423,"ay":0},"#,
+            r#"{"g":"space","cl":6,"dx":0,"dy":0,"ax":651,"ay":0},{"g":"fi","cl":7,"dx":0,"dy":0,"ax":1290,"ay":0},"#,
+            r#"{"g":"d","cl":9,"dx":0,"dy":0,"ax":1300,"ay":0},{"g":"d","cl":10,"dx":0,"dy":0,"ax":1300,"ay":0},"#,
+            r#"{"g":"l","cl":11,"dx":0,"dy":0,"ax":569,"ay":0},{"g":"e","cl":12,"dx":0,"dy":0,"ax":1260,"ay":0}]"#
+        );
+
+        let lib = FTLib::new();
+        let mut face = Face::new(&include_bytes!("../DejaVuSans.ttf")[..], 0, &lib).unwrap();
+        let mut shaper = Shaper::new();
+        let records = shaper.shape_text(text, &mut face, FaceSize::from_pixels(32), DPI::new(72, 72)).unwrap().into_records();
+
+        assert_eq!(hb_shape_text, serialize_glyphs(&records, &face, SerializeFormat::Text, SerializeFlags::empty()));
+        assert_eq!(hb_shape_json, serialize_glyphs(&records, &face, SerializeFormat::Json, SerializeFlags::empty()));
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use {Face, FTLib, Error, Operation, FaceError, reference_table};
+use ffi;
+use ft::{self, FT_Long};
+use harfbuzz_sys::hb_face_create_for_tables;
+
+use std::{fmt, mem, ptr, slice, panic};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::raw::{c_void, c_uchar, c_ulong};
+
+/// The reader a face created with `Face::new_reader` loads font data from.
+pub struct FontStream<R> {
+    // Boxed, since FreeType holds on to a pointer to the stream record.
+    stream: Box<StreamRec<R>>
+}
+
+/// A FreeType stream record along with the reader backing it. `ft_stream` needs to be the first
+/// field, so that FreeType's `FT_Stream` pointers can be cast back into a `StreamRec`.
+#[repr(C)]
+struct StreamRec<R> {
+    ft_stream: ft::FT_StreamRec_,
+    reader: R
+}
+
+impl<R: Read + Seek> Face<FontStream<R>> {
+    /// Open a face that reads its font data from `reader`. FreeType reads the parts of the font it
+    /// needs as it needs them, and Harfbuzz loads tables on demand, so the font never has to be
+    /// loaded into memory as a whole.
+    ///
+    /// The reader keeps getting used for as long as the face exists. If it fails or panics after
+    /// the face has been opened, the operation that needed the data fails with
+    /// `Error::InvalidStreamOperation`, which is what FreeType reports for any failed read.
+    pub fn new_reader(mut reader: R, face_index: i32, lib: &FTLib) -> Result<Face<FontStream<R>>, FaceError> {
+        let size = reader.seek(SeekFrom::End(0))
+            .map_err(|_| Error::InvalidStreamSeek.during(Operation::OpenFace))?;
+        if size > FT_Long::max_value() as u64 {
+            return Err(Error::InvalidStreamOperation.during(Operation::OpenFace));
+        }
+
+        unsafe {
+            let mut stream = Box::new(StreamRec {
+                ft_stream: mem::zeroed(),
+                reader
+            });
+            stream.ft_stream.size = size as c_ulong;
+            stream.ft_stream.read = Some(read_stream::<R>);
+            stream.ft_stream.close = Some(close_stream);
+
+            let mut args: ft::FT_Open_Args = mem::zeroed();
+            args.flags = ffi::FT_OPEN_STREAM;
+            args.stream = &mut stream.ft_stream;
+
+            let mut ft_face = ptr::null_mut();
+            let err_raw = {
+                let _lock = lib.lock();
+                ft::FT_Open_Face(lib.raw(), &args, face_index as FT_Long, &mut ft_face)
+            };
+            Error::check(err_raw).map_err(|e| e.during(Operation::OpenFace))?;
+
+            let hb_face = hb_face_create_for_tables(Some(reference_table), ft_face as *mut c_void, None);
+            Face::from_ft_face(ft_face, hb_face, FontStream{ stream }, lib)
+        }
+    }
+}
+
+impl<R> FontStream<R> {
+    #[inline]
+    pub fn get_ref(&self) -> &R {
+        &self.stream.reader
+    }
+}
+
+unsafe impl<R: Send> Send for FontStream<R> {}
+
+impl<R: fmt::Debug> fmt::Debug for FontStream<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FontStream")
+            .field("reader", &self.stream.reader)
+            .field("size", &self.stream.ft_stream.size)
+            .finish()
+    }
+}
+
+/// FreeType's stream read function. A `count` of zero asks for a seek, which returns zero on
+/// success; otherwise, this returns the number of bytes read, which FreeType treats as an error if
+/// it's short.
+unsafe extern "C" fn read_stream<R: Read + Seek>(stream: ft::FT_Stream, offset: c_ulong, buffer: *mut c_uchar, count: c_ulong) -> c_ulong {
+    let rec = &mut *(stream as *mut StreamRec<R>);
+
+    // Unwinding into FreeType isn't allowed, so a panicking reader is treated like a failing one.
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| -> io::Result<usize> {
+        rec.reader.seek(SeekFrom::Start(offset as u64))?;
+        if count == 0 {
+            return Ok(0);
+        }
+
+        let buffer = slice::from_raw_parts_mut(buffer, count as usize);
+        let mut read = 0;
+        while read < buffer.len() {
+            match rec.reader.read(&mut buffer[read..]) {
+                Ok(0) => break,
+                Ok(n) => read += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e)
+            }
+        }
+        Ok(read)
+    }));
+
+    match (result, count) {
+        (Ok(Ok(read)), _) => read as c_ulong,
+        (_, 0) => 1,
+        _ => 0
+    }
+}
+
+/// The reader gets dropped along with the `Face`, after FreeType is done with it, so there's
+/// nothing to do here.
+unsafe extern "C" fn close_stream(_: ft::FT_Stream) {}
@@ -0,0 +1,486 @@
+// Copyright 2018 Osspial
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use {Face, FTLib, Tag, Error, FaceError};
+use ffi;
+
+use stable_deref_trait::StableDeref;
+
+use std::{fmt, error, ptr};
+use std::ops::Deref;
+
+/// Controls how thoroughly `validate_font` and `Face::new_validated` check a font.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ValidationOptions {
+    /// Reject fonts where a table's checksum doesn't match the one recorded in the table
+    /// directory. Off by default: plenty of fonts in the wild have stale checksums, and neither
+    /// FreeType nor Harfbuzz checks them, so a mismatch alone doesn't make a font unsafe to load.
+    pub verify_checksums: bool,
+    /// Run FreeType's OpenType validator over the `BASE`, `GDEF`, `GPOS`, `GSUB` and `JSTF`
+    /// tables. This requires FreeType to be built with the `otvalid` module, and fails with
+    /// `Error::UnimplementedFeature` otherwise.
+    pub validate_layout: bool
+}
+
+/// Describes why a font was rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValidationError {
+    /// The data ends before the structure starting at `offset` does.
+    Truncated { offset: usize },
+    /// The data doesn't start with a known sfnt version or collection tag.
+    UnknownFormat(Tag),
+    FaceIndexOutOfRange { face_index: i32, num_faces: u32 },
+    NoTables,
+    DuplicateTable(Tag),
+    /// The table extends past the end of the data.
+    TableOutOfBounds(Tag),
+    OverlappingTables(Tag, Tag),
+    ChecksumMismatch { tag: Tag, expected: u32, actual: u32 },
+    MissingTable(Tag),
+    /// A table the font can't be loaded without has a malformed header.
+    MalformedTable(Tag),
+    /// FreeType's OpenType validator rejected the font's layout tables.
+    InvalidLayout(Error),
+    /// The font passed validation, but FreeType or Harfbuzz still failed to open it.
+    Face(FaceError)
+}
+
+struct TableRecord {
+    tag: Tag,
+    checksum: u32,
+    offset: usize,
+    length: usize
+}
+
+const SFNT_TRUETYPE: u32 = 0x00010000;
+const SFNT_OTTO: u32 = 0x4F54544F;
+const SFNT_TRUE: u32 = 0x74727565;
+const SFNT_TYP1: u32 = 0x74797031;
+const TTC_TAG: u32 = 0x74746366;
+
+const HEAD_MAGIC: u32 = 0x5F0F3CF5;
+
+/// Check that `data` holds a structurally sound sfnt font or font collection, without handing it
+/// to FreeType or Harfbuzz.
+///
+/// Only the face selected by `face_index` is checked, using the same index FreeType accepts for
+/// `Face::new`. This never panics and only reads within `data`, which makes it suitable for use as
+/// a fuzzing target. `options.validate_layout` is ignored, since layout validation is done by
+/// FreeType; use `Face::new_validated` to include it.
+pub fn validate_font(data: &[u8], face_index: i32, options: &ValidationOptions) -> Result<(), ValidationError> {
+    let face_offset = face_offset(data, face_index)?;
+
+    let sfnt_version = read_u32(data, face_offset)?;
+    match sfnt_version {
+        SFNT_TRUETYPE | SFNT_OTTO | SFNT_TRUE | SFNT_TYP1 => (),
+        _ => return Err(ValidationError::UnknownFormat(Tag::from_u32(sfnt_version)))
+    }
+
+    let tables = table_directory(data, face_offset)?;
+    check_overlap(&tables)?;
+
+    if options.verify_checksums {
+        for table in &tables {
+            let actual = table_checksum(table_data(data, table), table.tag == Tag(*b"head"));
+            if actual != table.checksum {
+                return Err(ValidationError::ChecksumMismatch {
+                    tag: table.tag,
+                    expected: table.checksum,
+                    actual
+                });
+            }
+        }
+    }
+
+    let find = |tag: &[u8; 4]| tables.iter().find(|t| t.tag == Tag(*tag)).map(|t| table_data(data, t));
+    match (find(b"head"), find(b"bhed")) {
+        (Some(head), _) => check_head(head, Tag(*b"head"))?,
+        (None, Some(bhed)) => check_head(bhed, Tag(*b"bhed"))?,
+        (None, None) => return Err(ValidationError::MissingTable(Tag(*b"head")))
+    }
+    match find(b"maxp") {
+        Some(maxp) => check_maxp(maxp)?,
+        None => return Err(ValidationError::MissingTable(Tag(*b"maxp")))
+    }
+    if let Some(hhea) = find(b"hhea") {
+        if hhea.len() < 36 {
+            return Err(ValidationError::MalformedTable(Tag(*b"hhea")));
+        }
+    }
+    if sfnt_version == SFNT_OTTO && find(b"CFF ").is_none() && find(b"CFF2").is_none() {
+        return Err(ValidationError::MissingTable(Tag(*b"CFF ")));
+    }
+
+    Ok(())
+}
+
+impl<B> Face<B>
+    where B: StableDeref + Deref<Target=[u8]>
+{
+    /// Create a face from untrusted font data, checking the font with `validate_font` before
+    /// FreeType or Harfbuzz ever see it.
+    pub fn new_validated(font_buffer: B, face_index: i32, lib: &FTLib, options: &ValidationOptions) -> Result<Face<B>, ValidationError> {
+        validate_font(&font_buffer, face_index, options)?;
+        let face = Face::new(font_buffer, face_index, lib)?;
+        if options.validate_layout {
+            face.validate_layout().map_err(ValidationError::InvalidLayout)?;
+        }
+        Ok(face)
+    }
+}
+
+impl<B: ?Sized> Face<B> {
+    /// Run FreeType's OpenType validator over the face's layout tables.
+    fn validate_layout(&self) -> Result<(), Error> {
+        let mut tables = [ptr::null(); 5];
+        unsafe {
+            let error = {
+                let [ref mut base, ref mut gdef, ref mut gpos, ref mut gsub, ref mut jstf] = tables;
+                ffi::FT_OpenType_Validate(self.ft_face, ffi::FT_VALIDATE_OT, base, gdef, gpos, gsub, jstf)
+            };
+            for table in tables.iter().filter(|t| !t.is_null()) {
+                ffi::FT_OpenType_Free(self.ft_face, *table);
+            }
+            Error::check(error)
+        }
+    }
+}
+
+impl Default for ValidationOptions {
+    #[inline]
+    fn default() -> ValidationOptions {
+        ValidationOptions {
+            verify_checksums: false,
+            validate_layout: false
+        }
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidationError::Truncated{ offset } => write!(f, "font data truncated at offset {}", offset),
+            ValidationError::UnknownFormat(tag) => write!(f, "unknown font format '{}'", tag),
+            ValidationError::FaceIndexOutOfRange{ face_index, num_faces } =>
+                write!(f, "face index {} out of range for collection of {} faces", face_index, num_faces),
+            ValidationError::NoTables => write!(f, "font has no tables"),
+            ValidationError::DuplicateTable(tag) => write!(f, "duplicate '{}' table", tag),
+            ValidationError::TableOutOfBounds(tag) => write!(f, "'{}' table extends past the end of the font data", tag),
+            ValidationError::OverlappingTables(a, b) => write!(f, "'{}' and '{}' tables overlap", a, b),
+            ValidationError::ChecksumMismatch{ tag, expected, actual } =>
+                write!(f, "'{}' table checksum is {:#010x}, expected {:#010x}", tag, actual, expected),
+            ValidationError::MissingTable(tag) => write!(f, "missing required '{}' table", tag),
+            ValidationError::MalformedTable(tag) => write!(f, "malformed '{}' table", tag),
+            ValidationError::InvalidLayout(err) => write!(f, "invalid OpenType layout tables: {}", err),
+            ValidationError::Face(ref err) => write!(f, "{}", err)
+        }
+    }
+}
+
+impl error::Error for ValidationError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ValidationError::InvalidLayout(ref err) => Some(err),
+            ValidationError::Face(ref err) => Some(err),
+            _ => None
+        }
+    }
+}
+
+impl From<FaceError> for ValidationError {
+    #[inline]
+    fn from(err: FaceError) -> ValidationError {
+        ValidationError::Face(err)
+    }
+}
+
+/// Find the offset of the selected face's offset table, resolving font collections.
+fn face_offset(data: &[u8], face_index: i32) -> Result<usize, ValidationError> {
+    // The upper 16 bits select a named instance of a variable font, and aren't relevant here.
+    let index = face_index & 0xFFFF;
+
+    if read_u32(data, 0)? != TTC_TAG {
+        return match index {
+            0 if face_index >= 0 => Ok(0),
+            _ => Err(ValidationError::FaceIndexOutOfRange{ face_index, num_faces: 1 })
+        };
+    }
+
+    let num_faces = read_u32(data, 8)?;
+    if face_index < 0 || index as u32 >= num_faces {
+        return Err(ValidationError::FaceIndexOutOfRange{ face_index, num_faces });
+    }
+    match read_u32(data, 12 + index as usize * 4)? as usize {
+        offset if offset < data.len() => Ok(offset),
+        offset => Err(ValidationError::Truncated{ offset })
+    }
+}
+
+fn table_directory(data: &[u8], face_offset: usize) -> Result<Vec<TableRecord>, ValidationError> {
+    let num_tables = read_u16(data, face_offset + 4)? as usize;
+    if num_tables == 0 {
+        return Err(ValidationError::NoTables);
+    }
+
+    let records_start = face_offset + 12;
+    // Make sure the whole directory is present before allocating space for it.
+    read_u32(data, records_start + num_tables * 16 - 4)?;
+
+    let mut tables: Vec<TableRecord> = Vec::with_capacity(num_tables);
+    for i in 0..num_tables {
+        let record = records_start + i * 16;
+        let table = TableRecord {
+            tag: Tag::from_u32(read_u32(data, record)?),
+            checksum: read_u32(data, record + 4)?,
+            offset: read_u32(data, record + 8)? as usize,
+            length: read_u32(data, record + 12)? as usize
+        };
+
+        match table.offset.checked_add(table.length) {
+            Some(end) if end <= data.len() => (),
+            _ => return Err(ValidationError::TableOutOfBounds(table.tag))
+        }
+        tables.push(table);
+    }
+
+    let mut tags = tables.iter().map(|t| t.tag).collect::<Vec<_>>();
+    tags.sort();
+    if let Some(pair) = tags.windows(2).find(|pair| pair[0] == pair[1]) {
+        return Err(ValidationError::DuplicateTable(pair[0]));
+    }
+
+    Ok(tables)
+}
+
+/// Reject tables that partially overlap. Tables that share the exact same data are allowed, since
+/// some fonts alias tables that way.
+fn check_overlap(tables: &[TableRecord]) -> Result<(), ValidationError> {
+    let mut sorted = tables.iter().filter(|t| t.length != 0).collect::<Vec<_>>();
+    sorted.sort_by_key(|t| (t.offset, t.length));
+
+    for pair in sorted.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        let aliased = a.offset == b.offset && a.length == b.length;
+        if a.offset + a.length > b.offset && !aliased {
+            return Err(ValidationError::OverlappingTables(a.tag, b.tag));
+        }
+    }
+    Ok(())
+}
+
+fn check_head(head: &[u8], tag: Tag) -> Result<(), ValidationError> {
+    let malformed = ValidationError::MalformedTable(tag);
+    if head.len() < 54 {
+        return Err(malformed);
+    }
+
+    let magic = read_u32(head, 12).map_err(|_| malformed)?;
+    let units_per_em = read_u16(head, 18).map_err(|_| malformed)?;
+    let index_to_loc_format = read_u16(head, 50).map_err(|_| malformed)?;
+    match (magic, units_per_em, index_to_loc_format) {
+        (HEAD_MAGIC, 16..=16384, 0..=1) => Ok(()),
+        _ => Err(malformed)
+    }
+}
+
+fn check_maxp(maxp: &[u8]) -> Result<(), ValidationError> {
+    let malformed = ValidationError::MalformedTable(Tag(*b"maxp"));
+    match read_u32(maxp, 0).map_err(|_| malformed)? {
+        0x00005000 if maxp.len() >= 6 => Ok(()),
+        0x00010000 if maxp.len() >= 32 => Ok(()),
+        _ => Err(malformed)
+    }
+}
+
+#[inline]
+fn table_data<'a>(data: &'a [u8], table: &TableRecord) -> &'a [u8] {
+    &data[table.offset..table.offset + table.length]
+}
+
+/// Compute a table's checksum. The `head` table's `checkSumAdjustment` field is skipped, as the
+/// OpenType spec requires.
+fn table_checksum(table: &[u8], is_head: bool) -> u32 {
+    let mut sum = 0u32;
+    for (i, chunk) in table.chunks(4).enumerate() {
+        if is_head && i == 2 {
+            continue;
+        }
+        let mut word = [0; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum = sum.wrapping_add(u32::from_be_bytes(word));
+    }
+    sum
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Result<u16, ValidationError> {
+    match data.get(offset..offset.wrapping_add(2)) {
+        Some(bytes) => Ok((bytes[0] as u16) << 8 | bytes[1] as u16),
+        None => Err(ValidationError::Truncated{ offset })
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32, ValidationError> {
+    match data.get(offset..offset.wrapping_add(4)) {
+        Some(bytes) => Ok((bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32),
+        None => Err(ValidationError::Truncated{ offset })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn head() -> Vec<u8> {
+        let mut head = vec![0; 54];
+        head[12..16].copy_from_slice(&HEAD_MAGIC.to_be_bytes());
+        head[18..20].copy_from_slice(&2048u16.to_be_bytes());
+        head
+    }
+
+    fn maxp() -> Vec<u8> {
+        vec![0, 0, 0x50, 0, 0, 1]
+    }
+
+    /// An sfnt font holding `tables`, with correct checksums and each table padded to four bytes.
+    fn sfnt(tables: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut data = SFNT_TRUETYPE.to_be_bytes().to_vec();
+        data.extend(&(tables.len() as u16).to_be_bytes());
+        data.extend(&[0; 6]);
+
+        let mut offset = 12 + tables.len() * 16;
+        for &(tag, ref table) in tables {
+            data.extend(tag);
+            data.extend(&table_checksum(table, tag == b"head").to_be_bytes());
+            data.extend(&(offset as u32).to_be_bytes());
+            data.extend(&(table.len() as u32).to_be_bytes());
+            offset += (table.len() + 3) & !3;
+        }
+        for (_, table) in tables {
+            data.extend(table);
+            data.resize((data.len() + 3) & !3, 0);
+        }
+        data
+    }
+
+    fn minimal() -> Vec<u8> {
+        sfnt(&[(b"head", head()), (b"maxp", maxp())])
+    }
+
+    /// Overwrite a field of the table record at `index` in `font`'s table directory.
+    fn set_record(font: &mut [u8], index: usize, field: usize, value: u32) {
+        let offset = 12 + index * 16 + field * 4;
+        font[offset..offset + 4].copy_from_slice(&value.to_be_bytes());
+    }
+
+    fn validate(data: &[u8], face_index: i32) -> Result<(), ValidationError> {
+        validate_font(data, face_index, &ValidationOptions::default())
+    }
+
+    #[test]
+    fn valid_fonts() {
+        assert_eq!(Ok(()), validate(&minimal(), 0));
+        assert_eq!(Ok(()), validate(include_bytes!("../DejaVuSans.ttf"), 0));
+    }
+
+    #[test]
+    fn truncated_directory() {
+        let font = minimal();
+        assert_eq!(Err(ValidationError::Truncated{ offset: 40 }), validate(&font[..28], 0));
+        assert_eq!(Err(ValidationError::Truncated{ offset: 4 }), validate(&font[..5], 0));
+    }
+
+    #[test]
+    fn overlapping_tables() {
+        let mut font = minimal();
+        // Move `maxp` into the middle of `head`.
+        set_record(&mut font, 1, 2, 12 + 2 * 16 + 4);
+        assert_eq!(
+            Err(ValidationError::OverlappingTables(Tag(*b"head"), Tag(*b"maxp"))),
+            validate(&font, 0)
+        );
+    }
+
+    #[test]
+    fn table_past_end() {
+        let mut font = minimal();
+        let len = font.len() as u32;
+        set_record(&mut font, 1, 3, len);
+        assert_eq!(Err(ValidationError::TableOutOfBounds(Tag(*b"maxp"))), validate(&font, 0));
+
+        set_record(&mut font, 1, 2, u32::MAX);
+        set_record(&mut font, 1, 3, 6);
+        assert_eq!(Err(ValidationError::TableOutOfBounds(Tag(*b"maxp"))), validate(&font, 0));
+    }
+
+    #[test]
+    fn face_index_out_of_range() {
+        let font = minimal();
+        assert_eq!(Err(ValidationError::FaceIndexOutOfRange{ face_index: 1, num_faces: 1 }), validate(&font, 1));
+
+        // A collection holding the minimal font, with its table offsets moved past the header.
+        let mut collection = b"ttcf\x00\x01\x00\x00".to_vec();
+        collection.extend(&1u32.to_be_bytes());
+        collection.extend(&16u32.to_be_bytes());
+        let mut face = font.clone();
+        for i in 0..2 {
+            let offset = read_u32(&font, 12 + i * 16 + 8).unwrap();
+            set_record(&mut face, i, 2, offset + 16);
+        }
+        collection.extend(face);
+
+        assert_eq!(Ok(()), validate(&collection, 0));
+        assert_eq!(Err(ValidationError::FaceIndexOutOfRange{ face_index: 1, num_faces: 1 }), validate(&collection, 1));
+        assert_eq!(Err(ValidationError::FaceIndexOutOfRange{ face_index: -1, num_faces: 1 }), validate(&collection, -1));
+    }
+
+    #[test]
+    fn malformed_tables() {
+        let mut bad_magic = head();
+        bad_magic[12] = 0;
+        assert_eq!(
+            Err(ValidationError::MalformedTable(Tag(*b"head"))),
+            validate(&sfnt(&[(b"head", bad_magic), (b"maxp", maxp())]), 0)
+        );
+
+        let mut bad_version = maxp();
+        bad_version[1] = 2;
+        assert_eq!(
+            Err(ValidationError::MalformedTable(Tag(*b"maxp"))),
+            validate(&sfnt(&[(b"head", head()), (b"maxp", bad_version)]), 0)
+        );
+        assert_eq!(
+            Err(ValidationError::MalformedTable(Tag(*b"maxp"))),
+            validate(&sfnt(&[(b"head", head()), (b"maxp", maxp()[..4].to_vec())]), 0)
+        );
+        assert_eq!(
+            Err(ValidationError::MissingTable(Tag(*b"maxp"))),
+            validate(&sfnt(&[(b"head", head())]), 0)
+        );
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let mut font = minimal();
+        let expected = table_checksum(&maxp(), false);
+        set_record(&mut font, 1, 1, expected + 1);
+
+        // Only checked when asked for.
+        assert_eq!(Ok(()), validate(&font, 0));
+        let options = ValidationOptions{ verify_checksums: true, ..ValidationOptions::default() };
+        assert_eq!(
+            Err(ValidationError::ChecksumMismatch{ tag: Tag(*b"maxp"), expected: expected + 1, actual: expected }),
+            validate_font(&font, 0, &options)
+        );
+    }
+}
@@ -0,0 +1,138 @@
+extern crate glyphydog;
+extern crate cgmath_geometry;
+
+use glyphydog::{FTLib, Face, FaceSize, AtlasOptions, BmFont, BmFontFormat, BmChar, Error};
+use std::collections::HashMap;
+
+use cgmath_geometry::rect::{DimsBox, GeoBox};
+
+/// A character's fields as written in a descriptor: id, x, y, width, height, x offset, y offset,
+/// x advance and page.
+type CharFields = [i64; 9];
+
+fn char_fields(c: &BmChar) -> CharFields {
+    [
+        c.id as i64, c.x as i64, c.y as i64, c.width as i64, c.height as i64,
+        c.x_offset as i64, c.y_offset as i64, c.x_advance as i64, c.page as i64
+    ]
+}
+
+fn text_chars(descriptor: &[u8]) -> Vec<CharFields> {
+    let descriptor = String::from_utf8(descriptor.to_vec()).unwrap();
+    descriptor.lines().filter(|line| line.starts_with("char ")).map(|line| {
+        let values = line.split_whitespace()
+            .filter_map(|field| {
+                let mut parts = field.splitn(2, '=');
+                Some((parts.next()?, parts.next()?.parse::<i64>().ok()?))
+            })
+            .collect::<HashMap<_, _>>();
+        let mut fields = [0; 9];
+        for (field, name) in fields.iter_mut().zip(&["id", "x", "y", "width", "height", "xoffset", "yoffset", "xadvance", "page"]) {
+            *field = values[name];
+        }
+        fields
+    }).collect()
+}
+
+/// The blocks of a binary descriptor, by block type.
+fn binary_blocks(descriptor: &[u8]) -> HashMap<u8, &[u8]> {
+    assert_eq!(b"BMF\x03", &descriptor[..4]);
+    let mut blocks = HashMap::new();
+    let mut rest = &descriptor[4..];
+    while !rest.is_empty() {
+        let len = u32_le(&rest[1..]) as usize;
+        blocks.insert(rest[0], &rest[5..5 + len]);
+        rest = &rest[5 + len..];
+    }
+    blocks
+}
+
+fn binary_chars(chars: &[u8]) -> Vec<CharFields> {
+    chars.chunks(20).map(|c| [
+        u32_le(c) as i64,
+        u16_le(&c[4..]) as i64, u16_le(&c[6..]) as i64, u16_le(&c[8..]) as i64, u16_le(&c[10..]) as i64,
+        u16_le(&c[12..]) as i16 as i64, u16_le(&c[14..]) as i16 as i64, u16_le(&c[16..]) as i16 as i64,
+        c[18] as i64
+    ]).collect()
+}
+
+fn u16_le(b: &[u8]) -> u16 {
+    b[0] as u16 | (b[1] as u16) << 8
+}
+
+fn u32_le(b: &[u8]) -> u32 {
+    u16_le(b) as u32 | (u16_le(&b[2..]) as u32) << 16
+}
+
+#[test]
+fn ascii_round_trip() {
+    let lib = FTLib::new();
+    let mut face = Face::new(&include_bytes!("../DejaVuSans.ttf")[..], 0, &lib).unwrap();
+    let chars = (0x20u8..0x7F).map(|c| c as char).collect::<Vec<_>>();
+    // Small pages, so the glyphs are spread over several of them.
+    let options = AtlasOptions {
+        face_size: FaceSize::from_pixels(24),
+        page_dims: DimsBox::new2(128, 128),
+        spacing: 2,
+        ..AtlasOptions::default()
+    };
+    let font = BmFont::build(&mut face, &chars, &options).unwrap();
+
+    assert_eq!(chars.len(), font.chars.len());
+    assert!(font.pages.len() > 1);
+
+    let (page_width, page_height) = (font.page_dims.width(), font.page_dims.height());
+    let placed = font.chars.iter().filter(|c| c.width != 0 && c.height != 0).collect::<Vec<_>>();
+    for c in &placed {
+        assert!((c.page as usize) < font.pages.len(), "{:?} is on a missing page", c.id);
+        assert!(
+            font.spacing <= c.x && c.x + c.width + font.spacing <= page_width &&
+            font.spacing <= c.y && c.y + c.height + font.spacing <= page_height,
+            "{:?} is outside of its page", c.id
+        );
+    }
+    for (i, a) in placed.iter().enumerate() {
+        for b in &placed[i + 1..] {
+            let apart =
+                a.page != b.page ||
+                a.x + a.width + font.spacing <= b.x || b.x + b.width + font.spacing <= a.x ||
+                a.y + a.height + font.spacing <= b.y || b.y + b.height + font.spacing <= a.y;
+            assert!(apart, "{:?} and {:?} overlap", a.id, b.id);
+        }
+    }
+
+    let expected = font.chars.iter().map(char_fields).collect::<Vec<_>>();
+    assert_eq!(expected, text_chars(&font.descriptor(BmFontFormat::Text, "ascii")));
+
+    let binary = font.descriptor(BmFontFormat::Binary, "ascii");
+    let blocks = binary_blocks(&binary);
+    assert_eq!(expected, binary_chars(blocks[&4]));
+
+    let common = blocks[&2];
+    assert_eq!(
+        [font.line_height, font.base, page_width, page_height, font.pages.len() as u32],
+        [u16_le(common) as u32, u16_le(&common[2..]) as u32, u16_le(&common[4..]) as u32, u16_le(&common[6..]) as u32, u16_le(&common[8..]) as u32]
+    );
+    let page_names = blocks[&3].split(|&b| b == 0).filter(|name| !name.is_empty()).collect::<Vec<_>>();
+    assert_eq!(font.pages.len(), page_names.len());
+    for (i, name) in page_names.iter().enumerate() {
+        assert_eq!(BmFont::page_file_name("ascii", i).as_bytes(), *name);
+    }
+}
+
+#[test]
+fn oversized_options() {
+    let lib = FTLib::new();
+    let mut face = Face::new(&include_bytes!("../DejaVuSans.ttf")[..], 0, &lib).unwrap();
+    let options = AtlasOptions {
+        page_dims: DimsBox::new2(65536, 512),
+        ..AtlasOptions::default()
+    };
+    assert_eq!(Error::InvalidArgument, BmFont::build(&mut face, &['a'], &options).unwrap_err().error);
+
+    let options = AtlasOptions {
+        spacing: 256,
+        ..AtlasOptions::default()
+    };
+    assert_eq!(Error::InvalidArgument, BmFont::build(&mut face, &['a'], &options).unwrap_err().error);
+}
@@ -0,0 +1,206 @@
+extern crate glyphydog;
+
+use glyphydog::{FTLib, Face, Shaper, ShapeCache, ShapeOptions, ShapedGlyph, FaceSize, DPI, CacheStats, GlyphCache, GlyphKey, GlyphImage, LoadFlags, RenderMode};
+use std::mem;
+
+const FONT: &[u8] = include_bytes!("../DejaVuSans.ttf");
+
+fn shape<B: ?Sized>(cache: &mut ShapeCache, shaper: &mut Shaper, text: &str, face: &mut Face<B>, options: &ShapeOptions) -> Vec<ShapedGlyph> {
+    cache.shape(shaper, text, face, FaceSize::from_pixels(16), DPI::new(72, 72), options).unwrap().to_vec()
+}
+
+fn glyph_key<B: ?Sized>(face: &Face<B>, c: char) -> GlyphKey {
+    GlyphKey::new(face.char_index(c), FaceSize::from_pixels(16), DPI::new(72, 72), LoadFlags::empty(), RenderMode::Normal)
+}
+
+fn render<B: ?Sized>(face: &mut Face<B>, key: GlyphKey) -> GlyphImage {
+    face.load_glyph(key.glyph_index, key.face_size, key.dpi, key.load_flags, key.render_mode).unwrap()
+        .render_image(key.render_mode).unwrap()
+}
+
+fn image_size(image: &GlyphImage) -> usize {
+    mem::size_of::<GlyphImage>() + image.bitmap.buffer.len()
+}
+
+fn stats(hits: u64, misses: u64, evictions: u64) -> CacheStats {
+    CacheStats{ hits, misses, evictions }
+}
+
+#[test]
+fn shape_hits_and_misses() {
+    let lib = FTLib::new();
+    let mut face = Face::new(FONT, 0, &lib).unwrap();
+    let mut shaper = Shaper::new();
+    let mut cache = ShapeCache::new(4);
+    let options = ShapeOptions::default();
+
+    let expected = shaper.shape_text_with("cached", &mut face, FaceSize::from_pixels(16), DPI::new(72, 72), &options)
+        .unwrap().collect::<Vec<_>>();
+    assert_eq!(expected, shape(&mut cache, &mut shaper, "cached", &mut face, &options));
+    assert_eq!(expected, shape(&mut cache, &mut shaper, "cached", &mut face, &options));
+    assert_eq!(stats(1, 1, 0), cache.stats());
+
+    // Changing the text, size or options misses.
+    shape(&mut cache, &mut shaper, "cache", &mut face, &options);
+    cache.shape(&mut shaper, "cached", &mut face, FaceSize::from_pixels(17), DPI::new(72, 72), &options).unwrap();
+    let english = ShapeOptions{ language: Some("en".to_owned()), ..ShapeOptions::default() };
+    shape(&mut cache, &mut shaper, "cached", &mut face, &english);
+    assert_eq!(stats(1, 4, 0), cache.stats());
+    assert_eq!(4, cache.len());
+
+    cache.reset_stats();
+    assert_eq!(stats(0, 0, 0), cache.stats());
+    assert_eq!(0.0, cache.stats().hit_rate());
+}
+
+#[test]
+fn shape_eviction_order() {
+    let lib = FTLib::new();
+    let mut face = Face::new(FONT, 0, &lib).unwrap();
+    let mut shaper = Shaper::new();
+    let mut cache = ShapeCache::new(2);
+    let options = ShapeOptions::default();
+
+    shape(&mut cache, &mut shaper, "a", &mut face, &options);
+    shape(&mut cache, &mut shaper, "b", &mut face, &options);
+    // Using "a" makes "b" the least recently used entry, so "c" evicts it.
+    shape(&mut cache, &mut shaper, "a", &mut face, &options);
+    shape(&mut cache, &mut shaper, "c", &mut face, &options);
+    assert_eq!(stats(1, 3, 1), cache.stats());
+    assert_eq!(2, cache.len());
+
+    shape(&mut cache, &mut shaper, "a", &mut face, &options);
+    shape(&mut cache, &mut shaper, "c", &mut face, &options);
+    assert_eq!(stats(3, 3, 1), cache.stats());
+    shape(&mut cache, &mut shaper, "b", &mut face, &options);
+    assert_eq!(stats(3, 4, 2), cache.stats());
+    assert_eq!(2, cache.len());
+}
+
+#[test]
+fn shape_per_face() {
+    let lib = FTLib::new();
+    let mut first = Face::new(FONT, 0, &lib).unwrap();
+    let mut second = Face::new(FONT, 0, &lib).unwrap();
+    let mut shaper = Shaper::new();
+    let mut cache = ShapeCache::new(4);
+    let options = ShapeOptions::default();
+
+    shape(&mut cache, &mut shaper, "face", &mut first, &options);
+    shape(&mut cache, &mut shaper, "face", &mut second, &options);
+    assert_eq!(stats(0, 2, 0), cache.stats());
+
+    cache.invalidate_face(&first);
+    assert_eq!(1, cache.len());
+    shape(&mut cache, &mut shaper, "face", &mut second, &options);
+    shape(&mut cache, &mut shaper, "face", &mut first, &options);
+    assert_eq!(stats(1, 3, 0), cache.stats());
+
+    cache.clear();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn shape_zero_capacity() {
+    let lib = FTLib::new();
+    let mut face = Face::new(FONT, 0, &lib).unwrap();
+    let mut shaper = Shaper::new();
+    let mut cache = ShapeCache::new(0);
+    let options = ShapeOptions::default();
+
+    let first = shape(&mut cache, &mut shaper, "uncached", &mut face, &options);
+    assert_eq!(first, shape(&mut cache, &mut shaper, "uncached", &mut face, &options));
+    assert!(!first.is_empty());
+    assert!(cache.is_empty());
+    assert_eq!(stats(0, 2, 0), cache.stats());
+}
+
+#[test]
+fn glyph_hits_and_misses() {
+    let lib = FTLib::new();
+    let mut face = Face::new(FONT, 0, &lib).unwrap();
+    let mut cache = GlyphCache::new(1 << 20);
+    let (a, b) = (glyph_key(&face, 'a'), glyph_key(&face, 'b'));
+    let expected = render(&mut face, a);
+
+    assert_eq!(&expected, cache.get_or_render(&mut face, a).unwrap());
+    assert_eq!(&expected, cache.get_or_render(&mut face, a).unwrap());
+    assert_eq!(Some(&expected), cache.get(&face, a));
+    assert_eq!(None, cache.get(&face, b));
+    assert_eq!(stats(2, 2, 0), cache.stats());
+    assert_eq!(0.5, cache.stats().hit_rate());
+    assert_eq!(image_size(&expected), cache.memory_used());
+
+    assert_eq!(Some(expected), cache.remove(&face, a));
+    assert!(cache.is_empty());
+    assert_eq!(0, cache.memory_used());
+}
+
+#[test]
+fn glyph_eviction_order() {
+    let lib = FTLib::new();
+    let mut face = Face::new(FONT, 0, &lib).unwrap();
+    let (a, b, c) = (glyph_key(&face, 'a'), glyph_key(&face, 'b'), glyph_key(&face, 'c'));
+    let sizes = [a, b, c].iter().map(|&key| image_size(&render(&mut face, key))).collect::<Vec<_>>();
+    let mut cache = GlyphCache::new(sizes.iter().sum());
+
+    for &key in &[a, b, c] {
+        cache.get_or_render(&mut face, key).unwrap();
+    }
+    assert_eq!(3, cache.len());
+    assert_eq!(cache.memory_budget(), cache.memory_used());
+
+    // Using "a" makes "b" the least recently used glyph, and then "c".
+    cache.get(&face, a).unwrap();
+    cache.set_memory_budget(sizes[0] + sizes[2]);
+    assert!(cache.get(&face, b).is_none());
+    cache.set_memory_budget(sizes[0]);
+    assert!(cache.get(&face, c).is_none());
+    assert!(cache.get(&face, a).is_some());
+    assert_eq!(stats(2, 5, 2), cache.stats());
+    assert_eq!(sizes[0], cache.memory_used());
+
+    // A glyph larger than the whole budget stays until the next one replaces it.
+    cache.set_memory_budget(1);
+    assert!(cache.is_empty());
+    cache.get_or_render(&mut face, b).unwrap();
+    assert_eq!((1, sizes[1]), (cache.len(), cache.memory_used()));
+    cache.get_or_render(&mut face, c).unwrap();
+    assert_eq!((1, sizes[2]), (cache.len(), cache.memory_used()));
+    assert!(cache.get(&face, b).is_none());
+}
+
+#[test]
+fn glyph_per_face() {
+    let lib = FTLib::new();
+    let mut first = Face::new(FONT, 0, &lib).unwrap();
+    let mut second = Face::new(FONT, 0, &lib).unwrap();
+    let mut cache = GlyphCache::new(1 << 20);
+    let key = glyph_key(&first, 'a');
+
+    cache.get_or_render(&mut first, key).unwrap();
+    cache.get_or_render(&mut second, key).unwrap();
+    assert_eq!(stats(0, 2, 0), cache.stats());
+    assert_eq!(2, cache.len());
+
+    cache.invalidate_face(&first);
+    assert!(cache.get(&first, key).is_none());
+    assert!(cache.get(&second, key).is_some());
+    assert_eq!(image_size(&render(&mut second, key)), cache.memory_used());
+}
+
+#[test]
+fn glyph_zero_budget() {
+    let lib = FTLib::new();
+    let mut face = Face::new(FONT, 0, &lib).unwrap();
+    let mut cache = GlyphCache::new(0);
+    let key = glyph_key(&face, 'a');
+    let expected = render(&mut face, key);
+
+    assert_eq!(&expected, cache.get_or_render(&mut face, key).unwrap());
+    assert_eq!(&expected, cache.get_or_render(&mut face, key).unwrap());
+    cache.insert(&face, key, expected);
+    assert!(cache.is_empty());
+    assert_eq!(0, cache.memory_used());
+    assert_eq!(stats(0, 2, 0), cache.stats());
+}
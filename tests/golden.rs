@@ -0,0 +1,218 @@
+//! Golden-image tests, which render text with the bundled DejaVu Sans and compare the results
+//! against the reference images in `tests/golden/`.
+//!
+//! A missing reference is a failure. To create references for new cases, or to update them after
+//! an intentional rendering change, run the tests with `GLYPHYDOG_BLESS=1` and check in the
+//! results. When a render doesn't match its reference, the render and an image highlighting the
+//! differing pixels are written to `target/golden-diff/`.
+
+extern crate glyphydog;
+extern crate cgmath_geometry;
+use cgmath_geometry::cgmath;
+extern crate png;
+
+use glyphydog::{FTLib, Face, Shaper, FaceSize, DPI, RenderMode, LoadFlags, LcdFilter, RenderTarget, ImageFormat, Rgba, BlendOptions, ShapedGlyph, render_text_to_buffer};
+use std::env;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use png::HasParameters;
+
+use cgmath::Point2;
+use cgmath_geometry::rect::DimsBox;
+
+/// The largest difference allowed between a channel of a rendered pixel and the reference, to
+/// absorb rounding differences between FreeType builds.
+const TOLERANCE: u8 = 8;
+const PADDING: i32 = 4;
+
+struct Case {
+    name: &'static str,
+    text: &'static str,
+    points: u32,
+    dpi: u32,
+    render_mode: RenderMode
+}
+
+const CASES: &[Case] = &[
+    Case{ name: "latin_12pt_96dpi_normal", text: "The quick brown fox jumps over the lazy dog", points: 12, dpi: 96, render_mode: RenderMode::Normal },
+    Case{ name: "latin_12pt_96dpi_light", text: "The quick brown fox jumps over the lazy dog", points: 12, dpi: 96, render_mode: RenderMode::Light },
+    Case{ name: "latin_12pt_96dpi_mono", text: "The quick brown fox jumps over the lazy dog", points: 12, dpi: 96, render_mode: RenderMode::Mono },
+    Case{ name: "latin_12pt_96dpi_lcd", text: "The quick brown fox jumps over the lazy dog", points: 12, dpi: 96, render_mode: RenderMode::Lcd },
+    Case{ name: "latin_12pt_96dpi_lcdv", text: "The quick brown fox jumps over the lazy dog", points: 12, dpi: 96, render_mode: RenderMode::LcdV },
+    Case{ name: "latin_8pt_72dpi_normal", text: "Hello World! 0123456789", points: 8, dpi: 72, render_mode: RenderMode::Normal },
+    Case{ name: "latin_16pt_144dpi_normal", text: "Hello World! 0123456789", points: 16, dpi: 144, render_mode: RenderMode::Normal },
+    Case{ name: "latin_48pt_72dpi_normal", text: "Wavy AVA", points: 48, dpi: 72, render_mode: RenderMode::Normal },
+    Case{ name: "ligatures_24pt_96dpi_normal", text: "office affluent", points: 24, dpi: 96, render_mode: RenderMode::Normal },
+    Case{ name: "greek_16pt_96dpi_normal", text: "Γειά σου Κόσμε!", points: 16, dpi: 96, render_mode: RenderMode::Normal },
+    Case{ name: "greek_16pt_96dpi_lcd", text: "Γειά σου Κόσμε!", points: 16, dpi: 96, render_mode: RenderMode::Lcd },
+    Case{ name: "combining_20pt_96dpi_normal", text: "e\u{301} a\u{30a} n\u{303}", points: 20, dpi: 96, render_mode: RenderMode::Normal },
+];
+
+struct Rendered {
+    width: u32,
+    height: u32,
+    data: Vec<u8>
+}
+
+#[test]
+fn golden_images() {
+    let mut lib = FTLib::new();
+    lib.set_lcd_filter(LcdFilter::Default).unwrap();
+    let mut face = Face::new(&include_bytes!("../DejaVuSans.ttf")[..], 0, &lib).unwrap();
+    let mut shaper = Shaper::new();
+
+    let bless = env::var_os("GLYPHYDOG_BLESS").map(|v| v != "0").unwrap_or(false);
+    let golden_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden");
+    let diff_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("target").join("golden-diff");
+    if bless {
+        fs::create_dir_all(&golden_dir).unwrap();
+    }
+
+    let mut failures = Vec::new();
+    for case in CASES {
+        let rendered = render(case, &mut face, &mut shaper);
+        let reference_path = golden_dir.join(format!("{}.png", case.name));
+
+        if bless {
+            write_png(&reference_path, &rendered);
+            continue;
+        }
+        if !reference_path.exists() {
+            failures.push(format!("{}: missing reference image, run with GLYPHYDOG_BLESS=1 to create it", case.name));
+            continue;
+        }
+
+        let reference = read_png(&reference_path);
+        if let Some(failure) = compare(&rendered, &reference) {
+            fs::create_dir_all(&diff_dir).unwrap();
+            write_png(&diff_path(&diff_dir, case.name, "actual"), &rendered);
+            if let Some(diff) = diff_image(&rendered, &reference) {
+                write_png(&diff_path(&diff_dir, case.name, "diff"), &diff);
+            }
+            failures.push(format!("{}: {}", case.name, failure));
+        }
+    }
+
+    if !failures.is_empty() {
+        panic!(
+            "{} golden image(s) failed, see {} for details:\n{}",
+            failures.len(),
+            diff_dir.display(),
+            failures.join("\n")
+        );
+    }
+}
+
+/// Render black text on a white background, sized to fit the text.
+fn render<B: ?Sized>(case: &Case, face: &mut Face<B>, shaper: &mut Shaper) -> Rendered {
+    let size = FaceSize::new(case.points * 64, case.points * 64);
+    let dpi = DPI::new(case.dpi, case.dpi);
+
+    let metrics = face.metrics_sized(size, dpi).unwrap();
+    let glyphs = shaper.shape_text(case.text, face, size, dpi).unwrap().collect::<Vec<ShapedGlyph>>();
+
+    let text_width = glyphs.iter().map(|g| g.advance.x).sum::<i32>();
+    let ascender = (metrics.ascender + 63) / 64;
+    let descender = (metrics.descender - 63) / 64;
+    let width = (text_width + PADDING * 2) as u32;
+    let height = (ascender - descender + PADDING * 2) as u32;
+
+    let mut data = vec![255; width as usize * height as usize * 4];
+    {
+        let mut target = RenderTarget::new(&mut data, DimsBox::new2(width, height), ImageFormat::Rgba8);
+        render_text_to_buffer(
+            glyphs, face, size, dpi, LoadFlags::empty(), case.render_mode,
+            &mut target, Point2::new(PADDING, PADDING + ascender), Rgba::new(0, 0, 0, 255), BlendOptions::NAIVE
+        ).unwrap();
+    }
+
+    Rendered{ width, height, data }
+}
+
+/// Describe how `rendered` differs from `reference`, or return `None` if they match within
+/// `TOLERANCE`.
+fn compare(rendered: &Rendered, reference: &Rendered) -> Option<String> {
+    if (rendered.width, rendered.height) != (reference.width, reference.height) {
+        return Some(format!(
+            "size changed from {}x{} to {}x{}",
+            reference.width, reference.height, rendered.width, rendered.height
+        ));
+    }
+
+    let mut differing = 0;
+    let mut max_difference = 0;
+    for (a, b) in rendered.data.chunks(4).zip(reference.data.chunks(4)) {
+        let difference = pixel_difference(a, b);
+        if difference > TOLERANCE {
+            differing += 1;
+        }
+        max_difference = max_difference.max(difference);
+    }
+
+    match differing {
+        0 => None,
+        _ => Some(format!("{} pixel(s) differ, by up to {}", differing, max_difference))
+    }
+}
+
+/// Build an image showing the reference faded out, with pixels that differ by more than
+/// `TOLERANCE` drawn in red.
+fn diff_image(rendered: &Rendered, reference: &Rendered) -> Option<Rendered> {
+    if (rendered.width, rendered.height) != (reference.width, reference.height) {
+        return None;
+    }
+
+    let mut data = Vec::with_capacity(reference.data.len());
+    for (a, b) in rendered.data.chunks(4).zip(reference.data.chunks(4)) {
+        match pixel_difference(a, b) {
+            d if d > TOLERANCE => data.extend_from_slice(&[255, 0, 0, 255]),
+            _ => {
+                let faded = 192 + b[1] / 4;
+                data.extend_from_slice(&[faded, faded, faded, 255]);
+            }
+        }
+    }
+
+    Some(Rendered {
+        width: reference.width,
+        height: reference.height,
+        data
+    })
+}
+
+fn pixel_difference(a: &[u8], b: &[u8]) -> u8 {
+    a.iter().zip(b).map(|(&a, &b)| (a as i16 - b as i16).abs() as u8).max().unwrap_or(0)
+}
+
+fn diff_path(diff_dir: &Path, name: &str, kind: &str) -> PathBuf {
+    diff_dir.join(format!("{}.{}.png", name, kind))
+}
+
+fn read_png(path: &Path) -> Rendered {
+    let decoder = png::Decoder::new(File::open(path).unwrap());
+    let (info, mut reader) = decoder.read_info().unwrap();
+    assert_eq!(
+        (info.color_type, info.bit_depth),
+        (png::ColorType::RGBA, png::BitDepth::Eight),
+        "reference image {} isn't 8-bit RGBA", path.display()
+    );
+
+    let mut data = vec![0; info.buffer_size()];
+    reader.next_frame(&mut data).unwrap();
+    Rendered {
+        width: info.width,
+        height: info.height,
+        data
+    }
+}
+
+fn write_png(path: &Path, image: &Rendered) {
+    let file = File::create(path).unwrap();
+    let ref mut w = BufWriter::new(file);
+
+    let mut encoder = png::Encoder::new(w, image.width, image.height);
+    encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().unwrap();
+    writer.write_image_data(&image.data).unwrap();
+}
@@ -0,0 +1,61 @@
+extern crate glyphydog;
+extern crate cgmath_geometry;
+
+use glyphydog::{FTLib, Face, FaceSize, DPI, LoadFlags, RenderMode, OwnedBitmap};
+
+use cgmath_geometry::rect::GeoBox;
+
+const FONT: &[u8] = include_bytes!("../DejaVuSans.ttf");
+
+fn render_bitmap<B: ?Sized>(face: &mut Face<B>, c: char) -> OwnedBitmap {
+    let glyph_index = face.char_index(c);
+    let mut slot = face.load_glyph(glyph_index, FaceSize::from_pixels(32), DPI::new(72, 72), LoadFlags::empty(), RenderMode::Normal).unwrap();
+    slot.render_glyph(RenderMode::Normal).unwrap().into_owned()
+}
+
+#[test]
+fn owned_bitmap_outlives_slot() {
+    let lib = FTLib::new();
+    let mut face = Face::new(FONT, 0, &lib).unwrap();
+
+    let a = render_bitmap(&mut face, 'a');
+    let image = {
+        let glyph_index = face.char_index('a');
+        face.load_glyph(glyph_index, FaceSize::from_pixels(32), DPI::new(72, 72), LoadFlags::empty(), RenderMode::Normal).unwrap()
+            .render_image(RenderMode::Normal).unwrap()
+    };
+    // Loading another glyph overwrites the glyph slot, but not the copies.
+    let w = render_bitmap(&mut face, 'W');
+
+    assert_ne!(a, w);
+    assert!(a.buffer.iter().any(|&c| c != 0));
+    assert_eq!(a, image.bitmap);
+    assert_eq!(a, render_bitmap(&mut face, 'a'));
+}
+
+#[test]
+fn glyph_image_scaled() {
+    let lib = FTLib::new();
+    let mut face = Face::new(FONT, 0, &lib).unwrap();
+    let glyph_index = face.char_index('g');
+    let image = face.load_glyph(glyph_index, FaceSize::from_pixels(32), DPI::new(72, 72), LoadFlags::empty(), RenderMode::Normal).unwrap()
+        .render_image(RenderMode::Normal).unwrap();
+
+    let total = |bitmap: &OwnedBitmap| bitmap.buffer.iter().map(|&c| c as f32).sum::<f32>();
+    for &scale in &[0.5, 1.5] {
+        let scaled = image.scaled(scale);
+        let round = |value: i32| (value as f32 * scale).round() as i32;
+        let (width, height) = (image.bitmap.dims.width(), image.bitmap.dims.height());
+        let (scaled_width, scaled_height) = (scaled.bitmap.dims.width(), scaled.bitmap.dims.height());
+
+        assert_eq!((round(width as i32) as u32, round(height as i32) as u32), (scaled_width, scaled_height));
+        assert_eq!((round(image.advance.x), round(image.advance.y)), (scaled.advance.x, scaled.advance.y));
+        assert_eq!((round(image.bitmap_offset.x), round(image.bitmap_offset.y)), (scaled.bitmap_offset.x, scaled.bitmap_offset.y));
+        assert_eq!(round(image.metrics.hori_advance), scaled.metrics.hori_advance);
+
+        // The scaled bitmap keeps the glyph's average coverage, give or take rounding.
+        let area = (scaled_width * scaled_height) as f32;
+        let expected = total(&image.bitmap) * area / (width * height) as f32;
+        assert!((total(&scaled.bitmap) - expected).abs() <= area * 0.5);
+    }
+}
@@ -0,0 +1,67 @@
+extern crate glyphydog;
+
+use glyphydog::{FTLib, Face, LayoutTable, Tag};
+
+const FONT: &[u8] = include_bytes!("../DejaVuSans.ttf");
+
+#[test]
+fn names() {
+    let lib = FTLib::new();
+    let face = Face::new(FONT, 0, &lib).unwrap();
+
+    assert_eq!(Some("DejaVu Sans".to_owned()), face.family_name());
+    assert_eq!(Some("Book".to_owned()), face.style_name());
+    assert_eq!(Some("DejaVuSans".to_owned()), face.postscript_name());
+    assert_eq!((1, 0), (face.num_faces(), face.face_index()));
+}
+
+#[test]
+fn coverage_includes_ascii() {
+    let lib = FTLib::new();
+    let face = Face::new(FONT, 0, &lib).unwrap();
+    let coverage = face.coverage();
+
+    for c in 0x20..0x7F {
+        assert!(coverage.iter().any(|range| range.contains(&c)), "{:?} isn't covered", c as u8 as char);
+    }
+    // The ranges are sorted, and merged wherever they touch.
+    for pair in coverage.windows(2) {
+        assert!(pair[0].end() + 1 < *pair[1].start(), "{:?} and {:?} aren't separate", pair[0], pair[1]);
+    }
+}
+
+#[test]
+fn outline_font_without_strikes() {
+    let lib = FTLib::new();
+    let face = Face::new(FONT, 0, &lib).unwrap();
+
+    assert!(face.strikes().is_empty());
+    assert!(face.variation_axes().is_empty());
+}
+
+#[test]
+fn latin_kerning_in_gpos() {
+    let lib = FTLib::new();
+    let face = Face::new(FONT, 0, &lib).unwrap();
+    let (latn, kern) = (Tag::new(b"latn"), Tag::new(b"kern"));
+
+    assert!(face.layout_scripts(LayoutTable::Gpos).contains(&latn));
+    assert!(face.layout_features(LayoutTable::Gpos).contains(&kern));
+
+    let systems = face.layout_systems(LayoutTable::Gpos);
+    let latin = systems.iter().find(|script| script.tag == latn).expect("no latn script in GPOS");
+    // The default language system comes first, followed by language-specific ones like Romanian.
+    assert_eq!(None, latin.languages[0].tag);
+    assert!(latin.languages.iter().any(|language| language.tag == Some(Tag::new(b"ROM "))));
+    for language in &latin.languages {
+        assert!(language.features.contains(&kern), "{:?} doesn't use kern", language.tag);
+    }
+}
+
+#[cfg(feature = "feature-names")]
+#[test]
+fn unnamed_feature() {
+    let lib = FTLib::new();
+    let face = Face::new(FONT, 0, &lib).unwrap();
+    assert_eq!(None, face.feature_names(LayoutTable::Gpos, Tag::new(b"kern")));
+}
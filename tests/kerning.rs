@@ -0,0 +1,52 @@
+extern crate glyphydog;
+
+use glyphydog::{FTLib, Face, Shaper, FaceSize, DPI, KerningSource, LoadFlags};
+
+#[test]
+fn kerning_pairs_match_shaping() {
+    let lib = FTLib::new();
+    let mut face = Face::new(&include_bytes!("../DejaVuSans.ttf")[..], 0, &lib).unwrap();
+    let mut shaper = Shaper::new();
+
+    let (a, v) = (face.char_index('A'), face.char_index('V'));
+    let pair = face.kerning_pairs()
+        .find(|pair| pair.left == a && pair.right == v && pair.source == KerningSource::Gpos)
+        .expect("no GPOS kerning for AV");
+
+    // At one pixel per font unit, the shaped advances are in font units.
+    let units_per_em = face.metrics_font_units().units_per_em as u32;
+    let size = FaceSize::from_pixels(units_per_em);
+    let dpi = DPI::new(72, 72);
+    let unkerned = shaper.shape_text("A", &mut face, size, dpi).unwrap().next().unwrap().advance.x;
+    let kerned = shaper.shape_text("AV", &mut face, size, dpi).unwrap().next().unwrap().advance.x;
+
+    assert_ne!(0, pair.value);
+    assert_eq!(pair.value, kerned - unkerned);
+}
+
+#[test]
+fn shaped_kerning_scales_with_size() {
+    let lib = FTLib::new();
+    let mut face = Face::new(&include_bytes!("../DejaVuSans.ttf")[..], 0, &lib).unwrap();
+    let mut shaper = Shaper::new();
+
+    let (a, v) = (face.char_index('A'), face.char_index('V'));
+    let pair = face.kerning_pairs()
+        .find(|pair| pair.left == a && pair.right == v && pair.source == KerningSource::Gpos)
+        .expect("no GPOS kerning for AV");
+    let units_per_em = face.metrics_font_units().units_per_em as i64;
+    let dpi = DPI::new(72, 72);
+
+    for &pixels in &[64, 200] {
+        let size = FaceSize::from_pixels(pixels);
+        // The font functions give Harfbuzz unhinted 26.6 advances.
+        let advance = face.glyph_advance(a, size, dpi, LoadFlags::NO_HINTING).unwrap() as i64;
+        let kerning = pair.value as i64 * pixels as i64 * 64 / units_per_em;
+        let expected = (advance + kerning) / 64;
+
+        let glyphs = shaper.shape_text("AV", &mut face, size, dpi).unwrap().collect::<Vec<_>>();
+        assert_eq!((a, v), (glyphs[0].glyph_index, glyphs[1].glyph_index));
+        let shaped = glyphs[1].pos.x as i64;
+        assert!((shaped - expected).abs() <= 1, "V is at {} at {}px, expected {}", shaped, pixels, expected);
+    }
+}
@@ -0,0 +1,132 @@
+extern crate glyphydog;
+
+use glyphydog::{FTLib, Face, FaceSize, DPI, LoadFlags, RenderMode, MemoryConfig, MemoryUsage, Error, Operation};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const FONT: &[u8] = include_bytes!("../DejaVuSans.ttf");
+
+/// Counts the bytes allocated through it. Every test gets its own allocator, since tests run in
+/// parallel.
+struct CountingAlloc {
+    live: AtomicUsize,
+    allocations: AtomicUsize
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.live.fetch_add(layout.size(), Ordering::SeqCst);
+        self.allocations.fetch_add(1, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.live.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+macro_rules! counting_alloc {
+    () => {{
+        static ALLOC: CountingAlloc = CountingAlloc {
+            live: AtomicUsize::new(0),
+            allocations: AtomicUsize::new(0)
+        };
+        &ALLOC
+    }};
+}
+
+fn load_glyphs<B: ?Sized>(face: &mut Face<B>, text: &str) {
+    for c in text.chars() {
+        let glyph_index = face.char_index(c);
+        face.load_glyph(glyph_index, FaceSize::from_pixels(48), DPI::new(72, 72), LoadFlags::empty(), RenderMode::Normal).unwrap()
+            .render_glyph(RenderMode::Normal).unwrap();
+    }
+}
+
+#[test]
+fn face_over_limit() {
+    let lib = FTLib::new();
+    let usage = lib.memory_usage();
+    lib.set_memory_limit(Some(usage.live + 16));
+    assert_eq!(Some(usage.live + 16), lib.memory_limit());
+
+    let error = Face::new(FONT, 0, &lib).err().unwrap();
+    assert_eq!(Operation::OpenFace, error.operation);
+    assert_eq!(Error::OutOfMemory, error.error);
+    // Everything allocated for the failed face was freed again.
+    assert_eq!(usage.live, lib.memory_usage().live);
+
+    lib.set_memory_limit(None);
+    Face::new(FONT, 0, &lib).unwrap();
+}
+
+#[test]
+fn library_over_limit() {
+    let alloc = counting_alloc!();
+    let config = MemoryConfig {
+        limit: Some(16),
+        allocator: Some(alloc)
+    };
+    assert_eq!(Error::OutOfMemory, FTLib::try_with_memory(config).unwrap_err());
+    assert_eq!(0, alloc.live.load(Ordering::SeqCst));
+}
+
+#[test]
+fn usage_after_dropping_faces() {
+    let lib = FTLib::new();
+    let before = lib.memory_usage().live;
+
+    let mut faces = (0..3).map(|_| Face::new(FONT, 0, &lib).unwrap()).collect::<Vec<_>>();
+    for face in &mut faces {
+        load_glyphs(face, "Memory");
+    }
+    let loaded = lib.memory_usage();
+    assert!(loaded.live > before);
+
+    drop(faces);
+    assert_eq!(MemoryUsage{ live: before, peak: loaded.peak }, lib.memory_usage());
+
+    lib.reset_peak_memory();
+    assert_eq!(MemoryUsage{ live: before, peak: before }, lib.memory_usage());
+}
+
+#[test]
+fn peak_is_monotonic() {
+    let lib = FTLib::new();
+    let mut face = Face::new(FONT, 0, &lib).unwrap();
+
+    let mut last = lib.memory_usage();
+    for c in "the peak never falls".chars() {
+        load_glyphs(&mut face, &c.to_string());
+        let usage = lib.memory_usage();
+        assert!(usage.peak >= last.peak, "peak fell from {} to {}", last.peak, usage.peak);
+        assert!(usage.peak >= usage.live);
+        last = usage;
+    }
+
+    drop(face);
+    let usage = lib.memory_usage();
+    assert!(usage.live < last.live);
+    assert_eq!(last.peak, usage.peak);
+}
+
+#[test]
+fn library_drop_frees_everything() {
+    let alloc = counting_alloc!();
+    let lib = FTLib::try_with_memory(MemoryConfig{ limit: None, allocator: Some(alloc) }).unwrap();
+    let mut face = Face::new(FONT, 0, &lib).unwrap();
+    load_glyphs(&mut face, "Dropped");
+
+    let usage = lib.memory_usage();
+    assert!(alloc.allocations.load(Ordering::SeqCst) > 0);
+    assert!(usage.live > 0);
+    // The allocator also sees the header in front of every allocation.
+    assert!(alloc.live.load(Ordering::SeqCst) >= usage.live);
+
+    // The face keeps the library alive, so FreeType's memory only goes away with both of them.
+    drop(lib);
+    assert!(alloc.live.load(Ordering::SeqCst) > 0);
+    drop(face);
+    assert_eq!(0, alloc.live.load(Ordering::SeqCst));
+}
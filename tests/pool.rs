@@ -0,0 +1,84 @@
+extern crate glyphydog;
+
+use glyphydog::{FTLib, Face, FacePool, Shaper, FaceSize, DPI, ShapedGlyph};
+use std::sync::{Arc, Barrier};
+use std::thread;
+
+const FONT: &[u8] = include_bytes!("../DejaVuSans.ttf");
+const TEXT: &str = "Pooled faces";
+
+fn shape<B: ?Sized>(face: &mut Face<B>) -> Vec<ShapedGlyph> {
+    Shaper::new().shape_text(TEXT, face, FaceSize::from_pixels(20), DPI::new(72, 72)).unwrap().collect()
+}
+
+#[test]
+fn distinct_faces_across_threads() {
+    const THREADS: usize = 4;
+
+    let lib = FTLib::new();
+    let mut face = Face::new(FONT, 0, &lib).unwrap();
+    let expected = shape(&mut face);
+    let prototype_id = face.id();
+    let pool = Arc::new(FacePool::new(face));
+    let barrier = Arc::new(Barrier::new(THREADS));
+
+    let threads = (0..THREADS).map(|_| {
+        let (pool, barrier) = (pool.clone(), barrier.clone());
+        thread::spawn(move || {
+            let mut face = pool.get().unwrap();
+            // Every thread holds its face until all of them have one.
+            barrier.wait();
+            let glyphs = shape(&mut face);
+            (face.id(), glyphs)
+        })
+    }).collect::<Vec<_>>();
+
+    let mut ids = Vec::new();
+    for thread in threads {
+        let (id, glyphs) = thread.join().unwrap();
+        assert_eq!(expected, glyphs);
+        assert_ne!(prototype_id, id);
+        assert!(!ids.contains(&id), "two threads got the same face");
+        ids.push(id);
+    }
+    assert_eq!(THREADS, pool.idle_count());
+}
+
+#[test]
+fn faces_are_reused() {
+    let lib = FTLib::new();
+    let pool = FacePool::new(Face::new(FONT, 0, &lib).unwrap());
+    assert_eq!(0, pool.idle_count());
+
+    let first_id = {
+        let mut face = pool.get().unwrap();
+        shape(&mut face);
+        face.id()
+    };
+    assert_eq!(1, pool.idle_count());
+
+    {
+        let mut face = pool.get().unwrap();
+        assert_eq!(first_id, face.id());
+        assert_eq!(0, pool.idle_count());
+        // A second face checked out at the same time is a new one.
+        let second = pool.get().unwrap();
+        assert_ne!(first_id, second.id());
+        shape(&mut face);
+    }
+    assert_eq!(2, pool.idle_count());
+
+    pool.shrink();
+    assert_eq!(0, pool.idle_count());
+}
+
+#[test]
+fn detached_faces_stay_out() {
+    let lib = FTLib::new();
+    let pool = FacePool::new(Face::new(FONT, 0, &lib).unwrap());
+
+    let mut face = pool.get().unwrap().detach();
+    assert_eq!(0, pool.idle_count());
+    assert_eq!(shape(&mut face), shape(&mut pool.get().unwrap()));
+    assert_eq!(1, pool.idle_count());
+}
@@ -0,0 +1,38 @@
+extern crate glyphydog;
+extern crate cgmath_geometry;
+
+use glyphydog::{FTLib, Face, ShapedGlyph, FaceSize, DPI, LoadFlags, RenderMode, RenderTarget, ImageFormat, Rgba, BlendOptions, render_text_to_buffer};
+
+use cgmath_geometry::cgmath::{Point2, Vector2};
+use cgmath_geometry::rect::DimsBox;
+
+#[test]
+fn overflowing_positions_skipped() {
+    let lib = FTLib::new();
+    let mut face = Face::new(&include_bytes!("../DejaVuSans.ttf")[..], 0, &lib).unwrap();
+    let glyph_index = face.char_index('W');
+    let glyph = |x, y| ShapedGlyph {
+        glyph_index,
+        advance: Vector2::new(0, 0),
+        pos: Point2::new(x, y),
+        str_index: 0
+    };
+    let glyphs = vec![glyph(i32::MAX, 0), glyph(0, i32::MIN), glyph(i32::MIN, 0), glyph(0, 0)];
+
+    let mut data = vec![0; 32 * 32];
+    render_text_to_buffer(
+        glyphs, &mut face, FaceSize::from_pixels(16), DPI::new(72, 72), LoadFlags::empty(), RenderMode::Normal,
+        &mut RenderTarget::new(&mut data, DimsBox::new2(32, 32), ImageFormat::A8),
+        Point2::new(8, 24), Rgba::new(255, 255, 255, 255), BlendOptions::NAIVE
+    ).unwrap();
+
+    // Only the glyph at the origin got drawn.
+    let mut expected = vec![0; 32 * 32];
+    render_text_to_buffer(
+        vec![glyph(0, 0)], &mut face, FaceSize::from_pixels(16), DPI::new(72, 72), LoadFlags::empty(), RenderMode::Normal,
+        &mut RenderTarget::new(&mut expected, DimsBox::new2(32, 32), ImageFormat::A8),
+        Point2::new(8, 24), Rgba::new(255, 255, 255, 255), BlendOptions::NAIVE
+    ).unwrap();
+    assert!(expected.iter().any(|&c| c != 0));
+    assert_eq!(expected, data);
+}
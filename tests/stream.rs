@@ -0,0 +1,115 @@
+extern crate glyphydog;
+extern crate cgmath_geometry;
+use cgmath_geometry::cgmath;
+
+use glyphydog::{FTLib, Face, Shaper, FaceSize, DPI, LoadFlags, RenderMode, RenderTarget, ImageFormat, Rgba, BlendOptions, Error, Operation, render_text_to_buffer};
+use std::cell::Cell;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::rc::Rc;
+
+use cgmath::Point2;
+use cgmath_geometry::rect::DimsBox;
+
+const FONT: &[u8] = include_bytes!("../DejaVuSans.ttf");
+
+/// Reads the font until `failing` gets set, and then returns errors or panics on every call.
+struct FailingReader {
+    inner: Cursor<&'static [u8]>,
+    failing: Rc<Cell<bool>>,
+    panics: bool
+}
+
+impl FailingReader {
+    fn check(&self) -> io::Result<()> {
+        match (self.failing.get(), self.panics) {
+            (false, _) => Ok(()),
+            (true, false) => Err(io::Error::new(io::ErrorKind::Other, "reader failed")),
+            (true, true) => panic!("reader panicked")
+        }
+    }
+}
+
+impl Read for FailingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.check()?;
+        self.inner.read(buf)
+    }
+}
+
+impl Seek for FailingReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.check()?;
+        self.inner.seek(pos)
+    }
+}
+
+fn render<B: ?Sized>(text: &str, face: &mut Face<B>) -> Vec<u8> {
+    let (size, dpi) = (FaceSize::from_pixels(24), DPI::new(72, 72));
+    let glyphs = Shaper::new().shape_text(text, face, size, dpi).unwrap().collect::<Vec<_>>();
+
+    let (width, height) = (200, 32);
+    let mut data = vec![0; width * height];
+    {
+        let mut target = RenderTarget::new(&mut data, DimsBox::new2(width as u32, height as u32), ImageFormat::A8);
+        render_text_to_buffer(
+            glyphs, face, size, dpi, LoadFlags::empty(), RenderMode::Normal,
+            &mut target, Point2::new(4, 24), Rgba::new(255, 255, 255, 255), BlendOptions::NAIVE
+        ).unwrap();
+    }
+    data
+}
+
+#[test]
+fn matches_memory_face() {
+    let lib = FTLib::new();
+    let mut memory_face = Face::new(FONT, 0, &lib).unwrap();
+    let mut reader_face = Face::new_reader(Cursor::new(FONT), 0, &lib).unwrap();
+
+    assert_eq!(memory_face.char_index('g'), reader_face.char_index('g'));
+    let rendered = render("Reading glyphs", &mut reader_face);
+    assert!(rendered.iter().any(|&c| c != 0));
+    assert_eq!(render("Reading glyphs", &mut memory_face), rendered);
+}
+
+fn fail_after_open(panics: bool) {
+    let lib = FTLib::new();
+    let failing = Rc::new(Cell::new(false));
+    let reader = FailingReader{ inner: Cursor::new(FONT), failing: failing.clone(), panics };
+    let mut face = Face::new_reader(reader, 0, &lib).unwrap();
+
+    let (size, dpi) = (FaceSize::from_pixels(16), DPI::new(72, 72));
+    let (a, b) = (face.char_index('a'), face.char_index('b'));
+    assert!(face.load_glyph(a, size, dpi, LoadFlags::empty(), RenderMode::Normal).is_ok());
+
+    failing.set(true);
+    match face.load_glyph(b, size, dpi, LoadFlags::empty(), RenderMode::Normal) {
+        Ok(_) => panic!("loaded a glyph from a failing reader"),
+        Err(error) => {
+            assert_eq!(Operation::LoadGlyph, error.operation);
+            assert_eq!(Some(b), error.glyph_index);
+            assert_eq!(Error::InvalidStreamOperation, error.error);
+        }
+    }
+
+    // The face keeps working once the reader does.
+    failing.set(false);
+    assert!(face.load_glyph(b, size, dpi, LoadFlags::empty(), RenderMode::Normal).is_ok());
+}
+
+#[test]
+fn reader_error_after_open() {
+    fail_after_open(false);
+}
+
+#[test]
+fn reader_panic_after_open() {
+    fail_after_open(true);
+}
+
+#[test]
+fn bad_face_index() {
+    let lib = FTLib::new();
+    let error = Face::new_reader(Cursor::new(FONT), 1, &lib).err().unwrap();
+    assert_eq!(Operation::OpenFace, error.operation);
+    assert_eq!(Error::InvalidArgument, error.error);
+}